@@ -16,6 +16,6 @@ fn main() -> Result<(), exitfailure::ExitFailure> {
     println!("Listening on http://127.0.0.1:4000/");
     server.bind("127.0.0.1:4000")?;
 
-    server.run_forever();
+    server.run_forever()?;
     Ok(())
 }
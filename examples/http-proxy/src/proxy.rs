@@ -5,7 +5,7 @@ use {
     reqwest::IntoUrl,
     std::{mem, net::SocketAddr},
     tsukuyomi::{
-        app::REMOTE_ADDR,
+        app::{RemoteAddr, REMOTE_ADDR},
         chain,
         extractor::{self, ExtractorExt}, //
         future::TryFuture,
@@ -122,9 +122,9 @@ pub fn proxy_client(
         extractor::header::headers(),
         extractor::value(client),
     ]
-    .map(|remote_addr, headers, client| Client {
+    .map(|remote_addr: Option<RemoteAddr>, headers, client| Client {
         client,
         headers,
-        remote_addr,
+        remote_addr: remote_addr.and_then(|addr| addr.as_tcp().cloned()),
     })
 }
@@ -35,7 +35,7 @@ fn main() -> Result<(), ExitFailure> {
 
     let mut server = Server::new(app)?;
     server.bind("127.0.0.1:4000")?;
-    server.run_forever();
+    server.run_forever()?;
 
     Ok(())
 }
@@ -0,0 +1,639 @@
+//! Response compression support for Tsukuyomi.
+
+#![doc(html_root_url = "https://docs.rs/tsukuyomi-compress/0.1.0-dev")]
+#![deny(
+    missing_docs,
+    missing_debug_implementations,
+    nonstandard_style,
+    rust_2018_idioms,
+    rust_2018_compatibility,
+    unused
+)]
+#![forbid(clippy::unimplemented)]
+
+use {
+    brotli::enc::{
+        encode::{
+            BrotliEncoderCompressStream, BrotliEncoderCreateInstance, BrotliEncoderDestroyInstance,
+            BrotliEncoderHasMoreOutput, BrotliEncoderIsFinished, BrotliEncoderOperation,
+            BrotliEncoderParameter, BrotliEncoderSetParameter, BrotliEncoderStateStruct,
+        },
+        StandardAlloc,
+    },
+    bytes::{Buf, Bytes},
+    flate2::{
+        write::{DeflateEncoder, GzEncoder},
+        Compression as Level,
+    },
+    futures::{Async, Poll, Stream},
+    http::{
+        header::{
+            HeaderMap, //
+            HeaderValue,
+            CONTENT_ENCODING,
+            CONTENT_LENGTH,
+            CONTENT_RANGE,
+            CONTENT_TYPE,
+            ETAG,
+            VARY,
+        },
+        Method, Request, StatusCode,
+    },
+    izanami::http::body::HttpBody,
+    mime::Mime,
+    std::{cmp, fmt, io, mem, sync::Arc},
+    tsukuyomi::{
+        input::header::AcceptEncoding,
+        output::{Response, ResponseBody},
+    },
+};
+
+/// A builder of `Compression`.
+#[derive(Debug)]
+pub struct Builder {
+    encodings: Vec<Encoding>,
+    min_size: u64,
+    level: u32,
+    brotli_quality: u32,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            encodings: vec![Encoding::Brotli, Encoding::Gzip, Encoding::Deflate],
+            min_size: 1024,
+            level: 6,
+            brotli_quality: 4,
+        }
+    }
+}
+
+impl Builder {
+    /// Creates a `Builder` with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether to use `gzip` as a content coding.
+    ///
+    /// The default value is `true`.
+    pub fn gzip(self, enabled: bool) -> Self {
+        self.encoding(Encoding::Gzip, enabled)
+    }
+
+    /// Sets whether to use `deflate` as a content coding.
+    ///
+    /// The default value is `true`.
+    pub fn deflate(self, enabled: bool) -> Self {
+        self.encoding(Encoding::Deflate, enabled)
+    }
+
+    /// Sets whether to use `br` (Brotli) as a content coding.
+    ///
+    /// The default value is `true`.
+    pub fn brotli(self, enabled: bool) -> Self {
+        self.encoding(Encoding::Brotli, enabled)
+    }
+
+    fn encoding(mut self, encoding: Encoding, enabled: bool) -> Self {
+        self.encodings.retain(|&e| e != encoding);
+        if enabled {
+            self.encodings.push(encoding);
+            self.encodings.sort();
+        }
+        self
+    }
+
+    /// Sets the minimum size of response body to be compressed, in bytes.
+    ///
+    /// The responses whose size is unknown at the time of replying are always compressed.
+    /// The default value is `1024`.
+    pub fn min_size(self, min_size: u64) -> Self {
+        Self { min_size, ..self }
+    }
+
+    /// Sets the compression level used in `gzip` and `deflate`, between 0 and 9.
+    ///
+    /// The default value is `6`.
+    pub fn level(self, level: u32) -> Self {
+        Self {
+            level: cmp::min(level, 9),
+            ..self
+        }
+    }
+
+    /// Sets the quality used in Brotli, between 0 and 11.
+    ///
+    /// The default value is `4`, which is suitable for on-the-fly compression.
+    pub fn brotli_quality(self, quality: u32) -> Self {
+        Self {
+            brotli_quality: cmp::min(quality, 11),
+            ..self
+        }
+    }
+
+    /// Creates a `Compression` with the current configuration.
+    pub fn build(self) -> Compression {
+        Compression {
+            inner: Arc::new(Inner {
+                encodings: self.encodings,
+                min_size: self.min_size,
+                level: self.level,
+                brotli_quality: self.brotli_quality,
+            }),
+        }
+    }
+}
+
+/// A `ModifyHandler` that compresses the response bodies.
+///
+/// The content coding is negotiated with the value of `Accept-Encoding`.
+/// The responses are not compressed if:
+///
+/// * the response already has the header field `Content-Encoding`,
+/// * the response represents a partial content (i.e. has `Content-Range`),
+/// * the size of response body is less than the configured minimum size, or
+/// * the media type in `Content-Type` is already compressed (such as images or archives).
+#[derive(Debug, Clone)]
+pub struct Compression {
+    inner: Arc<Inner>,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compression {
+    /// Create a new `Compression` with the default configuration.
+    pub fn new() -> Self {
+        Self::builder().build()
+    }
+
+    /// Create a builder of this type.
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+}
+
+mod impl_modify_handler_for_compression {
+    use {
+        super::Compression,
+        tsukuyomi::{
+            future::{Async, Poll, TryFuture},
+            handler::{metadata::Metadata, Handler, ModifyHandler},
+            input::Input,
+            output::{IntoResponse, Response},
+        },
+    };
+
+    impl<H> ModifyHandler<H> for Compression
+    where
+        H: Handler,
+        H::Output: IntoResponse,
+    {
+        type Output = Response;
+        type Error = H::Error;
+        type Handler = CompressionHandler<H>;
+
+        fn modify(&self, handler: H) -> Self::Handler {
+            CompressionHandler {
+                handler,
+                compression: self.clone(),
+            }
+        }
+    }
+
+    #[allow(missing_docs)]
+    #[derive(Debug)]
+    pub struct CompressionHandler<H> {
+        handler: H,
+        compression: Compression,
+    }
+
+    impl<H> Handler for CompressionHandler<H>
+    where
+        H: Handler,
+        H::Output: IntoResponse,
+    {
+        type Output = Response;
+        type Error = H::Error;
+        type Handle = CompressionHandle<H::Handle>;
+
+        fn metadata(&self) -> Metadata {
+            self.handler.metadata()
+        }
+
+        #[inline]
+        fn handle(&self) -> Self::Handle {
+            CompressionHandle {
+                handle: self.handler.handle(),
+                compression: self.compression.clone(),
+            }
+        }
+    }
+
+    #[allow(missing_docs)]
+    #[derive(Debug)]
+    pub struct CompressionHandle<H> {
+        handle: H,
+        compression: Compression,
+    }
+
+    impl<H> TryFuture for CompressionHandle<H>
+    where
+        H: TryFuture,
+        H::Ok: IntoResponse,
+    {
+        type Ok = Response;
+        type Error = H::Error;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            let output = futures::try_ready!(self.handle.poll_ready(input));
+            let response = self
+                .compression
+                .inner
+                .compress(input.request, output.into_response());
+            Ok(Async::Ready(response))
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    encodings: Vec<Encoding>,
+    min_size: u64,
+    level: u32,
+    brotli_quality: u32,
+}
+
+impl Inner {
+    fn is_compressible(&self, request: &Request<()>, response: &Response) -> bool {
+        if request.method() == Method::HEAD {
+            return false;
+        }
+
+        let status = response.status();
+        if status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+        {
+            return false;
+        }
+
+        let headers = response.headers();
+        if headers.contains_key(CONTENT_ENCODING) || headers.contains_key(CONTENT_RANGE) {
+            return false;
+        }
+
+        let content_length = response.body().content_length().or_else(|| {
+            headers
+                .get(CONTENT_LENGTH)
+                .and_then(|h| h.to_str().ok())
+                .and_then(|s| s.parse().ok())
+        });
+        if content_length.map_or(false, |len| len < self.min_size) {
+            return false;
+        }
+
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|s| s.parse::<Mime>().ok());
+        if content_type.map_or(false, |mime| is_compressed_mime(&mime)) {
+            return false;
+        }
+
+        true
+    }
+
+    fn compress(&self, request: &Request<()>, mut response: Response) -> Response {
+        if !self.is_compressible(request, &response) {
+            return response;
+        }
+
+        append_vary(response.headers_mut());
+
+        let encoding = match negotiate(request.headers(), &self.encodings) {
+            Some(encoding) => encoding,
+            None => return response,
+        };
+
+        let headers = response.headers_mut();
+        headers.insert(
+            CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.as_str()),
+        );
+        headers.remove(CONTENT_LENGTH);
+
+        // The compressed representation is not byte-for-byte identical to the original one.
+        if let Some(etag) = headers.get_mut(ETAG) {
+            if !etag.as_bytes().starts_with(b"W/") {
+                let mut weak = b"W/".to_vec();
+                weak.extend_from_slice(etag.as_bytes());
+                if let Ok(weak) = HeaderValue::from_bytes(&weak) {
+                    *etag = weak;
+                }
+            }
+        }
+
+        let encoder = Encoder::new(encoding, self.level, self.brotli_quality);
+        response.map(|body| {
+            ResponseBody::wrap_stream(CompressStream {
+                body,
+                encoder: Some(encoder),
+            })
+        })
+    }
+}
+
+/// Returns `true` if the media type represents the data which is already compressed.
+fn is_compressed_mime(mime: &Mime) -> bool {
+    match (mime.type_().as_str(), mime.subtype().as_str()) {
+        ("image", "svg") => false,
+        ("image", _) | ("audio", _) | ("video", _) => true,
+        ("font", "woff") | ("font", "woff2") => true,
+        ("text", "event-stream") => true,
+        ("application", subtype) => match subtype {
+            "zip" | "gzip" | "x-gzip" | "x-bzip2" | "x-xz" | "x-7z-compressed"
+            | "x-rar-compressed" | "zstd" | "octet-stream" => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn append_vary(headers: &mut HeaderMap) {
+    let has_vary = headers.get_all(VARY).iter().any(|h| {
+        h.to_str().ok().map_or(false, |s| {
+            s.split(',')
+                .map(str::trim)
+                .any(|s| s == "*" || s.eq_ignore_ascii_case("accept-encoding"))
+        })
+    });
+    if !has_vary {
+        headers.append(VARY, HeaderValue::from_static("accept-encoding"));
+    }
+}
+
+/// Selects the content coding to be used from the value of `Accept-Encoding`.
+///
+/// The order of `encodings` is used as the server's preference among the same quality values.
+fn negotiate(headers: &HeaderMap, encodings: &[Encoding]) -> Option<Encoding> {
+    let available: Vec<&str> = encodings.iter().map(|e| e.as_str()).collect();
+    let name = AcceptEncoding::from_headers(headers)
+        .negotiate(&available)
+        .into_iter()
+        .next()?;
+    encodings.iter().cloned().find(|e| e.as_str() == name)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Encoding {
+    Brotli,
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+enum Encoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(DeflateEncoder<Vec<u8>>),
+    Brotli(Box<BrotliEncoder>),
+}
+
+impl fmt::Debug for Encoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Encoder::Gzip(..) => "Gzip",
+            Encoder::Deflate(..) => "Deflate",
+            Encoder::Brotli(..) => "Brotli",
+        };
+        f.debug_tuple(name).finish()
+    }
+}
+
+impl Encoder {
+    fn new(encoding: Encoding, level: u32, brotli_quality: u32) -> Self {
+        match encoding {
+            Encoding::Gzip => Encoder::Gzip(GzEncoder::new(vec![], Level::new(level))),
+            Encoding::Deflate => Encoder::Deflate(DeflateEncoder::new(vec![], Level::new(level))),
+            Encoding::Brotli => Encoder::Brotli(Box::new(BrotliEncoder::new(brotli_quality, 22))),
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        use std::io::Write;
+        match self {
+            Encoder::Gzip(encoder) => encoder.write_all(data),
+            Encoder::Deflate(encoder) => encoder.write_all(data),
+            Encoder::Brotli(encoder) => encoder.write_all(data),
+        }
+    }
+
+    /// Takes the compressed data written so far.
+    fn take(&mut self) -> Bytes {
+        let buf = match self {
+            Encoder::Gzip(encoder) => encoder.get_mut(),
+            Encoder::Deflate(encoder) => encoder.get_mut(),
+            Encoder::Brotli(encoder) => encoder.get_mut(),
+        };
+        mem::replace(buf, vec![]).into()
+    }
+
+    fn finish(self) -> io::Result<Bytes> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.finish().map(Into::into),
+            Encoder::Deflate(encoder) => encoder.finish().map(Into::into),
+            Encoder::Brotli(encoder) => encoder.finish().map(Into::into),
+        }
+    }
+}
+
+/// A streaming Brotli encoder that writes the compressed data into a `Vec<u8>`.
+///
+/// `brotli::CompressorWriter` discards the error that occurs at finishing the stream,
+/// so the encoder state is driven directly in order to report it.
+struct BrotliEncoder {
+    state: BrotliEncoderStateStruct<StandardAlloc>,
+    output: Vec<u8>,
+}
+
+impl BrotliEncoder {
+    fn new(quality: u32, lgwin: u32) -> Self {
+        let mut state = BrotliEncoderCreateInstance(StandardAlloc::default());
+        BrotliEncoderSetParameter(
+            &mut state,
+            BrotliEncoderParameter::BROTLI_PARAM_QUALITY,
+            quality,
+        );
+        BrotliEncoderSetParameter(
+            &mut state,
+            BrotliEncoderParameter::BROTLI_PARAM_LGWIN,
+            lgwin,
+        );
+        Self {
+            state,
+            output: vec![],
+        }
+    }
+
+    fn get_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.output
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        self.compress(BrotliEncoderOperation::BROTLI_OPERATION_PROCESS, data)
+    }
+
+    fn finish(mut self) -> io::Result<Vec<u8>> {
+        self.compress(BrotliEncoderOperation::BROTLI_OPERATION_FINISH, &[])?;
+        Ok(mem::replace(&mut self.output, vec![]))
+    }
+
+    fn compress(&mut self, op: BrotliEncoderOperation, input: &[u8]) -> io::Result<()> {
+        let mut buf = [0; 4096];
+        let mut avail_in = input.len();
+        let mut input_offset = 0;
+        loop {
+            let mut avail_out = buf.len();
+            let mut output_offset = 0;
+            let ret = BrotliEncoderCompressStream(
+                &mut self.state,
+                op,
+                &mut avail_in,
+                input,
+                &mut input_offset,
+                &mut avail_out,
+                &mut buf,
+                &mut output_offset,
+                &mut None,
+                &mut |_, _, _, _| (),
+            );
+            self.output.extend_from_slice(&buf[..output_offset]);
+            if ret <= 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "failed to compress the data with Brotli",
+                ));
+            }
+            let done = match op {
+                BrotliEncoderOperation::BROTLI_OPERATION_FINISH => {
+                    BrotliEncoderIsFinished(&self.state) != 0
+                }
+                _ => avail_in == 0 && BrotliEncoderHasMoreOutput(&self.state) == 0,
+            };
+            if done {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl Drop for BrotliEncoder {
+    fn drop(&mut self) {
+        BrotliEncoderDestroyInstance(&mut self.state);
+    }
+}
+
+/// A stream that compresses the chunks of the inner body.
+#[derive(Debug)]
+struct CompressStream {
+    body: ResponseBody,
+    encoder: Option<Encoder>,
+}
+
+impl Stream for CompressStream {
+    type Item = izanami::http::body::Data;
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let encoder = match self.encoder {
+                Some(ref mut encoder) => encoder,
+                None => return Ok(Async::Ready(None)),
+            };
+
+            match futures::try_ready!(self.body.poll_data().map_err(Into::into)) {
+                Some(mut data) => {
+                    while data.has_remaining() {
+                        let n = {
+                            let chunk = data.bytes();
+                            encoder.write(chunk)?;
+                            chunk.len()
+                        };
+                        data.advance(n);
+                    }
+                    let compressed = encoder.take();
+                    if !compressed.is_empty() {
+                        return Ok(Async::Ready(Some(compressed.into())));
+                    }
+                }
+                None => {
+                    let encoder = self
+                        .encoder
+                        .take()
+                        .expect("the encoder should be available");
+                    return Ok(Async::Ready(Some(encoder.finish()?.into())));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{is_compressed_mime, negotiate, Encoding},
+        http::header::{HeaderMap, ACCEPT_ENCODING},
+    };
+
+    const ALL: &[Encoding] = &[Encoding::Brotli, Encoding::Gzip, Encoding::Deflate];
+
+    fn accept_encoding(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn negotiate_encoding() {
+        assert_eq!(
+            negotiate(&accept_encoding("gzip, deflate, br"), ALL),
+            Some(Encoding::Brotli)
+        );
+        assert_eq!(
+            negotiate(&accept_encoding("gzip;q=1.0, br;q=0.5"), ALL),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(
+            negotiate(&accept_encoding("br, gzip"), &[Encoding::Gzip]),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(
+            negotiate(&accept_encoding("*;q=0.5, br;q=0"), ALL),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(negotiate(&accept_encoding("identity"), ALL), None);
+        assert_eq!(negotiate(&HeaderMap::new(), ALL), None);
+    }
+
+    #[test]
+    fn compressed_mime() {
+        assert!(is_compressed_mime(&mime::IMAGE_PNG));
+        assert!(is_compressed_mime(&"application/zip".parse().unwrap()));
+        assert!(!is_compressed_mime(&mime::IMAGE_SVG));
+        assert!(!is_compressed_mime(&mime::TEXT_HTML_UTF_8));
+        assert!(!is_compressed_mime(&mime::APPLICATION_JSON));
+    }
+}
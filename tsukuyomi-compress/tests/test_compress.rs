@@ -0,0 +1,140 @@
+use {
+    bytes::Bytes,
+    flate2::read::GzDecoder,
+    http::{
+        header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, VARY},
+        Request, StatusCode,
+    },
+    izanami::http::body::Data,
+    std::io::Read,
+    tsukuyomi::{
+        endpoint::builder as endpoint,
+        output::ResponseBody,
+        test::{self, loc, TestServer},
+        App,
+    },
+    tsukuyomi_compress::Compression,
+};
+
+#[test]
+fn test_version_sync() {
+    version_sync::assert_html_root_url_updated!("src/lib.rs");
+}
+
+fn content() -> String {
+    "Hello, world!\n".repeat(256)
+}
+
+#[test]
+fn gzip_encoding() -> test::Result {
+    let app = App::build(|s| {
+        s.at("/", Compression::new(), {
+            endpoint::get() //
+                .call(content)
+        })
+    })?;
+    let mut server = TestServer::new(app)?;
+    let mut client = server.connect();
+
+    let body = client
+        .request(
+            Request::get("/")
+                .header(ACCEPT_ENCODING, "gzip, deflate")
+                .body("")?,
+        )
+        .assert(loc!(), StatusCode::OK)?
+        .assert(loc!(), test::header::eq(CONTENT_ENCODING, "gzip"))?
+        .assert(loc!(), test::header::eq(VARY, "accept-encoding"))?
+        .assert(loc!(), test::header::not_exists(CONTENT_LENGTH))?
+        .into_bytes()?;
+
+    let mut decoded = String::new();
+    GzDecoder::new(&body[..]).read_to_string(&mut decoded)?;
+    assert_eq!(decoded, content());
+
+    Ok(())
+}
+
+/// Creates the chunks of a streaming body, which are large enough for the
+/// encoder to emit the compressed data before the end of stream.
+fn chunks() -> Vec<String> {
+    (0..16u32)
+        .map(|i| {
+            (0..256u32)
+                .map(|j| format!("{:08x}\n", (i * 256 + j).wrapping_mul(0x9e37_79b9)))
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn brotli_encoding() -> test::Result {
+    let app = App::build(|s| {
+        s.at("/", Compression::new(), {
+            endpoint::get() //
+                .call(|| {
+                    let chunks = chunks()
+                        .into_iter()
+                        .map(|chunk| Data::from(Bytes::from(chunk)));
+                    let stream = futures::stream::iter_ok::<_, std::io::Error>(chunks);
+                    http::Response::new(ResponseBody::wrap_stream(stream))
+                })
+        })
+    })?;
+    let mut server = TestServer::new(app)?;
+    let mut client = server.connect();
+
+    let body = client
+        .request(Request::get("/").header(ACCEPT_ENCODING, "br").body("")?)
+        .assert(loc!(), StatusCode::OK)?
+        .assert(loc!(), test::header::eq(CONTENT_ENCODING, "br"))?
+        .assert(loc!(), test::header::not_exists(CONTENT_LENGTH))?
+        .into_bytes()?;
+
+    let mut decoded = String::new();
+    brotli::Decompressor::new(&body[..], 4096).read_to_string(&mut decoded)?;
+    assert_eq!(decoded, chunks().concat());
+
+    Ok(())
+}
+
+#[test]
+fn without_accept_encoding() -> test::Result {
+    let app = App::build(|s| {
+        s.at("/", Compression::new(), {
+            endpoint::get() //
+                .call(content)
+        })
+    })?;
+    let mut server = TestServer::new(app)?;
+    let mut client = server.connect();
+
+    client
+        .get("/")
+        .assert(loc!(), StatusCode::OK)?
+        .assert(loc!(), test::header::not_exists(CONTENT_ENCODING))?
+        .assert(loc!(), test::header::eq(VARY, "accept-encoding"))?
+        .assert(loc!(), test::body::eq(content()))?;
+
+    Ok(())
+}
+
+#[test]
+fn skip_small_response() -> test::Result {
+    let app = App::build(|s| {
+        s.at("/", Compression::new(), {
+            endpoint::get() //
+                .call(|| "hello")
+        })
+    })?;
+    let mut server = TestServer::new(app)?;
+    let mut client = server.connect();
+
+    client
+        .request(Request::get("/").header(ACCEPT_ENCODING, "gzip").body("")?)
+        .assert(loc!(), StatusCode::OK)?
+        .assert(loc!(), test::header::not_exists(CONTENT_ENCODING))?
+        .assert(loc!(), test::body::eq("hello"))?;
+
+    Ok(())
+}
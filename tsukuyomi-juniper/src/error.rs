@@ -2,7 +2,7 @@ use {
     http::{Response, StatusCode},
     serde_json::json,
     tsukuyomi::{
-        error::{Error, HttpError}, //
+        error::{Error, HttpError, ProblemDetails},
         future::{Async, Poll, TryFuture},
         handler::{metadata::Metadata, Handler, ModifyHandler},
         input::Input,
@@ -11,12 +11,12 @@ use {
     },
 };
 
+/// The error that occurs when parsing the request as a GraphQL request.
+#[allow(missing_docs)]
 #[derive(Debug, failure::Fail)]
 pub enum GraphQLParseError {
     #[fail(display = "the request method is invalid")]
     InvalidRequestMethod,
-    #[fail(display = "missing query")]
-    MissingQuery,
     #[fail(display = "missing content-type")]
     MissingMime,
     #[fail(display = "the content type is invalid.")]
@@ -24,7 +24,7 @@ pub enum GraphQLParseError {
     #[fail(display = "failed to parse input as a JSON object")]
     ParseJson(#[fail(cause)] serde_json::Error),
     #[fail(display = "failed to parse HTTP query")]
-    ParseQuery(#[fail(cause)] serde_urlencoded::de::Error),
+    ParseQuery(#[fail(cause)] tsukuyomi::input::query::QueryError),
     #[fail(display = "failed to decode input as a UTF-8 sequence")]
     DecodeUtf8(#[fail(cause)] std::str::Utf8Error),
 }
@@ -35,6 +35,18 @@ impl HttpError for GraphQLParseError {
     }
 }
 
+impl From<GraphQLParseError> for ProblemDetails {
+    fn from(err: GraphQLParseError) -> Self {
+        let detail = match err {
+            GraphQLParseError::ParseJson(ref cause) => format!("{}: {}", err, cause),
+            GraphQLParseError::ParseQuery(ref cause) => format!("{}: {}", err, cause),
+            GraphQLParseError::DecodeUtf8(ref cause) => format!("{}: {}", err, cause),
+            _ => err.to_string(),
+        };
+        ProblemDetails::new(err.status_code()).with_detail(detail)
+    }
+}
+
 /// Creates a `ModifyHandler` that catches the all kind of errors that the handler throws
 /// and converts them into GraphQL errors.
 pub fn capture_errors() -> CaptureErrors {
@@ -16,7 +16,7 @@ mod graphiql;
 mod request;
 
 pub use crate::{
-    error::{capture_errors, CaptureErrors},
+    error::{capture_errors, CaptureErrors, GraphQLParseError},
     graphiql::graphiql_source,
     request::{request, GraphQLRequest, GraphQLResponse},
 };
@@ -96,19 +96,6 @@ where
 }
 
 fn parse_query_request<S>(input: &mut Input<'_>) -> tsukuyomi::Result<GraphQLRequest<S>>
-where
-    S: ScalarValue,
-    for<'a> &'a S: ScalarRefValue<'a>,
-{
-    let query_str = input
-        .request
-        .uri()
-        .query()
-        .ok_or_else(|| GraphQLParseError::MissingQuery)?;
-    parse_query_str(query_str).map_err(Into::into)
-}
-
-fn parse_query_str<S>(s: &str) -> Result<GraphQLRequest<S>, GraphQLParseError>
 where
     S: ScalarValue,
     for<'a> &'a S: ScalarRefValue<'a>,
@@ -120,7 +107,7 @@ where
         variables: Option<String>,
     }
     let parsed: ParsedQuery =
-        serde_urlencoded::from_str(s).map_err(GraphQLParseError::ParseQuery)?;
+        tsukuyomi::input::query::parse(input).map_err(GraphQLParseError::ParseQuery)?;
 
     let query = percent_decode(parsed.query.as_ref())
         .decode_utf8()
@@ -10,20 +10,31 @@ use {
         Request, Response, StatusCode,
     },
     sha1::{Digest, Sha1},
-    tsukuyomi::error::HttpError,
+    tsukuyomi::error::{HttpError, ProblemDetails},
 };
 
+/// The error that occurs when the request is not a valid WebSocket handshake.
 #[derive(Debug, failure::Fail)]
 pub enum HandshakeError {
+    /// A required header field is missing.
     #[fail(display = "The header is missing: `{}'", name)]
-    MissingHeader { name: &'static str },
+    MissingHeader {
+        /// The name of the header field.
+        name: &'static str,
+    },
 
+    /// The value of a header field is invalid.
     #[fail(display = "The header value is invalid: `{}'", name)]
-    InvalidHeader { name: &'static str },
+    InvalidHeader {
+        /// The name of the header field.
+        name: &'static str,
+    },
 
+    /// The value of `Sec-WebSocket-Key` is invalid.
     #[fail(display = "The value of `Sec-WebSocket-Key` is invalid")]
     InvalidSecWebSocketKey,
 
+    /// The value of `Sec-WebSocket-Version` is not supported.
     #[fail(display = "The value of `Sec-WebSocket-Version` must be equal to '13'")]
     InvalidSecWebSocketVersion,
 }
@@ -34,6 +45,24 @@ impl HttpError for HandshakeError {
     }
 }
 
+impl From<HandshakeError> for ProblemDetails {
+    fn from(err: HandshakeError) -> Self {
+        let problem = ProblemDetails::new(err.status_code()) //
+            .with_detail(err.to_string());
+        match err {
+            HandshakeError::MissingHeader { name } | HandshakeError::InvalidHeader { name } => {
+                problem.with_extension("header", name)
+            }
+            HandshakeError::InvalidSecWebSocketKey => {
+                problem.with_extension("header", "Sec-WebSocket-Key")
+            }
+            HandshakeError::InvalidSecWebSocketVersion => {
+                problem.with_extension("header", "Sec-WebSocket-Version")
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Handshake {
     accept_hash: String,
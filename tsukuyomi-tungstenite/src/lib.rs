@@ -19,7 +19,10 @@ mod ws;
 #[doc(no_inline)]
 pub use tungstenite::protocol::{Message, WebSocketConfig};
 
-pub use crate::ws::{StreamError, WebSocketStream, Ws};
+pub use crate::{
+    handshake::HandshakeError,
+    ws::{StreamError, WebSocketStream, Ws},
+};
 
 #[allow(missing_docs)]
 pub fn ws() -> impl tsukuyomi::Extractor<
@@ -0,0 +1,24 @@
+use std::{env, process::Command};
+
+fn main() {
+    // `std::any::type_name` is stable since Rust 1.38, which is newer than the minimal
+    // supported version. It is used only for the error messages, so the older compilers
+    // fall back to a placeholder instead of the name of types.
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let minor = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|version| {
+            version
+                .split('.')
+                .nth(1)
+                .and_then(|m| m.parse::<u32>().ok())
+        });
+
+    println!("cargo:rustc-check-cfg=cfg(tsukuyomi_type_name)");
+    if minor.map_or(false, |minor| minor >= 38) {
+        println!("cargo:rustc-cfg=tsukuyomi_type_name");
+    }
+}
@@ -61,6 +61,11 @@ fn parse_literal(path: &str, span: Span) -> parse::Result<Vec<Param<'_>>> {
     while let Some(segment) = iter.next() {
         match segment.split_at(1) {
             (":", name) => {
+                // strip the constraint, e.g. `:id<\d+>` or `:id:u64`.
+                let name = match name.find(|c| c == '<' || c == ':') {
+                    Some(pos) => &name[..pos],
+                    None => name,
+                };
                 if !names.insert(name) {
                     return spanned_err(
                         span,
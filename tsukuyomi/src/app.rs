@@ -4,34 +4,115 @@ pub mod concurrency;
 pub mod config;
 pub mod path;
 
+mod hooks;
+mod host;
+mod mount;
+mod normalize;
 mod recognizer;
+mod routes;
 mod scope;
 mod service;
+mod state;
 
 #[cfg(test)]
 mod tests;
 
-pub(crate) use self::recognizer::Captures;
 pub use self::{
     config::{Error, Result},
+    host::VirtualHosts,
+    mount::MountHandler,
+    normalize::{NormalizePath, TrailingSlash},
+    routes::{NamedRoutes, RouteInfo, UrlForError},
     service::{AppBody, AppService},
 };
+pub(crate) use self::{recognizer::Captures, state::States};
 
 use {
     self::{
         concurrency::{Concurrency, DefaultConcurrency},
+        hooks::Hooks,
         recognizer::{RecognizeError, Recognizer},
         scope::{Scope, ScopeId, Scopes},
     },
-    crate::{input::localmap::local_key, uri::Uri},
-    std::{fmt, sync::Arc},
+    crate::{
+        handler::metadata::{AllowedMethods, RequiredStates},
+        input::localmap::local_key,
+        uri::Uri,
+    },
+    http::{header::HeaderValue, Method, Request},
+    std::{fmt, net::SocketAddr, sync::Arc},
 };
 
 local_key! {
-    pub const REMOTE_ADDR: std::net::SocketAddr;
+    pub const REMOTE_ADDR: RemoteAddr;
+
+    /// The path prefix stripped from the request path by `Scope::mount`.
+    ///
+    /// The value is the empty string if the `App` is mounted at the root.
+    pub const MOUNT_PREFIX: String;
+}
+
+/// The address of the peer that sent the request.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemoteAddr {
+    /// The address of the peer connected via TCP.
+    Tcp(SocketAddr),
+
+    /// The credentials of the peer connected via a Unix domain socket.
+    ///
+    /// The value is `None` if the credentials could not be retrieved from the socket.
+    Unix(Option<PeerCred>),
+}
+
+impl RemoteAddr {
+    /// Returns the socket address if the peer is connected via TCP.
+    pub fn as_tcp(&self) -> Option<&SocketAddr> {
+        match self {
+            RemoteAddr::Tcp(addr) => Some(addr),
+            RemoteAddr::Unix(..) => None,
+        }
+    }
+
+    /// Returns the peer credentials if the peer is connected via a Unix domain socket.
+    pub fn as_unix(&self) -> Option<&PeerCred> {
+        match self {
+            RemoteAddr::Tcp(..) => None,
+            RemoteAddr::Unix(cred) => cred.as_ref(),
+        }
+    }
+}
+
+impl From<SocketAddr> for RemoteAddr {
+    fn from(addr: SocketAddr) -> Self {
+        RemoteAddr::Tcp(addr)
+    }
+}
+
+impl fmt::Display for RemoteAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemoteAddr::Tcp(addr) => fmt::Display::fmt(addr, f),
+            RemoteAddr::Unix(Some(cred)) => write!(f, "unix:uid={},gid={}", cred.uid, cred.gid),
+            RemoteAddr::Unix(None) => f.write_str("unix"),
+        }
+    }
+}
+
+/// The credentials of the process connected via a Unix domain socket.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PeerCred {
+    /// The user ID of the peer process.
+    pub uid: u32,
+
+    /// The group ID of the peer process.
+    pub gid: u32,
 }
 
 /// The main type representing an HTTP application.
+///
+/// The requests with `HEAD` to the routes that accept `GET` but not `HEAD` are
+/// passed to the handlers as `GET`, and the body of their responses is discarded.
+/// The requests with `OPTIONS` to the routes that do not accept it are answered with `Allow`.
 #[derive(Debug)]
 pub struct App<C: Concurrency = DefaultConcurrency> {
     inner: Arc<AppInner<C>>,
@@ -50,19 +131,139 @@ impl<C: Concurrency> App<C> {
     pub fn new_service(&self) -> AppService<C> {
         AppService::new(self.inner.clone())
     }
+
+    /// Returns the table of the named routes in this `App`.
+    pub fn named_routes(&self) -> &NamedRoutes {
+        &self.inner.named_routes
+    }
+
+    /// Generates the URL of the route with the specified name.
+    ///
+    /// See the documentation of `NamedRoutes::url_for` for details.
+    pub fn url_for(
+        &self,
+        name: &str,
+        params: &[(&str, &str)],
+    ) -> std::result::Result<String, UrlForError> {
+        self.inner.named_routes.url_for(name, params)
+    }
+
+    /// Returns the list of routes registered in this `App`.
+    ///
+    /// The routes are listed in the order of registration, followed by
+    /// the default routes of the scopes.
+    pub fn routes(&self) -> Vec<RouteInfo<'_>> {
+        let inner = &*self.inner;
+
+        let routes = inner.recognizer.iter().map(|resource| RouteInfo {
+            uri: resource.uri.as_str(),
+            name: resource.name.as_ref().map(String::as_str),
+            allowed_methods: &resource.allowed_methods,
+            scopes: inner.scope_prefixes(resource.ancestors.iter().cloned()),
+            is_default: false,
+        });
+
+        let default_routes = inner
+            .scopes
+            .iter()
+            .filter(|scope| scope.data.default_handler.is_some())
+            .map(|scope| RouteInfo {
+                uri: scope.data.prefix.as_str(),
+                name: None,
+                allowed_methods: &scope.data.default_allowed_methods,
+                scopes: inner
+                    .scope_prefixes(scope.ancestors().iter().cloned().chain(Some(scope.id()))),
+                is_default: true,
+            });
+
+        routes.chain(default_routes).collect()
+    }
 }
 
 #[derive(Debug)]
 struct AppInner<C: Concurrency> {
     recognizer: Recognizer<Arc<ResourceData<C>>>,
     scopes: Scopes<ScopeData<C>>,
+    named_routes: NamedRoutes,
+    normalize_path: NormalizePath,
+    hooks: Hooks,
 }
 
 impl<C: Concurrency> AppInner<C> {
+    fn empty() -> Self {
+        Self {
+            recognizer: Recognizer::default(),
+            scopes: Scopes::new(ScopeData {
+                prefix: Uri::root(),
+                default_handler: None,
+                error_handler: None,
+                default_allowed_methods: AllowedMethods::any(),
+                default_required_states: RequiredStates::new(),
+                trailing_slash: None,
+                states: Arc::new(States::default()),
+            }),
+            named_routes: NamedRoutes::default(),
+            normalize_path: NormalizePath::default(),
+            hooks: Hooks::default(),
+        }
+    }
+
     fn scope(&self, id: ScopeId) -> &Scope<ScopeData<C>> {
         &self.scopes[id]
     }
 
+    /// Merges the application states of each scope with the ones of its ancestors,
+    /// and checks that the states required by the routes are registered.
+    fn resolve_states(&mut self) -> Result<()> {
+        let ids: Vec<ScopeId> = self.scopes.iter().map(|scope| scope.id()).collect();
+        for id in ids {
+            // The parent scope precedes its children, so its states have already been merged.
+            let parent = match self.scope(id).ancestors().last() {
+                Some(&parent) => parent,
+                None => continue,
+            };
+            let mut states = (*self.scope(parent).data.states).clone();
+            states.extend(&self.scope(id).data.states);
+            self.scopes[id].data.states = Arc::new(states);
+        }
+
+        let find_missing = |states: &States, required: &RequiredStates| {
+            required
+                .iter()
+                .find(|&(id, _)| !states.contains(id))
+                .map(|(_, name)| name)
+        };
+        let missing_state = |uri: &Uri, name: &str| {
+            Error::custom(failure::format_err!(
+                "the route `{}` requires the application state of type `{}`, \
+                 which is not registered in its scope",
+                uri,
+                name
+            ))
+        };
+        for resource in self.recognizer.iter() {
+            let states = &self.scope(resource.scope).data.states;
+            if let Some(name) = find_missing(states, &resource.required_states) {
+                return Err(missing_state(&resource.uri, name));
+            }
+        }
+        for scope in self.scopes.iter() {
+            let states = &scope.data.states;
+            if let Some(name) = find_missing(states, &scope.data.default_required_states) {
+                return Err(missing_state(&scope.data.prefix, name));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the URI prefixes of the specified scopes.
+    fn scope_prefixes(&self, ids: impl IntoIterator<Item = ScopeId>) -> Vec<&str> {
+        ids.into_iter()
+            .map(|id| self.scope(id).data.prefix.as_str())
+            .collect()
+    }
+
     /// Infers the scope where the input path belongs from the extracted candidates.
     fn infer_scope<'a>(
         &self,
@@ -98,19 +299,51 @@ impl<C: Concurrency> AppInner<C> {
         self.scope(node_id)
     }
 
-    fn find_default_handler(&self, start: ScopeId) -> Option<&C::Handler> {
+    /// Returns the default handler applied to the specified scope, with its allowed methods.
+    fn find_default_handler(&self, start: ScopeId) -> Option<(&C::Handler, &AllowedMethods)> {
         let scope = self.scope(start);
         if let Some(ref f) = scope.data.default_handler {
-            return Some(f);
+            return Some((f, &scope.data.default_allowed_methods));
         }
         scope
             .ancestors()
             .iter()
             .rev()
-            .filter_map(|&id| self.scope(id).data.default_handler.as_ref())
+            .map(|&id| &self.scope(id).data)
+            .filter_map(|data| {
+                data.default_handler
+                    .as_ref()
+                    .map(|f| (f, &data.default_allowed_methods))
+            })
             .next()
     }
 
+    /// Returns the error handler applied to the specified scope.
+    fn find_error_handler(&self, start: ScopeId) -> Option<&ErrorHandler> {
+        let scope = self.scope(start);
+        if let Some(ref f) = scope.data.error_handler {
+            return Some(&**f);
+        }
+        scope
+            .ancestors()
+            .iter()
+            .rev()
+            .filter_map(|&id| self.scope(id).data.error_handler.as_ref())
+            .map(|f| &**f)
+            .next()
+    }
+
+    /// Returns the policy on the trailing slash applied to the specified resource.
+    fn trailing_slash(&self, resource: &ResourceData<C>) -> TrailingSlash {
+        resource
+            .ancestors
+            .iter()
+            .rev()
+            .filter_map(|&id| self.scope(id).data.trailing_slash)
+            .next()
+            .unwrap_or_default()
+    }
+
     fn find_resource(
         &self,
         path: &str,
@@ -129,9 +362,20 @@ impl<C: Concurrency> AppInner<C> {
     }
 }
 
+/// The function that creates the response from an error occurred in a scope.
+type ErrorHandler = dyn Fn(&crate::Error, &Request<()>) -> crate::output::Response + Send + Sync;
+
 struct ScopeData<C: Concurrency> {
     prefix: Uri,
     default_handler: Option<C::Handler>,
+    error_handler: Option<Box<ErrorHandler>>,
+    default_allowed_methods: AllowedMethods,
+    default_required_states: RequiredStates,
+    trailing_slash: Option<TrailingSlash>,
+    /// The application states visible in this scope.
+    ///
+    /// While building `App`, it contains only the states registered in this scope.
+    states: Arc<States>,
 }
 
 impl<C: Concurrency> fmt::Debug for ScopeData<C> {
@@ -142,6 +386,12 @@ impl<C: Concurrency> fmt::Debug for ScopeData<C> {
                 "default_handler",
                 &self.default_handler.as_ref().map(|_| "<default handler>"),
             )
+            .field(
+                "error_handler",
+                &self.error_handler.as_ref().map(|_| "<error handler>"),
+            )
+            .field("trailing_slash", &self.trailing_slash)
+            .field("states", &self.states)
             .finish()
     }
 }
@@ -151,15 +401,38 @@ struct ResourceData<C: Concurrency> {
     scope: ScopeId,
     ancestors: Vec<ScopeId>,
     uri: Uri,
+    name: Option<String>,
+    allowed_methods: AllowedMethods,
+    required_states: RequiredStates,
     handler: C::Handler,
 }
 
+impl<C: Concurrency> ResourceData<C> {
+    /// Returns the value of `Allow` header field, or `None` if the resource accepts any methods.
+    ///
+    /// The methods handled automatically (`HEAD` and `OPTIONS`) are also listed.
+    fn allow_header(&self) -> Option<HeaderValue> {
+        if self.allowed_methods.is_any() {
+            return None;
+        }
+        let mut methods = self.allowed_methods.clone();
+        if methods.contains(&Method::GET) {
+            methods.extend(Some(Method::HEAD));
+        }
+        methods.extend(Some(Method::OPTIONS));
+        Some(methods.to_header_value())
+    }
+}
+
 impl<C: Concurrency> fmt::Debug for ResourceData<C> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Endpoint")
             .field("scope", &self.scope)
             .field("ancestors", &self.ancestors)
             .field("uri", &self.uri)
+            .field("name", &self.name)
+            .field("allowed_methods", &self.allowed_methods)
+            .field("required_states", &self.required_states)
             .finish()
     }
 }
@@ -1,16 +1,25 @@
 use {
     super::{
         concurrency::{Concurrency, DefaultConcurrency},
+        mount::MountHandler,
+        normalize::{NormalizePath, TrailingSlash},
         path::{IntoPath, Path, PathExtractor},
-        recognizer::Recognizer,
-        scope::{ScopeId, Scopes},
-        App, AppInner, ResourceData, ScopeData, Uri,
+        scope::ScopeId,
+        App, AppInner, AppService, ResourceData, ScopeData, States, Uri,
     },
     crate::{
         endpoint::Endpoint,
-        handler::{metadata::Metadata, Handler, ModifyHandler},
+        handler::{
+            metadata::{AllowedMethods, Metadata, RequiredStates},
+            Handler, ModifyHandler,
+        },
+        input::body::RequestBody,
+        output::{IntoResponse, ResponseBody},
         util::{Chain, Never},
     },
+    futures01::IntoFuture,
+    http::{Request, Response},
+    izanami::service::Service,
     std::{error, fmt, marker::PhantomData, rc::Rc, sync::Arc},
 };
 
@@ -61,13 +70,7 @@ where
     where
         F: FnOnce(&mut Scope<'_, (), C>) -> Result<()>,
     {
-        let mut app = AppInner {
-            recognizer: Recognizer::default(),
-            scopes: Scopes::new(ScopeData {
-                prefix: Uri::root(),
-                default_handler: None,
-            }),
-        };
+        let mut app = AppInner::empty();
 
         f(&mut Scope {
             app: &mut app,
@@ -76,6 +79,8 @@ where
             _marker: PhantomData,
         })?;
 
+        app.resolve_states()?;
+
         Ok(Self {
             inner: Arc::new(app),
         })
@@ -96,6 +101,15 @@ where
     C: Concurrency,
 {
     pub(crate) fn route2<H>(&mut self, handler: H) -> Result<()>
+    where
+        H: Handler,
+        M: ModifyHandler<H>,
+        M::Handler: Into<C::Handler>,
+    {
+        self.route_with_name(None, handler)
+    }
+
+    fn route_with_name<H>(&mut self, name: Option<&str>, handler: H) -> Result<()>
     where
         H: Handler,
         M: ModifyHandler<H>,
@@ -110,6 +124,13 @@ where
                 .join(&path)
                 .map_err(Error::custom)?;
 
+            if let Some(name) = name {
+                self.app
+                    .named_routes
+                    .insert(name.to_owned(), uri.clone())
+                    .map_err(Error::custom)?;
+            }
+
             let scope = &self.app.scopes[self.scope_id];
             self.app
                 .recognizer
@@ -124,12 +145,23 @@ where
                             .chain(Some(scope.id()))
                             .collect(),
                         uri: uri.clone(),
+                        name: name.map(ToOwned::to_owned),
+                        allowed_methods: handler.metadata().allowed_methods().clone(),
+                        required_states: handler.metadata().required_states().clone(),
                         handler: handler.into(),
                     }),
                 )
                 .map_err(Error::custom)?;
         } else {
-            self.app.scopes[self.scope_id].data.default_handler = Some(handler.into());
+            if name.is_some() {
+                return Err(Error::custom(failure::format_err!(
+                    "the default route cannot be named"
+                )));
+            }
+            let scope = &mut self.app.scopes[self.scope_id].data;
+            scope.default_allowed_methods = handler.metadata().allowed_methods().clone();
+            scope.default_required_states = handler.metadata().required_states().clone();
+            scope.default_handler = Some(handler.into());
         }
 
         Ok(())
@@ -154,6 +186,28 @@ where
         self.route2(modifier.modify(handler))
     }
 
+    /// Adds a route with the specified name onto the current scope.
+    ///
+    /// The URL of the named route can be generated by using `App::url_for`
+    /// or `NamedRoutes::url_for`.
+    pub fn at_named<P, M2, T>(
+        &mut self,
+        name: &str,
+        path: P,
+        modifier: M2,
+        endpoint: T,
+    ) -> Result<()>
+    where
+        P: IntoPath,
+        T: Endpoint<P::Output>,
+        M2: ModifyHandler<RouteHandler<P::Extractor, T>>,
+        M: ModifyHandler<M2::Handler>,
+        M::Handler: Into<C::Handler>,
+    {
+        let handler = RouteHandler::new(path.into_path(), endpoint);
+        self.route_with_name(Some(name), modifier.modify(handler))
+    }
+
     /// Adds a default route onto the current scope.
     ///
     /// The default route is used when the incoming request URI matches the prefix
@@ -168,6 +222,47 @@ where
         self.at(Path::<()>::new("*"), modifier, endpoint)
     }
 
+    /// Sets the function that creates the response from the errors occurred in the current scope.
+    ///
+    /// The error handler receives the error and the request without the body.
+    /// If the current scope has no error handler, the one of the nearest ancestor
+    /// scope is used, and the response is created by `Error::to_response` if
+    /// no error handler is found.
+    ///
+    /// Returning `ProblemDetails::from_error(err)` renders all errors in the scope
+    /// as the problem details defined in RFC 7807.
+    ///
+    /// ```
+    /// # use tsukuyomi::{App, endpoint::builder as endpoint, output::ResponseBody};
+    /// # use http::Response;
+    /// # fn main() -> tsukuyomi::app::Result<()> {
+    /// let app: App = App::build(|s| {
+    ///     s.error_handler(|err, request| {
+    ///         let mut response = Response::new(ResponseBody::from(format!(
+    ///             "{} {}: {}",
+    ///             request.method(),
+    ///             request.uri().path(),
+    ///             err
+    ///         )));
+    ///         *response.status_mut() = err.status_code();
+    ///         response
+    ///     });
+    ///     s.at("/", (), endpoint::reply("index"))
+    /// })?;
+    /// # drop(app);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn error_handler<F, R>(&mut self, f: F)
+    where
+        F: Fn(&crate::Error, &Request<()>) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.app.scopes[self.scope_id].data.error_handler = Some(Box::new(
+            move |err: &crate::Error, request: &Request<()>| f(err, request).into_response(),
+        ));
+    }
+
     /// Creates a sub-scope onto the current scope.
     ///
     /// Calling `nest` constructs a scope and allows to set a *default* route
@@ -187,6 +282,11 @@ where
                 ScopeData {
                     prefix: parent.prefix.join(&prefix).map_err(Error::custom)?,
                     default_handler: None,
+                    error_handler: None,
+                    default_allowed_methods: AllowedMethods::any(),
+                    default_required_states: RequiredStates::new(),
+                    trailing_slash: None,
+                    states: Arc::new(States::default()),
                 }
             })
             .map_err(Error::custom)?;
@@ -199,6 +299,110 @@ where
         })
     }
 
+    /// Mounts an `App` under the specified prefix.
+    ///
+    /// The requests whose path starts with `prefix` are forwarded to the mounted `App`
+    /// with the prefix stripped from the path, e.g. the mounted `App` at `/admin`
+    /// observes the request to `/admin/users` as the one to `/users`.
+    /// The stripped prefix is available in the mounted `App` through `MOUNT_PREFIX`.
+    ///
+    /// Note that the upgrade requests (e.g. WebSocket) are not forwarded to the mounted `App`.
+    pub fn mount<C2>(&mut self, prefix: &str, app: App<C2>) -> Result<()>
+    where
+        C2: Concurrency,
+        M: ModifyHandler<MountHandler<AppService<C2>>>,
+        M::Handler: Into<C::Handler>,
+    {
+        self.mount_service(prefix, app.new_service())?;
+        self.app.hooks.extend(&app.inner.hooks);
+        Ok(())
+    }
+
+    /// Mounts a `Service` under the specified prefix.
+    ///
+    /// See the documentation of `mount` for details.
+    pub fn mount_service<S, B>(&mut self, prefix: &str, service: S) -> Result<()>
+    where
+        S: Service<Request<RequestBody>, Response = Response<B>> + Clone,
+        S::Error: Into<crate::Error>,
+        B: Into<ResponseBody>,
+        M: ModifyHandler<MountHandler<S>>,
+        M::Handler: Into<C::Handler>,
+    {
+        let prefix: Uri = prefix.parse().map_err(Error::custom)?;
+        let catch_all: Uri = "/*path".parse().map_err(Error::custom)?;
+        let uri = prefix.join(&catch_all).map_err(Error::custom)?;
+
+        // The prefix without the trailing slash is also routed to the mounted service
+        // so that the request to `/admin` reaches the root of the `App` mounted at `/admin`.
+        if !prefix.as_str().ends_with('/') {
+            self.route2(MountHandler::new(service.clone(), prefix))?;
+        }
+        self.route2(MountHandler::new(service, uri))
+    }
+
+    /// Sets the policy on the trailing slash of the routes in the current scope.
+    ///
+    /// The policy is inherited by the sub-scopes unless they override it.
+    pub fn trailing_slash(&mut self, policy: TrailingSlash) {
+        self.app.scopes[self.scope_id].data.trailing_slash = Some(policy);
+    }
+
+    /// Registers an application state onto the current scope.
+    ///
+    /// The registered state is shared by the routes in the current scope and
+    /// its sub-scopes, and is obtained by using `extractor::state`.
+    /// If a state of the same type is registered in a sub-scope, it overrides this one.
+    pub fn state<T>(&mut self, state: T)
+    where
+        T: Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.app.scopes[self.scope_id].data.states).insert(state);
+    }
+
+    /// Registers a hook that is run before the server starts serving the requests.
+    ///
+    /// The hooks are run in order of registration, by `Server::run_forever` and
+    /// at the construction of `test::TestServer`. If one of the hooks fails,
+    /// the remaining ones are skipped and the server does not start.
+    pub fn on_startup<F, R>(&mut self, f: F)
+    where
+        F: Fn() -> R + Send + Sync + 'static,
+        R: IntoFuture<Item = ()>,
+        R::Future: Send + 'static,
+        R::Error: Into<failure::Error>,
+    {
+        self.app.hooks.on_startup(f);
+    }
+
+    /// Registers a hook that is run after the server has stopped.
+    ///
+    /// The hooks are run in order of registration. The errors from the hooks
+    /// are logged and do not stop the remaining ones.
+    pub fn on_shutdown<F, R>(&mut self, f: F)
+    where
+        F: Fn() -> R + Send + Sync + 'static,
+        R: IntoFuture<Item = ()>,
+        R::Future: Send + 'static,
+        R::Error: Into<failure::Error>,
+    {
+        self.app.hooks.on_shutdown(f);
+    }
+
+    /// Sets the normalization applied to the request path before routing.
+    ///
+    /// Since the normalization is applied before determining the scope,
+    /// this method can be called only in the root scope.
+    pub fn normalize_path(&mut self, normalize: NormalizePath) -> Result<()> {
+        if self.scope_id != ScopeId::root() {
+            return Err(Error::custom(failure::format_err!(
+                "the path normalization can be set only in the root scope"
+            )));
+        }
+        self.app.normalize_path = normalize;
+        Ok(())
+    }
+
     /// Adds the provided `ModifyHandler` to the stack and executes a configuration.
     ///
     /// Unlike `nest`, this method does not create a scope.
@@ -237,6 +441,7 @@ where
             path => Metadata::new(path.parse().expect("this is a bug")),
         };
         *metadata.allowed_methods_mut() = endpoint.allowed_methods();
+        *metadata.required_states_mut() = endpoint.required_states();
 
         Self {
             endpoint,
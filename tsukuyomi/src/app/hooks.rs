@@ -0,0 +1,84 @@
+use {
+    futures01::{stream, Future, IntoFuture, Stream},
+    std::{fmt, sync::Arc},
+};
+
+type HookFuture = Box<dyn Future<Item = (), Error = failure::Error> + Send + 'static>;
+type Hook = Arc<dyn Fn() -> HookFuture + Send + Sync + 'static>;
+
+/// The lifecycle hooks registered in an `App`.
+#[derive(Clone, Default)]
+pub(super) struct Hooks {
+    startup: Vec<Hook>,
+    shutdown: Vec<Hook>,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("startup", &self.startup.len())
+            .field("shutdown", &self.shutdown.len())
+            .finish()
+    }
+}
+
+impl Hooks {
+    pub(super) fn on_startup<F, R>(&mut self, f: F)
+    where
+        F: Fn() -> R + Send + Sync + 'static,
+        R: IntoFuture<Item = ()>,
+        R::Future: Send + 'static,
+        R::Error: Into<failure::Error>,
+    {
+        self.startup.push(hook(f));
+    }
+
+    pub(super) fn on_shutdown<F, R>(&mut self, f: F)
+    where
+        F: Fn() -> R + Send + Sync + 'static,
+        R: IntoFuture<Item = ()>,
+        R::Future: Send + 'static,
+        R::Error: Into<failure::Error>,
+    {
+        self.shutdown.push(hook(f));
+    }
+
+    /// Appends the hooks of another `App`, e.g. the mounted one.
+    pub(super) fn extend(&mut self, other: &Self) {
+        self.startup.extend(other.startup.iter().cloned());
+        self.shutdown.extend(other.shutdown.iter().cloned());
+    }
+
+    /// Creates a future that runs the startup hooks in order of registration.
+    ///
+    /// The remaining hooks are not run if one of the hooks fails.
+    pub(super) fn startup(
+        &self,
+    ) -> impl Future<Item = (), Error = failure::Error> + Send + 'static {
+        stream::iter_ok(self.startup.clone()).for_each(|hook| hook())
+    }
+
+    /// Creates a future that runs the shutdown hooks in order of registration.
+    ///
+    /// The errors from the hooks are logged, and do not stop the remaining hooks.
+    pub(super) fn shutdown(&self) -> impl Future<Item = (), Error = ()> + Send + 'static {
+        stream::iter_ok(self.shutdown.clone()).for_each(|hook| {
+            hook().then(|result| {
+                if let Err(err) = result {
+                    log::error!("error in the shutdown hook: {}", err);
+                }
+                Ok(())
+            })
+        })
+    }
+}
+
+fn hook<F, R>(f: F) -> Hook
+where
+    F: Fn() -> R + Send + Sync + 'static,
+    R: IntoFuture<Item = ()>,
+    R::Future: Send + 'static,
+    R::Error: Into<failure::Error>,
+{
+    Arc::new(move || Box::new(f().into_future().map_err(Into::into)) as HookFuture)
+}
@@ -0,0 +1,250 @@
+use {
+    super::{
+        concurrency::{Concurrency, DefaultConcurrency},
+        config::{Error, Result},
+        hooks::Hooks,
+        App, AppInner, AppService,
+    },
+    futures01::Future,
+    http::{header::HOST, request::Parts},
+    std::{collections::HashMap, sync::Arc},
+};
+
+/// A set of `App`s dispatched by the hostname of the request (virtual hosting).
+///
+/// The hostname is taken from the authority of the request URI (e.g. the `:authority`
+/// pseudo header field in HTTP/2), or the `Host` header field.
+/// The pattern of hostname may start with a wildcard label such as `*.example.com`,
+/// which matches any of its subdomains. If several patterns match, the exact one
+/// is preferred, and then the longest wildcard one.
+///
+/// The requests that match no pattern are dispatched to the fallback `App`,
+/// which responds `404 Not Found` to any request unless specified.
+///
+/// ```
+/// # use tsukuyomi::app::{App, VirtualHosts};
+/// # fn main() -> tsukuyomi::app::Result<()> {
+/// # let api_app: App = App::build(|_| Ok(()))?;
+/// # let users_app: App = App::build(|_| Ok(()))?;
+/// # let site_app: App = App::build(|_| Ok(()))?;
+/// let hosts = VirtualHosts::new()
+///     .host("api.example.com", api_app)?
+///     .host("*.users.example.com", users_app)?
+///     .fallback(site_app);
+/// # drop(hosts);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct VirtualHosts<C: Concurrency = DefaultConcurrency> {
+    hosts: Arc<HashMap<String, Arc<AppInner<C>>>>,
+    fallback: Arc<AppInner<C>>,
+}
+
+impl<C: Concurrency> Clone for VirtualHosts<C> {
+    fn clone(&self) -> Self {
+        Self {
+            hosts: self.hosts.clone(),
+            fallback: self.fallback.clone(),
+        }
+    }
+}
+
+impl<C: Concurrency> Default for VirtualHosts<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Concurrency> From<App<C>> for VirtualHosts<C> {
+    fn from(app: App<C>) -> Self {
+        Self {
+            hosts: Arc::new(HashMap::new()),
+            fallback: app.inner,
+        }
+    }
+}
+
+impl<C: Concurrency> VirtualHosts<C> {
+    /// Creates an empty `VirtualHosts`.
+    pub fn new() -> Self {
+        Self {
+            hosts: Arc::new(HashMap::new()),
+            fallback: Arc::new(AppInner::empty()),
+        }
+    }
+
+    /// Registers an `App` which serves the requests to the specified hostname.
+    pub fn host(mut self, pattern: &str, app: App<C>) -> Result<Self> {
+        let pattern = pattern.to_ascii_lowercase();
+        let name = if pattern.starts_with("*.") {
+            &pattern[2..]
+        } else {
+            &pattern[..]
+        };
+        if name.is_empty()
+            || name
+                .split('.')
+                .any(|label| label.is_empty() || label.contains('*'))
+        {
+            return Err(Error::custom(failure::format_err!(
+                "invalid hostname pattern: {:?}",
+                pattern
+            )));
+        }
+        if self.hosts.contains_key(&pattern) {
+            return Err(Error::custom(failure::format_err!(
+                "the hostname pattern {:?} has already been registered",
+                pattern
+            )));
+        }
+        Arc::make_mut(&mut self.hosts).insert(pattern, app.inner);
+        Ok(self)
+    }
+
+    /// Sets the `App` which serves the requests that match no hostname pattern.
+    pub fn fallback(self, app: App<C>) -> Self {
+        Self {
+            fallback: app.inner,
+            ..self
+        }
+    }
+
+    /// Creates a new instance of `AppService` that dispatches the requests to the registered `App`s.
+    pub fn new_service(&self) -> AppService<C> {
+        AppService::with_hosts(self.clone())
+    }
+
+    /// Creates a future that runs the startup hooks of the registered `App`s.
+    pub(crate) fn startup(
+        &self,
+    ) -> impl Future<Item = (), Error = failure::Error> + Send + 'static {
+        self.hooks().startup()
+    }
+
+    /// Creates a future that runs the shutdown hooks of the registered `App`s.
+    pub(crate) fn shutdown(&self) -> impl Future<Item = (), Error = ()> + Send + 'static {
+        self.hooks().shutdown()
+    }
+
+    /// Collects the hooks of the registered `App`s, starting from the fallback one.
+    ///
+    /// The hooks of an `App` registered to several hostnames are collected only once.
+    fn hooks(&self) -> Hooks {
+        let mut hosts: Vec<_> = self.hosts.iter().collect();
+        hosts.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut apps = vec![&self.fallback];
+        for (_, app) in hosts {
+            if !apps.iter().any(|registered| Arc::ptr_eq(registered, app)) {
+                apps.push(app);
+            }
+        }
+
+        let mut hooks = Hooks::default();
+        for app in apps {
+            hooks.extend(&app.hooks);
+        }
+        hooks
+    }
+
+    pub(super) fn find(&self, request: &Parts) -> &Arc<AppInner<C>> {
+        if self.hosts.is_empty() {
+            return &self.fallback;
+        }
+        request_host(request)
+            .and_then(|host| self.lookup(&host))
+            .unwrap_or(&self.fallback)
+    }
+
+    fn lookup(&self, host: &str) -> Option<&Arc<AppInner<C>>> {
+        if let Some(app) = self.hosts.get(host) {
+            return Some(app);
+        }
+        host.match_indices('.')
+            .find_map(|(pos, _)| self.hosts.get(&format!("*{}", &host[pos..])))
+    }
+}
+
+/// Extracts the hostname from the request, without the port number.
+fn request_host(request: &Parts) -> Option<String> {
+    let authority = match request.uri.authority_part() {
+        Some(authority) => authority.host().to_owned(),
+        None => {
+            let host = request.headers.get(HOST)?.to_str().ok()?;
+            let authority: http::uri::Authority = host.parse().ok()?;
+            authority.host().to_owned()
+        }
+    };
+    Some(authority.trim_end_matches('.').to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, http::Request};
+
+    fn parts(uri: &str, host: Option<&str>) -> Parts {
+        let mut request = Request::get(uri);
+        if let Some(host) = host {
+            request.header(HOST, host);
+        }
+        request.body(()).unwrap().into_parts().0
+    }
+
+    #[test]
+    fn request_host_from_header_or_uri() {
+        assert_eq!(
+            request_host(&parts("/", Some("Example.com:8080"))),
+            Some("example.com".into())
+        );
+        assert_eq!(
+            request_host(&parts("http://api.example.com/", Some("example.com"))),
+            Some("api.example.com".into())
+        );
+        assert_eq!(request_host(&parts("/", None)), None);
+    }
+
+    #[test]
+    fn lookup_hosts() -> Result<()> {
+        let hosts: VirtualHosts = VirtualHosts::new()
+            .host("example.com", App::build(|_| Ok(()))?)?
+            .host("*.example.com", App::build(|_| Ok(()))?)?
+            .host("*.users.example.com", App::build(|_| Ok(()))?)?;
+        let lookup = |host: &str| {
+            hosts.lookup(host).map(|app| {
+                hosts
+                    .hosts
+                    .iter()
+                    .find(|(_, a)| Arc::ptr_eq(a, app))
+                    .unwrap()
+                    .0
+                    .clone()
+            })
+        };
+
+        assert_eq!(lookup("example.com"), Some("example.com".into()));
+        assert_eq!(lookup("www.example.com"), Some("*.example.com".into()));
+        assert_eq!(lookup("a.b.example.com"), Some("*.example.com".into()));
+        assert_eq!(
+            lookup("alice.users.example.com"),
+            Some("*.users.example.com".into())
+        );
+        assert_eq!(lookup("example.org"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        let app = || App::build(|_| Ok(())).unwrap();
+        let hosts = || VirtualHosts::<DefaultConcurrency>::new();
+        assert!(hosts().host("", app()).is_err());
+        assert!(hosts().host("*", app()).is_err());
+        assert!(hosts().host("api.*.com", app()).is_err());
+        assert!(hosts().host("api..com", app()).is_err());
+        assert!(hosts()
+            .host("api.example.com", app())
+            .unwrap()
+            .host("API.example.com", app())
+            .is_err());
+    }
+}
@@ -0,0 +1,121 @@
+use {
+    super::{normalize::replace_path, RemoteAddr, MOUNT_PREFIX, REMOTE_ADDR},
+    crate::{
+        error::Error,
+        future::{Async, Poll, TryFuture},
+        handler::{metadata::Metadata, Handler},
+        input::{body::RequestBody, localmap::LocalData, Input},
+        output::ResponseBody,
+        uri::Uri,
+    },
+    futures01::Future,
+    http::{Request, Response},
+    izanami::service::Service,
+};
+
+/// The information passed to the mounted `App` via the request extensions.
+#[derive(Debug)]
+pub(super) struct Mounted {
+    pub(super) prefix: String,
+    pub(super) remote_addr: Option<RemoteAddr>,
+}
+
+/// A `Handler` that dispatches the requests to a mounted `Service`.
+#[doc(hidden)]
+#[allow(missing_debug_implementations)]
+pub struct MountHandler<S> {
+    service: S,
+    metadata: Metadata,
+}
+
+impl<S> MountHandler<S> {
+    pub(super) fn new(service: S, uri: Uri) -> Self {
+        Self {
+            service,
+            metadata: Metadata::new(uri),
+        }
+    }
+}
+
+impl<S, B> Handler for MountHandler<S>
+where
+    S: Service<Request<RequestBody>, Response = Response<B>> + Clone,
+    S::Error: Into<Error>,
+    B: Into<ResponseBody>,
+{
+    type Output = Response<ResponseBody>;
+    type Error = Error;
+    type Handle = MountHandle<S, S::Future>;
+
+    fn handle(&self) -> Self::Handle {
+        MountHandle {
+            state: MountHandleState::Ready(self.service.clone()),
+        }
+    }
+
+    fn metadata(&self) -> Metadata {
+        self.metadata.clone()
+    }
+}
+
+#[doc(hidden)]
+#[allow(missing_debug_implementations)]
+pub struct MountHandle<S, Fut> {
+    state: MountHandleState<S, Fut>,
+}
+
+enum MountHandleState<S, Fut> {
+    Ready(S),
+    InFlight(Fut),
+}
+
+impl<S, B> TryFuture for MountHandle<S, S::Future>
+where
+    S: Service<Request<RequestBody>, Response = Response<B>>,
+    S::Error: Into<Error>,
+    B: Into<ResponseBody>,
+{
+    type Ok = Response<ResponseBody>;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        loop {
+            self.state = match self.state {
+                MountHandleState::Ready(ref mut service) => {
+                    futures01::try_ready!(service.poll_ready().map_err(Into::into));
+                    MountHandleState::InFlight(service.call(mounted_request(input)))
+                }
+                MountHandleState::InFlight(ref mut in_flight) => {
+                    let response = futures01::try_ready!(in_flight.poll().map_err(Into::into));
+                    return Ok(Async::Ready(response.map(Into::into)));
+                }
+            };
+        }
+    }
+}
+
+/// Creates the request passed to the mounted `Service`, with the prefix stripped from the path.
+fn mounted_request(input: &mut Input<'_>) -> Request<RequestBody> {
+    let path = input.request.uri().path();
+    let (prefix, stripped) = match input.params.as_ref().and_then(|params| params.catch_all()) {
+        Some(rest) => (&path[..path.len() - rest.len() - 1], format!("/{}", rest)),
+        None => (path.trim_end_matches('/'), "/".to_owned()),
+    };
+    let prefix = match input.locals.get(&MOUNT_PREFIX) {
+        Some(outer) => format!("{}{}", outer, prefix),
+        None => prefix.to_owned(),
+    };
+    let uri = replace_path(input.request.uri(), &stripped).expect("should be a valid URI");
+
+    let body = RequestBody::take_from(input.locals).unwrap_or_else(|| RequestBody::new(""));
+    let mut request = Request::new(body);
+    *request.method_mut() = input.request.method().clone();
+    *request.uri_mut() = uri;
+    *request.version_mut() = input.request.version();
+    *request.headers_mut() = input.request.headers().clone();
+    request.extensions_mut().insert(Mounted {
+        prefix,
+        remote_addr: input.locals.get(&REMOTE_ADDR).cloned(),
+    });
+    request
+}
@@ -0,0 +1,206 @@
+use {
+    crate::output::ResponseBody,
+    http::{header::LOCATION, Method, Response, StatusCode},
+    std::borrow::Cow,
+};
+
+/// The policy on the trailing slash of the request path.
+///
+/// When no route matches the request path, the path with the trailing slash
+/// added or removed is looked up, and then the policy of the scope where the
+/// found route belongs is applied.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TrailingSlash {
+    /// The paths with and without the trailing slash are distinct (default).
+    Strict,
+
+    /// Redirects to the path of the registered route.
+    ///
+    /// The status code of the redirection is `301 Moved Permanently` for `GET`
+    /// and `HEAD` requests, and `308 Permanent Redirect` for the others so that
+    /// the clients do not change the method and the body.
+    Redirect,
+
+    /// Handles the request as if the path of the registered route was requested.
+    ///
+    /// The path of the request URI is replaced with the one of the route.
+    Transparent,
+}
+
+impl Default for TrailingSlash {
+    fn default() -> Self {
+        TrailingSlash::Strict
+    }
+}
+
+/// The configuration of the normalization applied to the request path before routing.
+///
+/// The request URI is replaced with the normalized one, so the handlers
+/// observe the normalized path.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct NormalizePath {
+    merge_slashes: bool,
+    resolve_dot_segments: bool,
+}
+
+impl NormalizePath {
+    /// Creates a `NormalizePath` that does not modify the path.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether to collapse the consecutive slashes into a single slash, e.g. `/a//b` to `/a/b`.
+    pub fn merge_slashes(self, enabled: bool) -> Self {
+        Self {
+            merge_slashes: enabled,
+            ..self
+        }
+    }
+
+    /// Sets whether to resolve the dot segments (`.` and `..`) in the path, e.g. `/a/./b/../c` to `/a/c`.
+    pub fn resolve_dot_segments(self, enabled: bool) -> Self {
+        Self {
+            resolve_dot_segments: enabled,
+            ..self
+        }
+    }
+
+    fn normalize<'a>(&self, path: &'a str) -> Cow<'a, str> {
+        if !path.starts_with('/') {
+            return Cow::Borrowed(path);
+        }
+
+        let needs_merge = self.merge_slashes && path.contains("//");
+        let needs_resolve = self.resolve_dot_segments
+            && path[1..]
+                .split('/')
+                .any(|segment| segment == "." || segment == "..");
+        if !needs_merge && !needs_resolve {
+            return Cow::Borrowed(path);
+        }
+
+        let mut segments: Vec<&str> = vec![];
+        let mut has_trailing_slash = false;
+        for segment in path[1..].split('/') {
+            has_trailing_slash = false;
+            match segment {
+                "" if self.merge_slashes => has_trailing_slash = true,
+                "." if self.resolve_dot_segments => has_trailing_slash = true,
+                ".." if self.resolve_dot_segments => {
+                    segments.pop();
+                    has_trailing_slash = true;
+                }
+                segment => segments.push(segment),
+            }
+        }
+
+        let mut normalized = String::with_capacity(path.len());
+        normalized.push('/');
+        normalized.push_str(&segments.join("/"));
+        if has_trailing_slash && !segments.is_empty() {
+            normalized.push('/');
+        }
+        Cow::Owned(normalized)
+    }
+
+    /// Returns the normalized URI, or `None` if the path is not modified.
+    pub(super) fn normalize_uri(&self, uri: &http::Uri) -> Option<http::Uri> {
+        match self.normalize(uri.path()) {
+            Cow::Borrowed(..) => None,
+            Cow::Owned(path) => replace_path(uri, &path),
+        }
+    }
+}
+
+/// Creates a URI with the path replaced with `path`, preserving the query.
+pub(super) fn replace_path(uri: &http::Uri, path: &str) -> Option<http::Uri> {
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_owned(),
+    };
+    let mut parts = http::uri::Parts::from(uri.clone());
+    parts.path_and_query = Some(path_and_query.parse().ok()?);
+    http::Uri::from_parts(parts).ok()
+}
+
+/// Returns the request path with the trailing slash added or removed.
+pub(super) fn toggle_trailing_slash(path: &str) -> Option<String> {
+    match path {
+        "/" | "*" => None,
+        path if path.ends_with('/') => Some(path[..path.len() - 1].to_owned()),
+        path => Some(format!("{}/", path)),
+    }
+}
+
+/// Creates the response that redirects the client to the path of the registered route.
+pub(super) fn redirect_trailing_slash(
+    method: &Method,
+    path: &str,
+    query: Option<&str>,
+) -> Response<ResponseBody> {
+    let mut response = Response::new(ResponseBody::empty());
+    *response.status_mut() = if *method == Method::GET || *method == Method::HEAD {
+        StatusCode::MOVED_PERMANENTLY
+    } else {
+        StatusCode::PERMANENT_REDIRECT
+    };
+    let location = match query {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_owned(),
+    };
+    if let Ok(location) = location.parse() {
+        response.headers_mut().insert(LOCATION, location);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_disabled() {
+        let normalize = NormalizePath::new();
+        assert_eq!(normalize.normalize("/a//b/./c/../"), "/a//b/./c/../");
+    }
+
+    #[test]
+    fn merge_slashes() {
+        let normalize = NormalizePath::new().merge_slashes(true);
+        assert_eq!(normalize.normalize("/a//b///c"), "/a/b/c");
+        assert_eq!(normalize.normalize("//a/b//"), "/a/b/");
+        assert_eq!(normalize.normalize("//"), "/");
+        assert_eq!(normalize.normalize("/a/./b"), "/a/./b");
+    }
+
+    #[test]
+    fn resolve_dot_segments() {
+        let normalize = NormalizePath::new().resolve_dot_segments(true);
+        assert_eq!(normalize.normalize("/a/./b/../c"), "/a/c");
+        assert_eq!(normalize.normalize("/a/b/.."), "/a/");
+        assert_eq!(normalize.normalize("/../../a"), "/a");
+        assert_eq!(normalize.normalize("/a/.."), "/");
+        assert_eq!(normalize.normalize("/a/..b/.c"), "/a/..b/.c");
+        assert_eq!(normalize.normalize("/a//./b"), "/a//b");
+    }
+
+    #[test]
+    fn normalize_uri_preserves_query() {
+        let normalize = NormalizePath::new()
+            .merge_slashes(true)
+            .resolve_dot_segments(true);
+        let uri: http::Uri = "/a//b/../c?x=1".parse().unwrap();
+        assert_eq!(
+            normalize.normalize_uri(&uri).map(|uri| uri.to_string()),
+            Some("/a/c?x=1".into())
+        );
+        assert!(normalize.normalize_uri(&"/a/c".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn toggle() {
+        assert_eq!(toggle_trailing_slash("/a"), Some("/a/".into()));
+        assert_eq!(toggle_trailing_slash("/a/"), Some("/a".into()));
+        assert_eq!(toggle_trailing_slash("/"), None);
+    }
+}
@@ -51,6 +51,13 @@ where
 
 /// A macro for generating the code that creates a [`Path`] from the provided tokens.
 ///
+/// A parameter may have a constraint, as a regular expression (`/:id<\d+>`)
+/// or as a type name (`/:id:u64`). The constraints are checked during the
+/// route recognition, and the request falls through to the next candidate
+/// route if its segment does not satisfy the constraint. The static segments
+/// are preferred over the constrained parameters, and then the unconstrained
+/// ones and the catch-all parameter.
+///
 /// [`Path`]: ./app/config/route/struct.Path.html
 #[macro_export]
 macro_rules! path {
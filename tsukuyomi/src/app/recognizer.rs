@@ -1,8 +1,10 @@
 //! The implementation of route recognizer.
 
 use {
+    crate::uri::{parse_param_segment, ParamConstraint},
     failure::Error,
     indexmap::{indexset, IndexMap, IndexSet},
+    regex::Regex,
     std::{
         cmp::{self, Ordering},
         fmt, mem,
@@ -104,15 +106,115 @@ impl<T> Recognizer<T> {
     pub fn get(&self, index: usize) -> Option<&T> {
         Some(self.inner.get_index(index)?.1)
     }
+
+    /// Returns an iterator over the registered values, in the order of insertion.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.inner.values()
+    }
+}
+
+/// The constraint on the value of a parameter, checked during recognition.
+#[derive(Clone)]
+pub(super) struct Constraint {
+    source: String,
+    kind: ConstraintKind,
+}
+
+#[derive(Clone)]
+enum ConstraintKind {
+    Regex(Regex),
+    Type(fn(&str) -> bool),
+}
+
+impl Constraint {
+    pub(super) fn new(constraint: ParamConstraint<'_>) -> Result<Self, Error> {
+        let (source, kind) = match constraint {
+            ParamConstraint::Regex(pattern) => {
+                let regex = Regex::new(&format!("^(?:{})$", pattern))?;
+                (format!("<{}>", pattern), ConstraintKind::Regex(regex))
+            }
+            ParamConstraint::Type(ty) => {
+                let is_valid: fn(&str) -> bool = match ty {
+                    "u8" => |s| s.parse::<u8>().is_ok(),
+                    "u16" => |s| s.parse::<u16>().is_ok(),
+                    "u32" => |s| s.parse::<u32>().is_ok(),
+                    "u64" => |s| s.parse::<u64>().is_ok(),
+                    "usize" => |s| s.parse::<usize>().is_ok(),
+                    "i8" => |s| s.parse::<i8>().is_ok(),
+                    "i16" => |s| s.parse::<i16>().is_ok(),
+                    "i32" => |s| s.parse::<i32>().is_ok(),
+                    "i64" => |s| s.parse::<i64>().is_ok(),
+                    "isize" => |s| s.parse::<isize>().is_ok(),
+                    "uuid" => |s| uuid::Uuid::parse_str(s).is_ok(),
+                    ty => failure::bail!("unsupported type constraint: '{}'", ty),
+                };
+                (format!(":{}", ty), ConstraintKind::Type(is_valid))
+            }
+        };
+        Ok(Self { source, kind })
+    }
+
+    /// Returns whether the (percent-encoded) path segment satisfies this constraint.
+    pub(super) fn is_match(&self, segment: &[u8]) -> bool {
+        let segment = match std::str::from_utf8(segment) {
+            Ok(segment) => segment,
+            Err(..) => return false,
+        };
+        match self.kind {
+            ConstraintKind::Regex(ref regex) => regex.is_match(segment),
+            ConstraintKind::Type(is_valid) => is_valid(segment),
+        }
+    }
+}
+
+impl PartialEq for Constraint {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+impl fmt::Debug for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
 }
 
 #[derive(Clone, PartialEq)]
 enum NodeKind {
     Static(Vec<u8>),
-    Param,
+    Param(Option<Constraint>),
     CatchAll,
 }
 
+impl NodeKind {
+    /// Parses the wildcard segment (`:name`, `:name<regex>`, `:name:type` or `*name`).
+    fn wildcard(segment: &[u8]) -> Result<Self, Error> {
+        let segment = std::str::from_utf8(segment)?;
+        let param = parse_param_segment(segment)?;
+        if param.catch_all {
+            return Ok(NodeKind::CatchAll);
+        }
+        Ok(NodeKind::Param(match param.constraint {
+            Some(constraint) => Some(Constraint::new(constraint)?),
+            None => None,
+        }))
+    }
+
+    /// The priority of the node among its siblings used in recognition.
+    ///
+    /// The static segments are preferred over the constrained parameters,
+    /// and then the unconstrained parameters and the catch-all parameter.
+    fn priority(&self) -> u8 {
+        match self {
+            NodeKind::Static(..) => 0,
+            NodeKind::Param(Some(..)) => 1,
+            NodeKind::Param(None) => 2,
+            NodeKind::CatchAll => 3,
+        }
+    }
+}
+
 #[cfg_attr(tarpaulin, skip)]
 impl fmt::Debug for NodeKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -121,7 +223,7 @@ impl fmt::Debug for NodeKind {
                 .debug_tuple("Static")
                 .field(&String::from_utf8_lossy(s))
                 .finish(),
-            NodeKind::Param => f.debug_tuple("Param").finish(),
+            NodeKind::Param(ref constraint) => f.debug_tuple("Param").field(constraint).finish(),
             NodeKind::CatchAll => f.debug_tuple("CatchAll").finish(),
         }
     }
@@ -183,24 +285,34 @@ impl<'a> InsertContext<'a> {
 
             // Insert the remaing path into the set of children.
             match self.path.get(offset) {
-                Some(b':') if n.children.is_empty() => {
-                    self.insert_child(n, offset)?;
-                    return Ok(());
-                }
-
-                Some(b'*') if n.children.is_empty() => failure::bail!("'catch-all' conflict"),
-
                 Some(b':') | Some(b'*') => {
-                    if n.children.iter().any(|ch| match ch.kind {
-                        NodeKind::Static(..) => true,
-                        _ => false,
-                    }) {
-                        failure::bail!("A static node has already inserted at wildcard position.");
-                    }
+                    let end = find_wildcard_end(self.path, offset)?;
+                    let kind = NodeKind::wildcard(&self.path[offset..end])?;
+                    let ch_pos = n.children.iter().position(|ch| ch.kind == kind);
+                    let pos = match ch_pos {
+                        Some(pos) => pos,
+                        None => {
+                            if kind == NodeKind::CatchAll && n.leaf.is_some() {
+                                failure::bail!("'catch-all' conflict");
+                            }
+                            // the catch-all parameter cannot coexist with the unconstrained
+                            // parameters, but can be the fallback of the constrained ones.
+                            if n.children.iter().any(|ch| match (&ch.kind, &kind) {
+                                (NodeKind::CatchAll, NodeKind::Param(None))
+                                | (NodeKind::CatchAll, NodeKind::CatchAll)
+                                | (NodeKind::Param(None), NodeKind::CatchAll) => true,
+                                _ => false,
+                            }) {
+                                failure::bail!("A wildcard node has already inserted.");
+                            }
+                            n.candidates.insert(self.index);
+                            self.insert_child(n, offset)?;
+                            return Ok(());
+                        }
+                    };
 
                     n.candidates.insert(self.index);
-                    n = &mut { n }.children[0];
-                    let end = find_wildcard_end(self.path, offset)?;
+                    n = &mut { n }.children[pos];
                     if end == self.path.len() {
                         break 'walk;
                     }
@@ -209,20 +321,10 @@ impl<'a> InsertContext<'a> {
 
                 Some(&c) => {
                     // Check if a child with the next path byte exists
-                    let mut ch_pos = None;
-                    for (i, ch) in n.children.iter().enumerate() {
-                        match ch.kind {
-                            NodeKind::Static(ref s) => {
-                                if s[0] == c {
-                                    ch_pos = Some(i);
-                                    break;
-                                }
-                            }
-                            NodeKind::Param | NodeKind::CatchAll => {
-                                failure::bail!("A wildcard node has already inserted.")
-                            }
-                        }
-                    }
+                    let ch_pos = n.children.iter().position(|ch| match ch.kind {
+                        NodeKind::Static(ref s) => s[0] == c,
+                        NodeKind::Param(..) | NodeKind::CatchAll => false,
+                    });
                     if let Some(pos) = ch_pos {
                         n.candidates.insert(self.index);
                         n = &mut { n }.children[pos];
@@ -233,7 +335,7 @@ impl<'a> InsertContext<'a> {
                     let pos = find_wildcard_begin(self.path, offset);
                     let mut ch = self.new_node(NodeKind::Static(self.path[offset..pos].to_owned()));
                     self.insert_child(&mut ch, pos)?;
-                    n.children.push(ch);
+                    push_child(n, ch);
                     n.candidates.insert(self.index);
 
                     return Ok(());
@@ -258,12 +360,8 @@ impl<'a> InsertContext<'a> {
         while pos < path.len() {
             // Insert a wildcard node
             let i = find_wildcard_end(path, pos)?;
-            n.children.push(self.new_node(match path[pos] {
-                b':' => NodeKind::Param,
-                b'*' => NodeKind::CatchAll,
-                c => failure::bail!("unexpected parameter type: '{}'", c),
-            }));
-            n = { n }.children.iter_mut().last().unwrap();
+            let ch_pos = push_child(n, self.new_node(NodeKind::wildcard(&path[pos..i])?));
+            n = &mut { n }.children[ch_pos];
             pos = i;
 
             // Insert a normal node
@@ -327,58 +425,93 @@ struct RecognizeContext<'a> {
 }
 
 impl<'a> RecognizeContext<'a> {
-    fn recognize<'t>(&mut self, mut n: &'t Node) -> Result<usize, RecognizeError<'t>> {
-        let mut offset = 0;
-        loop {
-            match n.kind {
-                NodeKind::Static(ref s) => match compare_length(&s[..], &self.path[offset..]) {
-                    Ordering::Less if self.path[offset..].starts_with(&s[..]) => offset += s.len(),
-                    Ordering::Greater if s[..].starts_with(&self.path[offset..]) => {
-                        offset = self.path.len()
-                    }
-                    Ordering::Equal if s[..] == self.path[offset..] => {
-                        offset = self.path.len();
-                        if let Some(i) = n.leaf {
-                            return Ok(i);
-                        }
+    fn recognize<'t>(
+        &mut self,
+        n: &'t Node,
+        mut offset: usize,
+    ) -> Result<usize, RecognizeError<'t>> {
+        match n.kind {
+            NodeKind::Static(ref s) => match compare_length(&s[..], &self.path[offset..]) {
+                Ordering::Less if self.path[offset..].starts_with(&s[..]) => offset += s.len(),
+                Ordering::Greater if s[..].starts_with(&self.path[offset..]) => {
+                    return Err(RecognizeError::PartiallyMatched(&n.candidates));
+                }
+                Ordering::Equal if s[..] == self.path[offset..] => {
+                    offset = self.path.len();
+                    if let Some(i) = n.leaf {
+                        return Ok(i);
                     }
-                    _ => return Err(RecognizeError::NotMatched),
-                },
-                NodeKind::Param => {
-                    let span = self.path[offset..]
-                        .iter()
-                        .position(|&b| b == b'/')
-                        .unwrap_or(self.path.len() - offset);
-                    self.captures
-                        .get_or_insert_with(Default::default)
-                        .params
-                        .push((offset, offset + span));
-                    offset += span;
-
-                    if offset >= self.path.len() {
-                        return n
-                            .leaf //
-                            .ok_or_else(|| RecognizeError::PartiallyMatched(&n.candidates));
+                }
+                _ => return Err(RecognizeError::NotMatched),
+            },
+            NodeKind::Param(ref constraint) => {
+                let span = self.path[offset..]
+                    .iter()
+                    .position(|&b| b == b'/')
+                    .unwrap_or(self.path.len() - offset);
+                if let Some(ref constraint) = constraint {
+                    if !constraint.is_match(&self.path[offset..offset + span]) {
+                        return Err(RecognizeError::NotMatched);
                     }
                 }
-                NodeKind::CatchAll => {
-                    self.captures.get_or_insert_with(Default::default).wildcard =
-                        Some((offset, self.path.len()));
+                self.captures
+                    .get_or_insert_with(Default::default)
+                    .params
+                    .push((offset, offset + span));
+                offset += span;
+
+                if offset >= self.path.len() {
                     return n
                         .leaf //
                         .ok_or_else(|| RecognizeError::PartiallyMatched(&n.candidates));
                 }
             }
+            NodeKind::CatchAll => {
+                self.captures.get_or_insert_with(Default::default).wildcard =
+                    Some((offset, self.path.len()));
+                return n
+                    .leaf //
+                    .ok_or_else(|| RecognizeError::PartiallyMatched(&n.candidates));
+            }
+        }
+
+        // Try the children in order of priority, and fall through to the next
+        // candidate if the path does not match to the subtree.
+        let mut error = None;
+        for ch in &n.children {
+            if let NodeKind::Static(ref s) = ch.kind {
+                if self.path.get(offset) != Some(&s[0]) {
+                    continue;
+                }
+            }
 
-            n = n
-                .children
-                .iter()
-                .find(|ch| match ch.kind {
-                    NodeKind::Static(ref s) => self.path.get(offset).map_or(false, |&c| s[0] == c),
-                    NodeKind::Param | NodeKind::CatchAll => true,
-                }) //
-                .ok_or_else(|| RecognizeError::PartiallyMatched(&n.candidates))?;
+            let saved = self
+                .captures
+                .as_ref()
+                .map(|captures| (captures.params.len(), captures.wildcard));
+            match self.recognize(ch, offset) {
+                Ok(i) => return Ok(i),
+                Err(err) => {
+                    match saved {
+                        Some((len, wildcard)) => {
+                            let captures = self.captures.as_mut().expect("should be Some");
+                            captures.params.truncate(len);
+                            captures.wildcard = wildcard;
+                        }
+                        None => *self.captures = None,
+                    }
+                    // prefer the error from the most deeply matched candidate.
+                    if error
+                        .as_ref()
+                        .map_or(true, |e| *e == RecognizeError::NotMatched)
+                    {
+                        error = Some(err);
+                    }
+                }
+            }
         }
+
+        Err(error.unwrap_or_else(|| RecognizeError::PartiallyMatched(&n.candidates)))
     }
 
     fn visit_tree<'t>(&mut self, tree: &'t Tree) -> Result<usize, RecognizeError<'t>> {
@@ -386,7 +519,7 @@ impl<'a> RecognizeContext<'a> {
             .root
             .as_ref()
             .ok_or_else(|| RecognizeError::NotMatched)?;
-        self.recognize(root)
+        self.recognize(root, 0)
     }
 }
 
@@ -402,6 +535,18 @@ fn compare_length<T>(s1: &[T], s2: &[T]) -> Ordering {
     s1.len().cmp(&s2.len())
 }
 
+/// Inserts a child node at the position according to its priority, and returns the position.
+fn push_child(n: &mut Node, ch: Node) -> usize {
+    let priority = ch.kind.priority();
+    let pos = n
+        .children
+        .iter()
+        .position(|c| c.kind.priority() > priority)
+        .unwrap_or_else(|| n.children.len());
+    n.children.insert(pos, ch);
+    pos
+}
+
 fn find_wildcard_begin(path: &[u8], offset: usize) -> usize {
     path.iter()
         .skip(offset)
@@ -414,22 +559,17 @@ fn find_wildcard_end(path: &[u8], offset: usize) -> Result<usize, Error> {
     if offset > 0 && path[offset - 1] != b'/' {
         failure::bail!("a wildcard character (':' or '*') must be located at the next of slash");
     }
-    let mut end = 1;
-    while offset + end < path.len() && path[offset + end] != b'/' {
-        match path[offset + end] {
-            b':' | b'*' => {
-                failure::bail!("wrong wildcard character (':' or '*') in a path segment")
-            }
-            _ => end += 1,
-        }
-    }
-    if end == 1 {
+    let end = path[offset..]
+        .iter()
+        .position(|&b| b == b'/')
+        .map_or_else(|| path.len(), |i| i + offset);
+    if end == offset + 1 {
         failure::bail!("empty wildcard name");
     }
-    if path[offset] == b'*' && offset + end < path.len() {
+    if path[offset] == b'*' && end < path.len() {
         failure::bail!("a 'catch-all' param must be located at the end of path");
     }
-    Ok(offset + end)
+    Ok(end)
 }
 
 #[cfg(test)]
@@ -587,6 +727,102 @@ mod tests {
         );
     }
 
+    #[test]
+    fn case12_static_and_param() {
+        let mut recognizer = Recognizer::default();
+        recognizer.insert("/users/:id", 0).unwrap();
+        recognizer.insert("/users/me", 1).unwrap();
+        recognizer.insert("/users/:id/posts", 2).unwrap();
+
+        let mut captures = None;
+        assert_eq!(recognizer.recognize("/users/me", &mut captures), Ok(&1));
+        assert_eq!(captures, None);
+
+        let mut captures = None;
+        assert_eq!(recognizer.recognize("/users/mei", &mut captures), Ok(&0));
+        assert_eq!(
+            captures,
+            Some(Captures {
+                params: vec![(7, 10)],
+                wildcard: None,
+            })
+        );
+
+        // falls through to the parameter if the static segment partially matches.
+        let mut captures = None;
+        assert_eq!(
+            recognizer.recognize("/users/me/posts", &mut captures),
+            Ok(&2)
+        );
+        assert_eq!(
+            captures,
+            Some(Captures {
+                params: vec![(7, 9)],
+                wildcard: None,
+            })
+        );
+    }
+
+    #[test]
+    fn case13_constrained_params() {
+        let mut recognizer = Recognizer::default();
+        recognizer.insert("/posts/:slug", 0).unwrap();
+        recognizer.insert(r"/posts/:id<\d+>", 1).unwrap();
+        recognizer.insert("/posts/:id:uuid", 2).unwrap();
+
+        assert_eq!(recognizer.recognize("/posts/42", &mut None), Ok(&1));
+        assert_eq!(
+            recognizer.recognize("/posts/936da01f-9abd-4d9d-80c7-02af85c822a8", &mut None),
+            Ok(&2)
+        );
+        assert_eq!(recognizer.recognize("/posts/hello", &mut None), Ok(&0));
+        assert_eq!(recognizer.recognize("/posts/42a", &mut None), Ok(&0));
+    }
+
+    #[test]
+    fn case14_constraint_fallthrough() {
+        let mut recognizer = Recognizer::default();
+        recognizer.insert("/files/:id:u32/raw", 0).unwrap();
+        recognizer.insert("/files/*path", 1).unwrap();
+
+        let mut captures = None;
+        assert_eq!(recognizer.recognize("/files/10/raw", &mut captures), Ok(&0));
+        assert_eq!(
+            captures,
+            Some(Captures {
+                params: vec![(7, 9)],
+                wildcard: None,
+            })
+        );
+
+        // the captured parameter is discarded when falling through.
+        let mut captures = None;
+        assert_eq!(
+            recognizer.recognize("/files/10/readme.txt", &mut captures),
+            Ok(&1)
+        );
+        assert_eq!(
+            captures,
+            Some(Captures {
+                params: vec![],
+                wildcard: Some((7, 20)),
+            })
+        );
+
+        assert_eq!(recognizer.recognize("/files/-1/raw", &mut None), Ok(&1));
+    }
+
+    #[test]
+    fn case15_constraint_not_matched() {
+        let mut recognizer = Recognizer::default();
+        recognizer.insert(r"/posts/:id<\d+>", 0).unwrap();
+
+        assert_eq!(
+            recognizer.recognize("/posts/hello", &mut None),
+            Err(RecognizeError::NotMatched)
+        );
+    }
+
     #[test]
     fn case10_asterisk() {
         let mut recognizer = Recognizer::default();
@@ -697,7 +933,7 @@ mod tests_tree {
             leaf: None,
             candidates: Candidates(indexset![0]),
             children: vec![Node {
-                kind: NodeKind::Param, // ":id"
+                kind: NodeKind::Param(None), // ":id"
                 leaf: Some(0),
                 candidates: Candidates(indexset![0]),
                 children: vec![],
@@ -723,7 +959,7 @@ mod tests_tree {
                 leaf: None,
                 candidates: Candidates(indexset![1, 2, 3, 4]),
                 children: vec![Node {
-                    kind: NodeKind::Param, // ":name"
+                    kind: NodeKind::Param(None), // ":name"
                     leaf: Some(2),
                     candidates: Candidates(indexset![1, 2, 3, 4]),
                     children: vec![Node {
@@ -731,7 +967,7 @@ mod tests_tree {
                         leaf: Some(1),
                         candidates: Candidates(indexset![1, 3, 4]),
                         children: vec![Node {
-                            kind: NodeKind::Param, // ":id"
+                            kind: NodeKind::Param(None), // ":id"
                             leaf: Some(4),
                             candidates: Candidates(indexset![3, 4]),
                             children: vec![Node {
@@ -784,12 +1020,29 @@ mod tests_tree {
         }
     );
 
-    #[test]
-    fn failcase1_conflict_static_and_param() {
-        let mut recognizer = Recognizer::default();
-        assert!(recognizer.insert("/foo", ()).is_ok());
-        assert!(recognizer.insert("/:id", ()).is_err());
-    }
+    t!(
+        static_and_param_case1,
+        ["/users/:id", "/users/me"],
+        Node {
+            kind: NodeKind::Static("/users/".into()),
+            leaf: None,
+            candidates: Candidates(indexset![0, 1]),
+            children: vec![
+                Node {
+                    kind: NodeKind::Static("me".into()),
+                    leaf: Some(1),
+                    candidates: Candidates(indexset![1]),
+                    children: vec![],
+                },
+                Node {
+                    kind: NodeKind::Param(None), // ":id"
+                    leaf: Some(0),
+                    candidates: Candidates(indexset![0]),
+                    children: vec![],
+                },
+            ],
+        }
+    );
 
     #[test]
     fn failcase2_conflict_catch_all() {
@@ -798,13 +1051,6 @@ mod tests_tree {
         assert!(recognizer.insert("/foo/*path", ()).is_err());
     }
 
-    #[test]
-    fn failcase3_conflict_param_static() {
-        let mut recognizer = Recognizer::default();
-        assert!(recognizer.insert("/:id", ()).is_ok());
-        assert!(recognizer.insert("/foo", ()).is_err());
-    }
-
     #[test]
     fn failcase4_conflict_catch_all_2() {
         let mut recognizer = Recognizer::default();
@@ -833,6 +1079,25 @@ mod tests_tree {
         assert!(recognizer.insert("/:id", ()).is_err());
     }
 
+    #[test]
+    fn catch_all_as_fallback_of_constrained_param() {
+        let mut recognizer = Recognizer::default();
+        assert!(recognizer.insert(r"/:id<\d+>", ()).is_ok());
+        assert!(recognizer.insert("/*path", ()).is_ok());
+        assert!(recognizer.insert("/:name", ()).is_err());
+
+        let mut recognizer = Recognizer::default();
+        assert!(recognizer.insert("/*path", ()).is_ok());
+        assert!(recognizer.insert(r"/:id<\d+>", ()).is_ok());
+    }
+
+    #[test]
+    fn failcase10_invalid_constraint() {
+        let mut recognizer = Recognizer::default();
+        assert!(recognizer.insert(r"/:id<[0-9>", ()).is_err());
+        assert!(recognizer.insert("/:id:unknown", ()).is_err());
+    }
+
     #[test]
     fn failcase8_conflict_entire_path() {
         let mut recognizer = Recognizer::default();
@@ -0,0 +1,299 @@
+use {
+    super::recognizer::Constraint,
+    crate::{
+        error::HttpError,
+        handler::metadata::AllowedMethods,
+        uri::{parse_param_segment, Uri},
+    },
+    http::StatusCode,
+    std::{collections::HashMap, fmt, sync::Arc},
+    url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET},
+};
+
+/// An error that occurs during generating a URL from a named route.
+#[derive(Debug, failure::Fail)]
+pub enum UrlForError {
+    /// The route with the specified name is not registered.
+    #[fail(display = "unknown route name: {:?}", _0)]
+    UnknownName(String),
+
+    /// The value of a parameter in the route is not provided.
+    #[fail(display = "missing parameter: {:?}", _0)]
+    MissingParam(String),
+
+    /// The value of a parameter does not satisfy the constraint of the parameter.
+    #[fail(display = "invalid parameter: {:?}", _0)]
+    InvalidParam(String),
+}
+
+impl HttpError for UrlForError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// The table of the named routes in an `App`, used for generating URLs.
+///
+/// The value of this type can be obtained from `App::named_routes`, or by
+/// using `extractor::named_routes` during handling the request.
+/// If the `App` is mounted by `Scope::mount`, the URLs generated from the latter
+/// are prefixed with the mount point.
+#[derive(Debug, Clone, Default)]
+pub struct NamedRoutes {
+    routes: Arc<HashMap<String, NamedRoute>>,
+    prefix: String,
+}
+
+/// The URI pattern of a named route, with the constraints of its parameters.
+#[derive(Debug, Clone)]
+struct NamedRoute {
+    uri: Uri,
+    constraints: HashMap<String, Constraint>,
+}
+
+impl NamedRoutes {
+    pub(super) fn insert(&mut self, name: String, uri: Uri) -> Result<(), failure::Error> {
+        if self.routes.contains_key(&name) {
+            failure::bail!("the route name {:?} has already been used", name);
+        }
+
+        let mut constraints = HashMap::new();
+        for segment in uri.as_str().split('/').filter(|s| s.starts_with(':')) {
+            let param = parse_param_segment(segment)?;
+            if let Some(constraint) = param.constraint {
+                constraints.insert(param.name.to_owned(), Constraint::new(constraint)?);
+            }
+        }
+
+        Arc::make_mut(&mut self.routes).insert(name, NamedRoute { uri, constraints });
+        Ok(())
+    }
+
+    /// Returns a copy of this table that generates the URLs with the specified prefix.
+    pub(super) fn with_prefix(&self, prefix: &str) -> Self {
+        Self {
+            routes: self.routes.clone(),
+            prefix: prefix.to_owned(),
+        }
+    }
+
+    /// Returns the URI pattern of the route with the specified name, e.g. `/posts/:id`.
+    ///
+    /// The returned pattern does not contain the mount point of the `App`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.routes.get(name).map(|route| route.uri.as_str())
+    }
+
+    /// Generates the URL of the route with the specified name.
+    ///
+    /// The parameters in the route (`:param` and `*catch_all`) are replaced with
+    /// the percent-encoded values in `params`. The slashes in the value of
+    /// a catch-all parameter are kept as they are.
+    ///
+    /// If the parameter has a constraint (e.g. `:id:u32`), the encoded value must
+    /// satisfy it so that the generated URL is recognized by the route.
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Result<String, UrlForError> {
+        let route = self
+            .routes
+            .get(name)
+            .ok_or_else(|| UrlForError::UnknownName(name.to_owned()))?;
+        let path = route.uri.as_str();
+        if path == "/" {
+            return Ok(format!("{}/", self.prefix));
+        }
+
+        let find_param = |name: &str| {
+            params
+                .iter()
+                .find(|&&(param, _)| param == name)
+                .map(|&(_, value)| value)
+                .ok_or_else(|| UrlForError::MissingParam(name.to_owned()))
+        };
+
+        let mut url = String::with_capacity(self.prefix.len() + path.len());
+        url.push_str(&self.prefix);
+        for segment in path[1..].split('/') {
+            url.push('/');
+            match segment.as_bytes().first() {
+                Some(b':') | Some(b'*') => {
+                    let param = parse_param_segment(segment).expect("should be a valid segment");
+                    let value = find_param(param.name)?;
+                    if param.catch_all {
+                        for (i, piece) in value.split('/').enumerate() {
+                            if i > 0 {
+                                url.push('/');
+                            }
+                            url.extend(utf8_percent_encode(piece, PATH_SEGMENT_ENCODE_SET));
+                        }
+                    } else {
+                        let encoded: String =
+                            utf8_percent_encode(value, PATH_SEGMENT_ENCODE_SET).collect();
+                        if let Some(constraint) = route.constraints.get(param.name) {
+                            if !constraint.is_match(encoded.as_bytes()) {
+                                return Err(UrlForError::InvalidParam(param.name.to_owned()));
+                            }
+                        }
+                        url.push_str(&encoded);
+                    }
+                }
+                _ => url.push_str(segment),
+            }
+        }
+
+        Ok(url)
+    }
+}
+
+/// The information of a route registered in an `App`, obtained from `App::routes`.
+///
+/// The `Display` implementation formats the route as a line of the route table,
+/// e.g. `GET, POST /posts/:id (post)`. Only the registered methods are listed,
+/// so `HEAD` does not appear unless it is registered explicitly, and the route
+/// accepting any method is shown as `*`.
+#[derive(Debug, Clone)]
+pub struct RouteInfo<'a> {
+    pub(super) uri: &'a str,
+    pub(super) name: Option<&'a str>,
+    pub(super) allowed_methods: &'a AllowedMethods,
+    pub(super) scopes: Vec<&'a str>,
+    pub(super) is_default: bool,
+}
+
+impl<'a> RouteInfo<'a> {
+    /// Returns the full URI of this route.
+    ///
+    /// If this is the default route, the value is the prefix of its scope.
+    pub fn uri(&self) -> &'a str {
+        self.uri
+    }
+
+    /// Returns the name of this route, if specified.
+    pub fn name(&self) -> Option<&'a str> {
+        self.name
+    }
+
+    /// Returns the set of methods accepted by this route.
+    pub fn allowed_methods(&self) -> &'a AllowedMethods {
+        self.allowed_methods
+    }
+
+    /// Returns the prefixes of the scopes which this route belongs to, from the root scope.
+    pub fn scopes(&self) -> &[&'a str] {
+        &self.scopes[..]
+    }
+
+    /// Returns whether this route is the default route of its scope.
+    pub fn is_default(&self) -> bool {
+        self.is_default
+    }
+}
+
+impl<'a> fmt::Display for RouteInfo<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let methods = self.allowed_methods.to_header_value();
+        write!(
+            f,
+            "{} {}",
+            String::from_utf8_lossy(methods.as_bytes()),
+            self.uri
+        )?;
+        if self.is_default {
+            f.write_str(" (default)")?;
+        }
+        if let Some(name) = self.name {
+            write!(f, " ({})", name)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, matches::assert_matches};
+
+    fn named_routes() -> NamedRoutes {
+        let mut routes = NamedRoutes::default();
+        for &(name, uri) in &[
+            ("index", "/"),
+            ("post", "/posts/:id"),
+            ("user", r"/users/:id<\d+>"),
+            ("comment", "/posts/:id/comments/:comment_id/"),
+            ("static", "/static/*path"),
+        ] {
+            routes.insert(name.into(), uri.parse().unwrap()).unwrap();
+        }
+        routes
+    }
+
+    #[test]
+    fn url_for() {
+        let routes = named_routes();
+        assert_eq!(routes.url_for("index", &[]).unwrap(), "/");
+        assert_eq!(
+            routes.url_for("post", &[("id", "42")]).unwrap(),
+            "/posts/42"
+        );
+        assert_eq!(
+            routes.url_for("user", &[("id", "42")]).unwrap(),
+            "/users/42"
+        );
+        assert_eq!(
+            routes
+                .url_for("comment", &[("comment_id", "7"), ("id", "42")])
+                .unwrap(),
+            "/posts/42/comments/7/"
+        );
+        assert_eq!(
+            routes.url_for("post", &[("id", "a b/c")]).unwrap(),
+            "/posts/a%20b%2Fc"
+        );
+        assert_eq!(
+            routes
+                .url_for("static", &[("path", "css/style sheet.css")])
+                .unwrap(),
+            "/static/css/style%20sheet.css"
+        );
+        assert_eq!(
+            routes
+                .url_for("static", &[("path", "files/100%.txt?v=1")])
+                .unwrap(),
+            "/static/files/100%25.txt%3Fv=1"
+        );
+    }
+
+    #[test]
+    fn url_for_with_prefix() {
+        let routes = named_routes().with_prefix("/v1/admin");
+        assert_eq!(routes.url_for("index", &[]).unwrap(), "/v1/admin/");
+        assert_eq!(
+            routes.url_for("post", &[("id", "42")]).unwrap(),
+            "/v1/admin/posts/42"
+        );
+        assert_eq!(routes.get("post"), Some("/posts/:id"));
+    }
+
+    #[test]
+    fn url_for_errors() {
+        let routes = named_routes();
+        assert_matches!(
+            routes.url_for("unknown", &[]),
+            Err(UrlForError::UnknownName(ref name)) if name == "unknown"
+        );
+        assert_matches!(
+            routes.url_for("comment", &[("id", "42")]),
+            Err(UrlForError::MissingParam(ref name)) if name == "comment_id"
+        );
+        assert_matches!(
+            routes.url_for("user", &[("id", "alice")]),
+            Err(UrlForError::InvalidParam(ref name)) if name == "id"
+        );
+    }
+
+    #[test]
+    fn duplicated_name() {
+        let mut routes = named_routes();
+        assert!(routes
+            .insert("post".into(), "/p/:id".parse().unwrap())
+            .is_err());
+    }
+}
@@ -40,6 +40,11 @@ impl<T> Scopes<T> {
         }
     }
 
+    /// Returns an iterator over the scopes, starting from the root.
+    pub(super) fn iter(&self) -> impl Iterator<Item = &Scope<T>> {
+        Some(&self.root).into_iter().chain(&self.nodes)
+    }
+
     pub(super) fn add_node(&mut self, parent: ScopeId, data: T) -> Result<ScopeId, Error> {
         let id = ScopeId {
             inner: ScopeIdInner::Index(self.nodes.len()),
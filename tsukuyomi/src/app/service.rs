@@ -1,8 +1,11 @@
 use {
     super::{
         concurrency::{imp::ConcurrencyImpl, Concurrency, DefaultConcurrency},
+        mount::Mounted,
+        normalize,
         recognizer::Captures,
-        AppInner, ResourceData,
+        scope::ScopeId,
+        AppInner, RemoteAddr, ResourceData, TrailingSlash, VirtualHosts,
     },
     crate::{
         input::{
@@ -19,13 +22,13 @@ use {
     futures01::{Async, Future, Poll},
     http::{
         header::{self, HeaderMap},
-        Request, Response,
+        Method, Request, Response, StatusCode,
     },
     izanami::{
         http::{HttpBody, HttpUpgrade},
         service::Service,
     },
-    std::{fmt, marker::PhantomData, net::SocketAddr, sync::Arc},
+    std::{fmt, marker::PhantomData, sync::Arc},
     tokio_buf::SizeHint,
     tokio_io::{AsyncRead, AsyncWrite},
 };
@@ -43,24 +46,107 @@ macro_rules! ready {
 /// The instance of `Service` generated by `App`.
 #[derive(Debug)]
 pub struct AppService<C: Concurrency = DefaultConcurrency> {
-    inner: Arc<AppInner<C>>,
-    remote_addr: Option<SocketAddr>,
+    target: Target<C>,
+    remote_addr: Option<RemoteAddr>,
+    max_header_count: Option<usize>,
+    max_header_size: Option<usize>,
 }
 
 impl<C: Concurrency> AppService<C> {
     pub(super) fn new(inner: Arc<AppInner<C>>) -> Self {
+        Self::with_target(Target::App(inner))
+    }
+
+    pub(super) fn with_hosts(hosts: VirtualHosts<C>) -> Self {
+        Self::with_target(Target::Hosts(hosts))
+    }
+
+    fn with_target(target: Target<C>) -> Self {
         Self {
-            inner,
+            target,
             remote_addr: None,
+            max_header_count: None,
+            max_header_size: None,
+        }
+    }
+
+    /// Sets the address of the peer, which is exposed to the handlers as `REMOTE_ADDR`.
+    pub fn remote_addr(self, addr: impl Into<RemoteAddr>) -> Self {
+        Self {
+            remote_addr: Some(addr.into()),
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of header fields in a request.
+    ///
+    /// The requests exceeding this limit are rejected with `431 Request Header Fields Too Large`.
+    pub fn max_header_count(self, max: usize) -> Self {
+        Self {
+            max_header_count: Some(max),
+            ..self
         }
     }
 
-    pub fn remote_addr(self, addr: SocketAddr) -> Self {
+    /// Sets the maximum total size of the header fields in a request, in bytes.
+    ///
+    /// The size is calculated as the sum of the lengths of each field name and value.
+    /// The requests exceeding this limit are rejected with `431 Request Header Fields Too Large`.
+    pub fn max_header_size(self, max: usize) -> Self {
         Self {
-            remote_addr: Some(addr),
+            max_header_size: Some(max),
             ..self
         }
     }
+
+    fn check_header_limits(&self, headers: &HeaderMap) -> Result<(), crate::Error> {
+        if let Some(max) = self.max_header_count {
+            if headers.len() > max {
+                return Err(crate::error::request_header_fields_too_large(
+                    "too many header fields",
+                ));
+            }
+        }
+        if let Some(max) = self.max_header_size {
+            let size: usize = headers
+                .iter()
+                .map(|(name, value)| name.as_str().len() + value.len())
+                .sum();
+            if size > max {
+                return Err(crate::error::request_header_fields_too_large(
+                    "the header fields are too large",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<C: Concurrency> Clone for AppService<C> {
+    fn clone(&self) -> Self {
+        Self {
+            target: self.target.clone(),
+            remote_addr: self.remote_addr.clone(),
+            max_header_count: self.max_header_count,
+            max_header_size: self.max_header_size,
+        }
+    }
+}
+
+/// The `App`s which an `AppService` dispatches the requests to.
+#[derive(Debug)]
+enum Target<C: Concurrency> {
+    App(Arc<AppInner<C>>),
+    Hosts(VirtualHosts<C>),
+}
+
+impl<C: Concurrency> Clone for Target<C> {
+    fn clone(&self) -> Self {
+        match self {
+            Target::App(inner) => Target::App(inner.clone()),
+            Target::Hosts(hosts) => Target::Hosts(hosts.clone()),
+        }
+    }
 }
 
 impl<C, Bd> Service<Request<Bd>> for AppService<C>
@@ -79,24 +165,52 @@ where
 
     #[inline]
     fn call(&mut self, request: Request<Bd>) -> Self::Future {
-        let (parts, body) = request.into_parts();
+        let (mut parts, body) = request.into_parts();
+
+        let inner = match self.target {
+            Target::App(ref inner) => inner.clone(),
+            Target::Hosts(ref hosts) => hosts.find(&parts).clone(),
+        };
+        if let Some(uri) = inner.normalize_path.normalize_uri(&parts.uri) {
+            parts.uri = uri;
+        }
+
+        let state = match self.check_header_limits(&parts.headers) {
+            Ok(()) => AppFutureState::Init,
+            Err(err) => AppFutureState::Rejected(Some(err)),
+        };
 
         let mut locals = LocalMap::default();
         RequestBody::from(body).insert_into(&mut locals);
 
-        if let Some(addr) = self.remote_addr {
-            locals.insert(&super::REMOTE_ADDR, addr);
+        if let Some(ref addr) = self.remote_addr {
+            locals.insert(&super::REMOTE_ADDR, addr.clone());
         }
 
+        // the request forwarded from the outer `App` by `Scope::mount`.
+        let mut named_routes = None;
+        if let Some(mounted) = parts.extensions.remove::<Mounted>() {
+            named_routes = Some(inner.named_routes.with_prefix(&mounted.prefix));
+            locals.insert(&super::MOUNT_PREFIX, mounted.prefix);
+            if let Some(addr) = mounted.remote_addr {
+                locals.insert(&super::REMOTE_ADDR, addr);
+            }
+        }
+        parts
+            .extensions
+            .insert(named_routes.unwrap_or_else(|| inner.named_routes.clone()));
+
         AppFuture {
+            head: parts.method == Method::HEAD,
             request: Request::from_parts(parts, ()),
-            inner: self.inner.clone(),
+            inner,
             cookie_jar: None,
             response_headers: None,
             locals,
             resource: None,
             captures: None,
-            state: AppFutureState::Init,
+            scope: ScopeId::root(),
+            state,
         }
     }
 }
@@ -105,6 +219,8 @@ where
 #[must_use = "futures do nothing unless polled"]
 #[derive(Debug)]
 pub struct AppFuture<C: Concurrency> {
+    /// Whether the original request method is `HEAD`.
+    head: bool,
     request: Request<()>,
     inner: Arc<AppInner<C>>,
     cookie_jar: Option<CookieJar>,
@@ -112,11 +228,13 @@ pub struct AppFuture<C: Concurrency> {
     locals: LocalMap,
     resource: Option<Arc<ResourceData<C>>>,
     captures: Option<Captures>,
+    scope: ScopeId,
     state: AppFutureState<C>,
 }
 
 enum AppFutureState<C: Concurrency> {
     Init,
+    Rejected(Option<crate::Error>),
     InFlight(<C::Impl as ConcurrencyImpl>::Handle),
     Done,
 }
@@ -125,12 +243,21 @@ impl<C: Concurrency> fmt::Debug for AppFutureState<C> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             AppFutureState::Init => f.debug_struct("Init").finish(),
+            AppFutureState::Rejected(..) => f.debug_struct("Rejected").finish(),
             AppFutureState::InFlight(..) => f.debug_struct("InFlight").finish(),
             AppFutureState::Done => f.debug_struct("Done").finish(),
         }
     }
 }
 
+/// The result of routing the request.
+enum Recognized<H> {
+    /// The handle of the matched route.
+    Handle(H),
+    /// The response created without calling any handler, e.g. the redirection.
+    Reply(Response<ResponseBody>),
+}
+
 macro_rules! input {
     ($self:expr) => {
         &mut Input {
@@ -155,7 +282,9 @@ macro_rules! input {
 }
 
 impl<C: Concurrency> AppFuture<C> {
-    fn process_recognize(&mut self) -> Result<<C::Impl as ConcurrencyImpl>::Handle, crate::Error> {
+    fn process_recognize(
+        &mut self,
+    ) -> Result<Recognized<<C::Impl as ConcurrencyImpl>::Handle>, crate::Error> {
         self.resource = None;
         self.captures = None;
 
@@ -164,13 +293,113 @@ impl<C: Concurrency> AppFuture<C> {
             .find_resource(self.request.uri().path(), &mut self.captures)
         {
             Ok(resource) => {
-                self.resource = Some(resource.clone());
-                Ok(<C::Impl as ConcurrencyImpl>::handle(&resource.handler))
+                let resource = resource.clone();
+                self.enter_scope(resource.scope);
+                self.process_auto_head(&resource.allowed_methods);
+                let handle = <C::Impl as ConcurrencyImpl>::handle(&resource.handler);
+                self.resource = Some(resource);
+                Ok(Recognized::Handle(handle))
             }
-            Err(scope) => match self.inner.find_default_handler(scope.id()) {
-                Some(fallback) => Ok(<C::Impl as ConcurrencyImpl>::handle(fallback)),
-                None => Err(http::StatusCode::NOT_FOUND.into()),
-            },
+            Err(scope) => {
+                let scope_id = scope.id();
+                if let Some(recognized) = self.process_trailing_slash() {
+                    return Ok(recognized);
+                }
+                self.enter_scope(scope_id);
+                let inner = self.inner.clone();
+                match inner.find_default_handler(scope_id) {
+                    Some((fallback, allowed_methods)) => {
+                        self.process_auto_head(allowed_methods);
+                        Ok(Recognized::Handle(<C::Impl as ConcurrencyImpl>::handle(
+                            fallback,
+                        )))
+                    }
+                    None => Err(StatusCode::NOT_FOUND.into()),
+                }
+            }
+        }
+    }
+
+    /// Sets the scope where the request belongs, and exposes the application
+    /// states visible in the scope to the extractors.
+    fn enter_scope(&mut self, scope: ScopeId) {
+        self.scope = scope;
+        let states = self.inner.scope(scope).data.states.clone();
+        self.request.extensions_mut().insert(states);
+    }
+
+    /// Looks up the route with the trailing slash of the path toggled,
+    /// and applies the policy of the found route.
+    ///
+    /// The redirection is not an error, and hence is not passed to the error handlers.
+    fn process_trailing_slash(
+        &mut self,
+    ) -> Option<Recognized<<C::Impl as ConcurrencyImpl>::Handle>> {
+        let path = normalize::toggle_trailing_slash(self.request.uri().path())?;
+
+        let mut captures = None;
+        let resource = match self.inner.find_resource(&path, &mut captures) {
+            Ok(resource) => resource.clone(),
+            Err(..) => return None,
+        };
+
+        match self.inner.trailing_slash(&resource) {
+            TrailingSlash::Strict => None,
+            TrailingSlash::Redirect => Some(Recognized::Reply(normalize::redirect_trailing_slash(
+                self.request.method(),
+                &path,
+                self.request.uri().query(),
+            ))),
+            TrailingSlash::Transparent => {
+                *self.request.uri_mut() = normalize::replace_path(self.request.uri(), &path)?;
+                self.enter_scope(resource.scope);
+                self.process_auto_head(&resource.allowed_methods);
+                let handle = <C::Impl as ConcurrencyImpl>::handle(&resource.handler);
+                self.resource = Some(resource);
+                self.captures = captures;
+                Some(Recognized::Handle(handle))
+            }
+        }
+    }
+
+    /// Dispatches a `HEAD` request as `GET` if the handler accepts `GET` but not `HEAD`.
+    ///
+    /// The body of the response is removed in `process_before_reply`.
+    fn process_auto_head(&mut self, allowed_methods: &AllowedMethods) {
+        if self.request.method() == Method::HEAD
+            && !allowed_methods.contains(&Method::HEAD)
+            && allowed_methods.contains(&Method::GET)
+        {
+            *self.request.method_mut() = Method::GET;
+        }
+    }
+
+    /// Creates the response to an `OPTIONS` request if the matched route does not handle it.
+    fn process_auto_options(&self) -> Option<Response<ResponseBody>> {
+        if self.request.method() != Method::OPTIONS {
+            return None;
+        }
+        let resource = self.resource.as_ref()?;
+        if resource.allowed_methods.contains(&Method::OPTIONS) {
+            return None;
+        }
+        let allow = resource.allow_header()?;
+
+        let mut response = Response::new(ResponseBody::empty());
+        *response.status_mut() = StatusCode::NO_CONTENT;
+        response.headers_mut().insert(header::ALLOW, allow);
+        Some(response)
+    }
+
+    /// Creates the response from the error, using the error handler of the current scope.
+    fn process_error(&self, err: crate::Error) -> Response<ResponseBody> {
+        match self.inner.find_error_handler(self.scope) {
+            Some(error_handler) => {
+                let mut output = error_handler(&err, &self.request);
+                output.extensions_mut().insert(err);
+                output
+            }
+            None => err.into_response(),
         }
     }
 
@@ -205,6 +434,22 @@ impl<C: Concurrency> AppFuture<C> {
                 });
             output.headers_mut().remove(header::TRANSFER_ENCODING);
         }
+
+        // append the allowed methods to `405 Method Not Allowed`.
+        if output.status() == StatusCode::METHOD_NOT_ALLOWED {
+            if let Some(allow) = self.resource.as_ref().and_then(|r| r.allow_header()) {
+                output
+                    .headers_mut()
+                    .entry(header::ALLOW)
+                    .expect("valid header name")
+                    .or_insert(allow);
+            }
+        }
+
+        // strip the body of the response to HEAD, keeping `Content-Length`.
+        if self.head {
+            *output.body_mut() = ResponseBody::empty();
+        }
     }
 }
 
@@ -216,9 +461,16 @@ impl<C: Concurrency> Future for AppFuture<C> {
         let polled = loop {
             self.state = match self.state {
                 AppFutureState::Init => match self.process_recognize() {
-                    Ok(in_flight) => AppFutureState::InFlight(in_flight),
+                    Ok(Recognized::Handle(in_flight)) => match self.process_auto_options() {
+                        Some(output) => break Ok((output, None)),
+                        None => AppFutureState::InFlight(in_flight),
+                    },
+                    Ok(Recognized::Reply(output)) => break Ok((output, None)),
                     Err(err) => break Err(err),
                 },
+                AppFutureState::Rejected(ref mut err) => {
+                    break Err(err.take().expect("the future has already polled."));
+                }
                 AppFutureState::InFlight(ref mut in_flight) => {
                     break ready!(<C::Impl as ConcurrencyImpl>::poll_ready_handle(
                         in_flight,
@@ -232,7 +484,7 @@ impl<C: Concurrency> Future for AppFuture<C> {
 
         let (mut output, upgrade) = match polled {
             Ok(output) => output,
-            Err(err) => (err.into_response(), None),
+            Err(err) => (self.process_error(err), None),
         };
 
         self.process_before_reply(&mut output);
@@ -255,6 +507,12 @@ impl<C: Concurrency> AppBody<C> {
     }
 }
 
+impl<C: Concurrency> From<AppBody<C>> for ResponseBody {
+    fn from(body: AppBody<C>) -> Self {
+        body.into_response_body()
+    }
+}
+
 impl<C: Concurrency> HttpBody for AppBody<C> {
     type Data = <ResponseBody as HttpBody>::Data;
     type Error = <ResponseBody as HttpBody>::Error;
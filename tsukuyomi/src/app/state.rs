@@ -0,0 +1,70 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
+    sync::Arc,
+};
+
+/// A type map that holds the application states registered in a scope.
+#[derive(Clone, Default)]
+pub(crate) struct States {
+    map: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl fmt::Debug for States {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("States")
+            .field("len", &self.map.len())
+            .finish()
+    }
+}
+
+impl States {
+    pub(super) fn insert<T>(&mut self, value: T)
+    where
+        T: Send + Sync + 'static,
+    {
+        self.map.insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    /// Overwrites the states with the ones in `other`.
+    pub(super) fn extend(&mut self, other: &Self) {
+        self.map
+            .extend(other.map.iter().map(|(&id, state)| (id, state.clone())));
+    }
+
+    pub(super) fn contains(&self, id: &TypeId) -> bool {
+        self.map.contains_key(id)
+    }
+
+    pub(crate) fn get<T>(&self) -> Option<Arc<T>>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.map
+            .get(&TypeId::of::<T>())
+            .cloned()
+            .and_then(|state| state.downcast().ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn override_state() {
+        let mut outer = States::default();
+        outer.insert(1u32);
+        outer.insert("outer");
+
+        let mut inner = States::default();
+        inner.insert(2u32);
+
+        let mut merged = outer.clone();
+        merged.extend(&inner);
+        assert_eq!(merged.get::<u32>().map(|x| *x), Some(2));
+        assert_eq!(merged.get::<&str>().map(|x| *x), Some("outer"));
+        assert!(merged.get::<String>().is_none());
+    }
+}
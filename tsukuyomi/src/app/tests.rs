@@ -184,3 +184,71 @@ fn experimental_api() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn reject_too_large_header_fields() -> Result<()> {
+    use {
+        crate::input::body::RequestBody, futures01::Future, http::Request,
+        izanami::service::Service,
+    };
+
+    let app: App = App::build(|s| {
+        s.at("/", (), endpoint::reply(""))?;
+        Ok(())
+    })?;
+
+    let request = || {
+        Request::get("/")
+            .header("x-foo", "foo")
+            .header("x-bar", "bar")
+            .body(RequestBody::new(""))
+            .unwrap()
+    };
+
+    let mut service = app.new_service();
+    let response = service.call(request()).wait().unwrap();
+    assert_eq!(response.status(), 200);
+
+    let mut service = app.new_service().max_header_count(1);
+    let response = service.call(request()).wait().unwrap();
+    assert_eq!(response.status(), 431);
+
+    let mut service = app.new_service().max_header_size(10);
+    let response = service.call(request()).wait().unwrap();
+    assert_eq!(response.status(), 431);
+
+    Ok(())
+}
+
+#[test]
+fn list_routes() -> Result<()> {
+    let app: App = App::build(|s| {
+        s.at("/", (), endpoint::get().reply(""))?;
+        s.nest("/api", (), |s| {
+            s.at_named("post", path!("/posts/:id"), (), endpoint::reply(""))?;
+            s.default((), endpoint::reply(""))
+        })
+    })?;
+
+    let routes = app.routes();
+    let table: Vec<String> = routes.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        table,
+        vec![
+            "GET /".to_owned(),
+            "* /api/posts/:id (post)".to_owned(),
+            "* /api (default)".to_owned(),
+        ]
+    );
+
+    assert_eq!(routes[1].uri(), "/api/posts/:id");
+    assert_eq!(routes[1].name(), Some("post"));
+    assert_eq!(routes[1].scopes(), &["/", "/api"]);
+    assert!(routes[1].allowed_methods().is_any());
+    assert!(!routes[1].is_default());
+
+    assert_eq!(routes[2].scopes(), &["/", "/api"]);
+    assert!(routes[2].is_default());
+
+    Ok(())
+}
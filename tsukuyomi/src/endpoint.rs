@@ -3,7 +3,12 @@
 pub mod builder;
 
 use {
-    crate::{error::Error, future::TryFuture, handler::metadata::AllowedMethods, input::Input},
+    crate::{
+        error::Error,
+        future::TryFuture,
+        handler::metadata::{AllowedMethods, RequiredStates},
+        input::Input,
+    },
     http::{Method, StatusCode},
 };
 
@@ -22,6 +27,13 @@ pub trait Endpoint<T> {
     ///
     /// This method is used for implementation of `Handler::metadata`.
     fn allowed_methods(&self) -> AllowedMethods;
+
+    /// Returns a set of application states that this endpoint requires.
+    ///
+    /// This method is used for implementation of `Handler::metadata`.
+    fn required_states(&self) -> RequiredStates {
+        RequiredStates::new()
+    }
 }
 
 #[derive(Debug)]
@@ -64,6 +76,18 @@ pub fn endpoint<T, R>(
     apply: impl Fn(T, &mut ApplyContext<'_, '_>) -> Result<R, (T, ApplyError)>,
     allowed_methods: AllowedMethods,
 ) -> impl Endpoint<T, Output = R::Ok, Error = R::Error, Future = R>
+where
+    R: TryFuture,
+{
+    self::endpoint_with_states(apply, allowed_methods, RequiredStates::new())
+}
+
+/// Creates an `Endpoint` that requires the specified application states.
+pub(crate) fn endpoint_with_states<T, R>(
+    apply: impl Fn(T, &mut ApplyContext<'_, '_>) -> Result<R, (T, ApplyError)>,
+    allowed_methods: AllowedMethods,
+    required_states: RequiredStates,
+) -> impl Endpoint<T, Output = R::Ok, Error = R::Error, Future = R>
 where
     R: TryFuture,
 {
@@ -71,6 +95,7 @@ where
     struct ApplyFn<F> {
         apply: F,
         allowed_methods: AllowedMethods,
+        required_states: RequiredStates,
     }
 
     impl<F, T, R> Endpoint<T> for ApplyFn<F>
@@ -91,11 +116,17 @@ where
         fn allowed_methods(&self) -> AllowedMethods {
             self.allowed_methods.clone()
         }
+
+        #[inline]
+        fn required_states(&self) -> RequiredStates {
+            self.required_states.clone()
+        }
     }
 
     ApplyFn {
         apply,
         allowed_methods,
+        required_states,
     }
 }
 
@@ -116,6 +147,11 @@ where
     fn allowed_methods(&self) -> AllowedMethods {
         (**self).allowed_methods()
     }
+
+    #[inline]
+    fn required_states(&self) -> RequiredStates {
+        (**self).required_states()
+    }
 }
 
 impl<E, T> Endpoint<T> for std::sync::Arc<E>
@@ -135,6 +171,11 @@ where
     fn allowed_methods(&self) -> AllowedMethods {
         (**self).allowed_methods()
     }
+
+    #[inline]
+    fn required_states(&self) -> RequiredStates {
+        (**self).required_states()
+    }
 }
 
 mod impl_chain {
@@ -143,7 +184,7 @@ mod impl_chain {
         crate::{
             error::Error,
             future::{Poll, TryFuture},
-            handler::metadata::AllowedMethods,
+            handler::metadata::{AllowedMethods, RequiredStates},
             input::Input,
             util::{Chain, Either},
         },
@@ -170,6 +211,13 @@ mod impl_chain {
             let right = self.right.allowed_methods();
             left.merge(right)
         }
+
+        #[inline]
+        fn required_states(&self) -> RequiredStates {
+            let left = self.left.required_states();
+            let right = self.right.required_states();
+            left.merge(right)
+        }
     }
 
     #[derive(Debug)]
@@ -107,6 +107,7 @@ where
         T: Combine<E::Output>,
         F: Func<<T as Combine<E::Output>>::Out> + Clone,
     {
+        let required_states = self.extractor.required_states();
         let apply_fn = {
             let allowed_methods = self.allowed_methods.clone();
             let extractor = self.extractor;
@@ -121,7 +122,7 @@ where
                 })
             }
         };
-        crate::endpoint::endpoint(apply_fn, self.allowed_methods)
+        crate::endpoint::endpoint_with_states(apply_fn, self.allowed_methods, required_states)
     }
 
     /// Creates an `Endpoint` that replies its result as a `Future`.
@@ -140,6 +141,7 @@ where
         R: IntoFuture,
         R::Error: Into<Error>,
     {
+        let required_states = self.extractor.required_states();
         let apply_fn = {
             let allowed_methods = self.allowed_methods.clone();
             let extractor = self.extractor;
@@ -155,7 +157,7 @@ where
                 })
             }
         };
-        crate::endpoint::endpoint(apply_fn, self.allowed_methods)
+        crate::endpoint::endpoint_with_states(apply_fn, self.allowed_methods, required_states)
     }
 }
 
@@ -3,7 +3,11 @@
 //! Tsukuyomi models the all errors generated during handling HTTP requests with a trait
 //! named [`HttpError`]. This trait is a method for converting itself into an HTTP response.
 //!
+//! The errors can also be rendered as the problem details defined in RFC 7807,
+//! by converting them into [`ProblemDetails`].
+//!
 //! [`HttpError`]: ./trait.HttpError.html
+//! [`ProblemDetails`]: ./struct.ProblemDetails.html
 
 use {
     crate::{
@@ -15,6 +19,10 @@ use {
     std::{any::TypeId, fmt, io},
 };
 
+mod problem;
+
+pub use self::problem::ProblemDetails;
+
 /// A type alias of `Result<T, E>` with `error::Error` as error type.
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -37,6 +45,13 @@ pub trait HttpError: AsFail + fmt::Debug + fmt::Display + Send + Sync + 'static
         response
     }
 
+    /// Creates the message body of an HTTP response from this error value.
+    ///
+    /// The default implementation returns an empty body.
+    fn to_response_body(&self) -> ResponseBody {
+        ResponseBody::empty()
+    }
+
     // not a public API.
     #[doc(hidden)]
     fn __private_type_id__(&self) -> TypeId {
@@ -160,6 +175,18 @@ define_custom_errors! {
     /// Equivalent to `err_msg(StatusCode::METHOD_NOT_ALLOWED, msg)`.
     method_not_allowed => METHOD_NOT_ALLOWED,
 
+    /// Equivalent to `err_msg(StatusCode::NOT_ACCEPTABLE, msg)`.
+    not_acceptable => NOT_ACCEPTABLE,
+
+    /// Equivalent to `err_msg(StatusCode::PAYLOAD_TOO_LARGE, msg)`.
+    payload_too_large => PAYLOAD_TOO_LARGE,
+
+    /// Equivalent to `err_msg(StatusCode::UNSUPPORTED_MEDIA_TYPE, msg)`.
+    unsupported_media_type => UNSUPPORTED_MEDIA_TYPE,
+
+    /// Equivalent to `err_msg(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE, msg)`.
+    request_header_fields_too_large => REQUEST_HEADER_FIELDS_TOO_LARGE,
+
     /// Equivalent to `err_msg(StatusCode::INTERNAL_SERVER_ERROR, msg)`.
     internal_server_error => INTERNAL_SERVER_ERROR,
 }
@@ -289,7 +316,8 @@ impl AsFail for Error {
 impl IntoResponse for Error {
     fn into_response(self) -> crate::output::Response {
         let (mut parts, ()) = self.inner.to_response().into_parts();
+        let body = self.inner.to_response_body();
         parts.extensions.insert(self);
-        Response::from_parts(parts, ResponseBody::empty())
+        Response::from_parts(parts, body)
     }
 }
@@ -0,0 +1,278 @@
+use {
+    super::{Error, HttpError},
+    crate::{
+        endpoint::ApplyError,
+        output::{IntoResponse, ResponseBody},
+    },
+    http::{
+        header::{HeaderValue, CONTENT_TYPE},
+        Response, StatusCode,
+    },
+    serde_json::{Map, Value},
+    std::fmt,
+};
+
+/// The media type of the problem details in JSON format.
+const APPLICATION_PROBLEM_JSON: &str = "application/problem+json";
+
+/// An error value that is rendered as a problem details object defined in [RFC 7807].
+///
+/// The response body is a JSON object with the content type `application/problem+json`.
+/// The members `type` and `title` default to `about:blank` and the canonical reason
+/// of the status code, and the members `detail` and `instance` are omitted unless
+/// they are specified.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::error::ProblemDetails;
+/// # use http::StatusCode;
+/// let problem = ProblemDetails::new(StatusCode::FORBIDDEN)
+///     .with_type("https://example.com/probs/out-of-credit")
+///     .with_title("You do not have enough credit.")
+///     .with_detail("Your current balance is 30, but that costs 50.")
+///     .with_instance("/account/12345/msgs/abc")
+///     .with_extension("balance", 30);
+///
+/// assert_eq!(problem.status(), StatusCode::FORBIDDEN);
+/// assert_eq!(problem.to_json()["balance"], 30);
+/// ```
+///
+/// [RFC 7807]: https://tools.ietf.org/html/rfc7807
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProblemDetails {
+    problem_type: String,
+    title: String,
+    status: StatusCode,
+    detail: Option<String>,
+    instance: Option<String>,
+    extensions: Map<String, Value>,
+}
+
+impl ProblemDetails {
+    /// Creates a `ProblemDetails` with the specified status code.
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            problem_type: "about:blank".into(),
+            title: status.canonical_reason().unwrap_or("Unknown Error").into(),
+            status,
+            detail: None,
+            instance: None,
+            extensions: Map::new(),
+        }
+    }
+
+    /// Creates a `ProblemDetails` from an arbitrary `Error`.
+    ///
+    /// If the underlying error value is a `ProblemDetails`, it is returned as it is.
+    /// Otherwise, the status code of the error is used and its message is set to `detail`.
+    /// This is the way to convert the errors thrown by the framework, such as the ones
+    /// from the extractors in `extractor::body`, into the problem details.
+    ///
+    /// The message of server errors (`5xx`) is not set to `detail`, since it may
+    /// expose the internal details of the server to the clients.
+    pub fn from_error(err: &Error) -> Self {
+        if let Some(problem) = err.downcast_ref::<Self>() {
+            return problem.clone();
+        }
+        let status = err.status_code();
+        if status.is_server_error() {
+            Self::new(status)
+        } else {
+            Self::new(status).with_detail(err.to_string())
+        }
+    }
+
+    /// Sets the URI reference that identifies the problem type.
+    pub fn with_type(self, problem_type: impl Into<String>) -> Self {
+        Self {
+            problem_type: problem_type.into(),
+            ..self
+        }
+    }
+
+    /// Sets the short, human-readable summary of the problem type.
+    pub fn with_title(self, title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            ..self
+        }
+    }
+
+    /// Sets the human-readable explanation specific to this occurrence of the problem.
+    pub fn with_detail(self, detail: impl Into<String>) -> Self {
+        Self {
+            detail: Some(detail.into()),
+            ..self
+        }
+    }
+
+    /// Sets the URI reference that identifies the specific occurrence of the problem.
+    pub fn with_instance(self, instance: impl Into<String>) -> Self {
+        Self {
+            instance: Some(instance.into()),
+            ..self
+        }
+    }
+
+    /// Adds an extension member.
+    ///
+    /// The extension members whose name conflicts with the standard members are ignored.
+    pub fn with_extension(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.extensions.insert(name.into(), value.into());
+        self
+    }
+
+    /// Returns the URI reference that identifies the problem type.
+    pub fn problem_type(&self) -> &str {
+        &self.problem_type
+    }
+
+    /// Returns the short, human-readable summary of the problem type.
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Returns the HTTP status code of this problem.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Returns the human-readable explanation specific to this occurrence of the problem.
+    pub fn detail(&self) -> Option<&str> {
+        self.detail.as_ref().map(|s| &**s)
+    }
+
+    /// Returns the URI reference that identifies the specific occurrence of the problem.
+    pub fn instance(&self) -> Option<&str> {
+        self.instance.as_ref().map(|s| &**s)
+    }
+
+    /// Returns the extension members.
+    pub fn extensions(&self) -> &Map<String, Value> {
+        &self.extensions
+    }
+
+    /// Converts this value into a JSON object.
+    pub fn to_json(&self) -> Value {
+        let mut members = self.extensions.clone();
+        members.insert("type".into(), self.problem_type.clone().into());
+        members.insert("title".into(), self.title.clone().into());
+        members.insert("status".into(), self.status.as_u16().into());
+        if let Some(ref detail) = self.detail {
+            members.insert("detail".into(), detail.clone().into());
+        } else {
+            members.remove("detail");
+        }
+        if let Some(ref instance) = self.instance {
+            members.insert("instance".into(), instance.clone().into());
+        } else {
+            members.remove("instance");
+        }
+        Value::Object(members)
+    }
+}
+
+impl fmt::Display for ProblemDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.detail {
+            Some(ref detail) => write!(f, "{}: {}", self.title, detail),
+            None => f.write_str(&self.title),
+        }
+    }
+}
+
+impl failure::Fail for ProblemDetails {}
+
+impl HttpError for ProblemDetails {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn to_response(&self) -> Response<()> {
+        let mut response = Response::new(());
+        *response.status_mut() = self.status;
+        response.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static(APPLICATION_PROBLEM_JSON),
+        );
+        response
+    }
+
+    fn to_response_body(&self) -> ResponseBody {
+        self.to_json().to_string().into_bytes().into()
+    }
+}
+
+impl IntoResponse for ProblemDetails {
+    fn into_response(self) -> crate::output::Response {
+        Error::from(self).into_response()
+    }
+}
+
+impl From<ApplyError> for ProblemDetails {
+    fn from(_err: ApplyError) -> Self {
+        Self::new(StatusCode::METHOD_NOT_ALLOWED)
+            .with_detail("the request method is not allowed for this endpoint")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, serde_json::json};
+
+    #[test]
+    fn default_members() {
+        let problem = ProblemDetails::new(StatusCode::NOT_FOUND);
+        assert_eq!(
+            problem.to_json(),
+            json!({
+                "type": "about:blank",
+                "title": "Not Found",
+                "status": 404,
+            })
+        );
+    }
+
+    #[test]
+    fn standard_members_take_precedence() {
+        let problem = ProblemDetails::new(StatusCode::BAD_REQUEST)
+            .with_extension("status", 200)
+            .with_extension("detail", "overridden")
+            .with_extension("invalid-params", json!(["name"]));
+        assert_eq!(
+            problem.to_json(),
+            json!({
+                "type": "about:blank",
+                "title": "Bad Request",
+                "status": 400,
+                "invalid-params": ["name"],
+            })
+        );
+    }
+
+    #[test]
+    fn from_error() {
+        let err = crate::error::bad_request("missing the header field `Content-type`");
+        let problem = ProblemDetails::from_error(&err);
+        assert_eq!(problem.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            problem.detail(),
+            Some("missing the header field `Content-type`")
+        );
+
+        let err = crate::error::internal_server_error("failed to connect to the database");
+        let problem = ProblemDetails::from_error(&err);
+        assert_eq!(problem.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(problem.detail(), None);
+
+        let err = Error::from(
+            ProblemDetails::new(StatusCode::SERVICE_UNAVAILABLE)
+                .with_detail("under maintenance")
+                .with_instance("/a"),
+        );
+        let problem = ProblemDetails::from_error(&err);
+        assert_eq!(problem.detail(), Some("under maintenance"));
+        assert_eq!(problem.instance(), Some("/a"));
+    }
+}
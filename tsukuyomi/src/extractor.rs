@@ -10,13 +10,16 @@ pub use self::ext::ExtractorExt;
 
 use {
     crate::{
+        app::NamedRoutes,
         error::Error,
         future::TryFuture,
         generic::Tuple,
-        input::Input,
+        handler::metadata::RequiredStates,
+        input::{query::QueryError, Input},
         util::Never, //
     },
     serde::de::DeserializeOwned,
+    std::{marker::PhantomData, sync::Arc},
 };
 
 /// A trait abstracting the extraction of values from the incoming request.
@@ -35,6 +38,13 @@ pub trait Extractor {
     /// Note that the actual extraction process is started when the value
     /// of `Extract` is polled.
     fn extract(&self) -> Self::Extract;
+
+    /// Returns the set of application states that this extractor requires.
+    ///
+    /// This method is used for checking the registration of states when building `App`.
+    fn required_states(&self) -> RequiredStates {
+        RequiredStates::new()
+    }
 }
 
 impl<E> Extractor for Box<E>
@@ -49,6 +59,11 @@ where
     fn extract(&self) -> Self::Extract {
         (**self).extract()
     }
+
+    #[inline]
+    fn required_states(&self) -> RequiredStates {
+        (**self).required_states()
+    }
 }
 
 impl<E> Extractor for std::rc::Rc<E>
@@ -63,6 +78,11 @@ where
     fn extract(&self) -> Self::Extract {
         (**self).extract()
     }
+
+    #[inline]
+    fn required_states(&self) -> RequiredStates {
+        (**self).required_states()
+    }
 }
 
 impl<E> Extractor for std::sync::Arc<E>
@@ -77,6 +97,11 @@ where
     fn extract(&self) -> Self::Extract {
         (**self).extract()
     }
+
+    #[inline]
+    fn required_states(&self) -> RequiredStates {
+        (**self).required_states()
+    }
 }
 
 impl Extractor for () {
@@ -245,26 +270,109 @@ pub fn version() -> impl Extractor<
 }
 
 /// Creates an `Extractor` that parses the value of query string to `T`.
+///
+/// If the request URI has no query, it is parsed as an empty string.
 pub fn query<T>() -> impl Extractor<
     Output = (T,), //
-    Error = Error,
-    Extract = impl TryFuture<Ok = (T,), Error = Error> + Send + 'static,
+    Error = QueryError,
+    Extract = impl TryFuture<Ok = (T,), Error = QueryError> + Send + 'static,
 >
 where
     T: DeserializeOwned,
 {
-    self::ready(move |input| {
-        let query_str = input
+    self::ready(|input| crate::input::query::parse(input).map(|x| (x,)))
+}
+
+/// Creates an `Extractor` that returns the table of the named routes in the current `App`.
+///
+/// The returned value is used for generating URLs during handling the request.
+pub fn named_routes() -> impl Extractor<
+    Output = (NamedRoutes,), //
+    Error = Never,
+    Extract = impl TryFuture<Ok = (NamedRoutes,), Error = Never> + Send + 'static,
+> {
+    self::ready(|input| {
+        Ok((input
             .request
-            .uri()
-            .query()
-            .ok_or_else(|| crate::error::bad_request("missing query"))?;
-        serde_urlencoded::from_str(query_str) //
-            .map(|x| (x,))
-            .map_err(crate::error::bad_request)
+            .extensions()
+            .get::<NamedRoutes>()
+            .cloned()
+            .unwrap_or_default(),))
     })
 }
 
+/// Creates an `Extractor` that returns the application state of the specified type.
+///
+/// The state is registered by `Scope::state`, and the one registered in the innermost
+/// scope of the matched route is returned.
+/// Building `App` fails if the state is not registered in the scope of the route.
+///
+/// The state is extracted as `Arc<T>` rather than `&T`, since the values returned
+/// from the extractors are passed to the handler by value and cannot borrow from
+/// the request. The shared reference can be obtained through `Deref`.
+pub fn state<T>() -> impl Extractor<
+    Output = (Arc<T>,), //
+    Error = Error,
+    Extract = self::state::State<T>, // private
+>
+where
+    T: Send + Sync + 'static,
+{
+    self::state::StateExtractor(PhantomData)
+}
+
+mod state {
+    use {
+        super::*,
+        crate::{app::States, future::Poll},
+    };
+
+    #[allow(missing_debug_implementations)]
+    pub struct StateExtractor<T>(pub(super) PhantomData<fn() -> T>);
+
+    impl<T> Extractor for StateExtractor<T>
+    where
+        T: Send + Sync + 'static,
+    {
+        type Output = (Arc<T>,);
+        type Error = Error;
+        type Extract = State<T>;
+
+        #[inline]
+        fn extract(&self) -> Self::Extract {
+            State(PhantomData)
+        }
+
+        fn required_states(&self) -> RequiredStates {
+            let mut states = RequiredStates::new();
+            states.insert::<T>();
+            states
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct State<T>(PhantomData<fn() -> T>);
+
+    impl<T> TryFuture for State<T>
+    where
+        T: Send + Sync + 'static,
+    {
+        type Ok = (Arc<T>,);
+        type Error = Error;
+
+        #[inline]
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            input
+                .request
+                .extensions()
+                .get::<Arc<States>>()
+                .and_then(|states| states.get::<T>())
+                .map(|state| (state,).into())
+                .ok_or_else(|| crate::error::internal_server_error("missing application state"))
+        }
+    }
+}
+
 /// Creates an `Extractor` that returns the value of extension of the specified type.
 pub fn extension<T>() -> impl Extractor<
     Output = (T,), //
@@ -1,24 +1,48 @@
 //! Extractors for parsing message body.
+//!
+//! The extractors which read the entire of message body decode the data
+//! according to the header field `Content-Encoding` (`gzip` and `deflate` are supported)
+//! before parsing it. The request with unsupported content coding is rejected
+//! with `415 Unsupported Media Type`.
+//!
+//! The size of request body can be limited by `Decode::limit`, or by applying
+//! `BodyLimit` to the scope. The request whose body exceeds the limit is rejected
+//! with `413 Payload Too Large`.
 
 use {
     super::Extractor,
     crate::{
         error::Error,
-        future::{Poll, TryFuture},
+        future::TryFuture,
         input::{
-            body::{ReadAll, RequestBody},
+            body::{PayloadTooLarge, ReadAll, RequestBody},
             header::ContentType,
             localmap::LocalData,
+            multipart::{Form, Multipart, MultipartError},
             Input,
         },
     },
     bytes::Bytes,
-    futures01::Future,
+    flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder},
+    http::header::{HeaderMap, CONTENT_ENCODING, CONTENT_LENGTH},
     mime::Mime,
     serde::de::DeserializeOwned,
-    std::{marker::PhantomData, str},
+    std::{
+        fmt,
+        io::{self, Read},
+        marker::PhantomData,
+        path::PathBuf,
+        str,
+    },
 };
 
+/// The maximum size of the request body after decompression, in bytes,
+/// used when the limit of request body is not specified.
+const MAX_DECOMPRESSED_SIZE: u64 = 10 * 1024 * 1024;
+
+/// The default maximum size of each text field in `ParseForm`, in bytes.
+const DEFAULT_TEXT_LIMIT: u64 = 1024 * 1024;
+
 #[derive(Debug, failure::Fail)]
 enum ExtractBodyError {
     #[fail(display = "missing the header field `Content-type`")]
@@ -35,53 +59,217 @@ enum ExtractBodyError {
 
     #[fail(display = "the content of message body is invalid: {}", cause)]
     InvalidContent { cause: failure::Error },
+
+    #[fail(display = "unsupported content coding: {}", encoding)]
+    UnsupportedContentEncoding { encoding: String },
+
+    #[fail(display = "failed to decompress the message body: {}", cause)]
+    Decompress { cause: io::Error },
+
+    #[fail(
+        display = "the decompressed message body is too large (limit: {} bytes)",
+        limit
+    )]
+    DecompressedTooLarge { limit: u64 },
 }
 
-trait Decoder<T> {
-    fn validate_mime(mime: Option<&Mime>) -> Result<(), ExtractBodyError>;
-    fn decode(data: &[u8]) -> Result<T, ExtractBodyError>;
+/// A content coding which can be decoded before parsing the message body.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ContentCoding {
+    Gzip,
+    Deflate,
 }
 
-fn decode<T, D>() -> impl Extractor<
-    Output = (T,),
-    Error = Error,
-    Extract = impl TryFuture<Ok = (T,), Error = Error> + Send + 'static,
->
-where
-    T: 'static,
-    D: Decoder<T> + 'static,
-{
-    #[allow(missing_debug_implementations)]
-    struct Decode<T, D> {
-        _marker: PhantomData<fn(D) -> T>,
+/// Returns the list of content codings applied to the message body, in the order they were applied.
+fn content_codings(headers: &HeaderMap) -> Result<Vec<ContentCoding>, Error> {
+    let mut codings = vec![];
+    for h in headers.get_all(CONTENT_ENCODING) {
+        let h = h.to_str().map_err(crate::error::bad_request)?;
+        for name in h.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match &*name.to_ascii_lowercase() {
+                "identity" => {}
+                "gzip" | "x-gzip" => codings.push(ContentCoding::Gzip),
+                "deflate" => codings.push(ContentCoding::Deflate),
+                _ => {
+                    return Err(crate::error::unsupported_media_type(
+                        ExtractBodyError::UnsupportedContentEncoding {
+                            encoding: name.to_owned(),
+                        },
+                    ));
+                }
+            }
+        }
     }
+    Ok(codings)
+}
 
-    impl<T, D> Extractor for Decode<T, D>
-    where
-        D: Decoder<T>,
-    {
-        type Output = (T,);
-        type Error = Error;
-        type Extract = DecodeFuture<T, D>;
-
-        fn extract(&self) -> Self::Extract {
-            DecodeFuture {
-                state: State::Init,
-                _marker: PhantomData,
+/// Decodes the message body according to the content codings.
+///
+/// The size of the decoded data is limited to `limit` in order to
+/// protect against the decompression bombs.
+fn decompress(mut data: Bytes, codings: &[ContentCoding], limit: u64) -> Result<Bytes, Error> {
+    for &coding in codings.iter().rev() {
+        let reader: Box<dyn Read> = match coding {
+            ContentCoding::Gzip => Box::new(GzDecoder::new(&data[..])),
+            // Some clients send the raw DEFLATE stream without the zlib wrapper.
+            ContentCoding::Deflate if is_zlib_header(&data) => {
+                Box::new(ZlibDecoder::new(&data[..]))
             }
+            ContentCoding::Deflate => Box::new(DeflateDecoder::new(&data[..])),
+        };
+
+        let mut decoded = vec![];
+        reader
+            .take(limit + 1)
+            .read_to_end(&mut decoded)
+            .map_err(|cause| crate::error::bad_request(ExtractBodyError::Decompress { cause }))?;
+        if decoded.len() as u64 > limit {
+            return Err(crate::error::payload_too_large(
+                ExtractBodyError::DecompressedTooLarge { limit },
+            ));
+        }
+
+        data = decoded.into();
+    }
+    Ok(data)
+}
+
+fn is_zlib_header(data: &[u8]) -> bool {
+    match data {
+        [cmf, flg, ..] => cmf & 0x0F == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
+
+/// Takes the request body from the context and starts to read it.
+///
+/// The request is rejected without receiving the body if the value of `Content-Length`
+/// exceeds the limit.
+fn start_read_all(
+    input: &mut Input<'_>,
+    limit: Option<u64>,
+) -> Result<(ReadAll, Option<u64>), Error> {
+    let body = RequestBody::take_from(input.locals).ok_or_else(stolen_payload)?;
+    let limit = limit.or_else(|| body.limit());
+
+    let read_all = match limit {
+        Some(limit) => {
+            check_content_length(input, limit)?;
+            body.read_all().limit(limit)
+        }
+        None => body.read_all(),
+    };
+
+    Ok((read_all, limit))
+}
+
+/// Rejects the request if the value of `Content-Length` exceeds the limit.
+fn check_content_length(input: &Input<'_>, limit: u64) -> Result<(), Error> {
+    let content_length = input
+        .request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.parse::<u64>().ok());
+    if content_length.map_or(false, |len| len > limit) {
+        return Err(PayloadTooLarge::new(limit).into());
+    }
+    Ok(())
+}
+
+mod decoder {
+    use {crate::error::Error, bytes::Bytes, mime::Mime};
+
+    pub trait Decoder<T> {
+        fn validate_mime(mime: Option<&Mime>) -> Result<(), Error>;
+        fn decode(data: Bytes) -> Result<T, Error>;
+    }
+}
+
+/// An `Extractor` that reads the entire of request body and decodes it into `T`.
+///
+/// The value of this type is returned from `plain`, `json`, `urlencoded` and `read_all`.
+/// These functions returned `impl Extractor` until 0.5, and the concrete type is exposed
+/// instead so that the limit of request body can be configured by `Decode::limit`.
+pub struct Decode<T, D> {
+    limit: Option<u64>,
+    _marker: PhantomData<fn(D) -> T>,
+}
+
+impl<T, D> fmt::Debug for Decode<T, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Decode")
+            .field("limit", &self.limit)
+            .finish()
+    }
+}
+
+impl<T, D> Decode<T, D> {
+    fn new() -> Self {
+        Self {
+            limit: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sets the maximum size of request body, in bytes.
+    ///
+    /// This value takes precedence over the limit specified by `BodyLimit`.
+    /// The limit is also applied to the size of decompressed data.
+    pub fn limit(self, limit: u64) -> Self {
+        Self {
+            limit: Some(limit),
+            ..self
+        }
+    }
+}
+
+impl<T, D> Extractor for Decode<T, D>
+where
+    D: self::decoder::Decoder<T>,
+{
+    type Output = (T,);
+    type Error = Error;
+    type Extract = self::decode::DecodeFuture<T, D>; // private
+
+    fn extract(&self) -> Self::Extract {
+        self::decode::DecodeFuture {
+            state: self::decode::State::Init,
+            limit: self.limit,
+            _marker: PhantomData,
         }
     }
+}
+
+mod decode {
+    use {
+        super::{
+            content_codings, decoder::Decoder, decompress, start_read_all, ContentCoding,
+            MAX_DECOMPRESSED_SIZE,
+        },
+        crate::{
+            error::Error,
+            fs::blocking_io,
+            future::{Poll, TryFuture},
+            input::{body::ReadAll, header::ContentType, Input},
+        },
+        bytes::Bytes,
+        futures01::Future,
+        std::marker::PhantomData,
+    };
 
     #[allow(missing_debug_implementations)]
-    enum State {
+    pub(super) enum State {
         Init,
-        ReadAll(ReadAll),
+        ReadAll(ReadAll, Vec<ContentCoding>, u64),
+        Decompress(Bytes, Vec<ContentCoding>, u64),
     }
 
     #[allow(missing_debug_implementations)]
-    struct DecodeFuture<T, D> {
-        state: State,
-        _marker: PhantomData<fn(D) -> T>,
+    pub struct DecodeFuture<T, D> {
+        pub(super) state: State,
+        pub(super) limit: Option<u64>,
+        pub(super) _marker: PhantomData<fn(D) -> T>,
     }
 
     impl<T, D> TryFuture for DecodeFuture<T, D>
@@ -96,168 +284,539 @@ where
                 self.state = match self.state {
                     State::Init => {
                         let mime_opt = crate::input::header::parse::<ContentType>(input)?;
-                        D::validate_mime(mime_opt).map_err(crate::error::bad_request)?;
-                        RequestBody::take_from(input.locals)
-                            .map(|body| State::ReadAll(body.read_all()))
-                            .ok_or_else(stolen_payload)?
+                        D::validate_mime(mime_opt)?;
+                        let codings = content_codings(input.request.headers())?;
+                        let (read_all, limit) = start_read_all(input, self.limit)?;
+                        State::ReadAll(read_all, codings, limit.unwrap_or(MAX_DECOMPRESSED_SIZE))
                     }
-                    State::ReadAll(ref mut read_all) => {
+                    State::ReadAll(ref mut read_all, ref mut codings, limit) => {
                         let data = futures01::try_ready!(read_all.poll());
-                        return D::decode(&*data)
-                            .map(|out| (out,).into())
-                            .map_err(crate::error::bad_request);
+                        if codings.is_empty() {
+                            return D::decode(data).map(|out| (out,).into());
+                        }
+                        State::Decompress(data, std::mem::replace(codings, vec![]), limit)
+                    }
+                    State::Decompress(ref data, ref codings, limit) => {
+                        // The decompression is CPU-bound, so it is performed
+                        // on the blocking pool rather than on the I/O thread.
+                        let data = futures01::try_ready!(blocking_io(|| Ok(decompress(
+                            data.clone(),
+                            codings,
+                            limit
+                        ))))?;
+                        return D::decode(data).map(|out| (out,).into());
                     }
                 };
             }
         }
     }
-
-    Decode::<T, D> {
-        _marker: PhantomData,
-    }
 }
 
-/// Creates an `Extractor` that parses the entire of request body into `T` as a plain text.
-pub fn plain<T>() -> impl Extractor<
-    Output = (T,),
-    Error = Error,
-    Extract = impl TryFuture<Ok = (T,), Error = Error> + Send + 'static,
->
+/// The decoder that parses the request body as a plain text.
+#[derive(Debug)]
+pub struct PlainTextDecoder(());
+
+impl<T> self::decoder::Decoder<T> for PlainTextDecoder
 where
-    T: DeserializeOwned + 'static,
+    T: DeserializeOwned,
 {
-    #[allow(missing_debug_implementations)]
-    struct PlainTextDecoder(());
-
-    impl<T> Decoder<T> for PlainTextDecoder
-    where
-        T: DeserializeOwned,
-    {
-        fn validate_mime(mime: Option<&Mime>) -> Result<(), ExtractBodyError> {
-            if let Some(mime) = mime {
-                if mime.type_() != mime::TEXT || mime.subtype() != mime::PLAIN {
-                    return Err(ExtractBodyError::UnexpectedContentType {
+    fn validate_mime(mime: Option<&Mime>) -> Result<(), Error> {
+        if let Some(mime) = mime {
+            if mime.type_() != mime::TEXT || mime.subtype() != mime::PLAIN {
+                return Err(crate::error::bad_request(
+                    ExtractBodyError::UnexpectedContentType {
                         expected: "text/plain",
-                    });
-                }
-                if let Some(charset) = mime.get_param("charset") {
-                    if charset != "utf-8" {
-                        return Err(ExtractBodyError::NotUtf8Charset);
-                    }
+                    },
+                ));
+            }
+            if let Some(charset) = mime.get_param("charset") {
+                if charset != "utf-8" {
+                    return Err(crate::error::bad_request(ExtractBodyError::NotUtf8Charset));
                 }
             }
-            Ok(())
         }
+        Ok(())
+    }
+
+    fn decode(data: Bytes) -> Result<T, Error> {
+        let s = str::from_utf8(&*data) //
+            .map_err(|cause| invalid_content(cause.into()))?;
+        serde_plain::from_str(s) //
+            .map_err(|cause| invalid_content(cause.into()))
+    }
+}
+
+/// The decoder that parses the request body as JSON data.
+#[derive(Debug)]
+pub struct JsonDecoder(());
+
+impl<T> self::decoder::Decoder<T> for JsonDecoder
+where
+    T: DeserializeOwned,
+{
+    fn validate_mime(mime: Option<&Mime>) -> Result<(), Error> {
+        let mime =
+            mime.ok_or_else(|| crate::error::bad_request(ExtractBodyError::MissingContentType))?;
+        if *mime != mime::APPLICATION_JSON {
+            return Err(crate::error::bad_request(
+                ExtractBodyError::UnexpectedContentType {
+                    expected: "application/json",
+                },
+            ));
+        }
+        Ok(())
+    }
 
-        fn decode(data: &[u8]) -> Result<T, ExtractBodyError> {
-            let s = str::from_utf8(&*data) //
-                .map_err(|cause| ExtractBodyError::InvalidContent {
-                    cause: cause.into(),
-                })?;
-            serde_plain::from_str(s) //
-                .map_err(|cause| ExtractBodyError::InvalidContent {
-                    cause: cause.into(),
-                })
+    fn decode(data: Bytes) -> Result<T, Error> {
+        serde_json::from_slice(&*data).map_err(|cause| invalid_content(cause.into()))
+    }
+}
+
+/// The decoder that parses the request body as url-encoded data.
+#[derive(Debug)]
+pub struct UrlencodedDecoder(());
+
+impl<T> self::decoder::Decoder<T> for UrlencodedDecoder
+where
+    T: DeserializeOwned,
+{
+    fn validate_mime(mime: Option<&Mime>) -> Result<(), Error> {
+        let mime =
+            mime.ok_or_else(|| crate::error::bad_request(ExtractBodyError::MissingContentType))?;
+        if *mime != mime::APPLICATION_WWW_FORM_URLENCODED {
+            return Err(crate::error::bad_request(
+                ExtractBodyError::UnexpectedContentType {
+                    expected: "application/x-www-form-urlencoded",
+                },
+            ));
         }
+        Ok(())
+    }
+
+    fn decode(data: Bytes) -> Result<T, Error> {
+        serde_urlencoded::from_bytes(&*data).map_err(|cause| invalid_content(cause.into()))
+    }
+}
+
+/// The decoder that returns the request body as it is.
+#[derive(Debug)]
+pub struct RawDecoder(());
+
+impl self::decoder::Decoder<Bytes> for RawDecoder {
+    fn validate_mime(_: Option<&Mime>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn decode(data: Bytes) -> Result<Bytes, Error> {
+        Ok(data)
     }
+}
 
-    decode::<T, PlainTextDecoder>()
+fn invalid_content(cause: failure::Error) -> Error {
+    crate::error::bad_request(ExtractBodyError::InvalidContent { cause })
+}
+
+/// Creates an `Extractor` that parses the entire of request body into `T` as a plain text.
+pub fn plain<T>() -> Decode<T, PlainTextDecoder>
+where
+    T: DeserializeOwned + 'static,
+{
+    Decode::new()
 }
 
 /// Creates an `Extractor` that parses the entire of request body into `T` as JSON data.
-pub fn json<T>() -> impl Extractor<
-    Output = (T,),
-    Error = Error,
-    Extract = impl TryFuture<Ok = (T,), Error = Error> + Send + 'static,
->
+pub fn json<T>() -> Decode<T, JsonDecoder>
 where
     T: DeserializeOwned + 'static,
 {
-    #[allow(missing_debug_implementations)]
-    struct JsonDecoder(());
+    Decode::new()
+}
+
+/// Creates an `Extractor` that parses the entire of request body into `T` as url-encoded data.
+pub fn urlencoded<T>() -> Decode<T, UrlencodedDecoder>
+where
+    T: DeserializeOwned + 'static,
+{
+    Decode::new()
+}
+
+/// Creates an extractor that reads the entire of request body as a single byte sequence.
+///
+/// If the request body is compressed with `gzip` or `deflate`, the returned data is decompressed
+/// according to the header field `Content-Encoding`.
+pub fn read_all() -> Decode<Bytes, RawDecoder> {
+    Decode::new()
+}
+
+/// Creates an `Extractor` that parses the request body as `multipart/form-data`.
+///
+/// The extracted value is a `Stream` that yields the parts of the request body.
+/// Use `ParseMultipart::form` to receive the text fields and the uploaded files at once.
+pub fn multipart() -> ParseMultipart {
+    ParseMultipart::default()
+}
+
+/// An `Extractor` that parses the request body as `multipart/form-data`.
+#[derive(Debug, Default, Clone)]
+pub struct ParseMultipart {
+    part_limit: Option<u64>,
+    total_limit: Option<u64>,
+}
+
+impl ParseMultipart {
+    /// Sets the maximum size of each part, in bytes.
+    pub fn part_limit(self, limit: u64) -> Self {
+        Self {
+            part_limit: Some(limit),
+            ..self
+        }
+    }
+
+    /// Sets the maximum size of the entire request body, in bytes.
+    ///
+    /// If this value is not specified, the limit set by `BodyLimit` is used.
+    pub fn total_limit(self, limit: u64) -> Self {
+        Self {
+            total_limit: Some(limit),
+            ..self
+        }
+    }
 
-    impl<T> Decoder<T> for JsonDecoder
+    /// Converts itself into an `Extractor` that deserializes the text fields into `T`
+    /// and spools the file parts into the temporary files.
+    pub fn form<T>(self) -> ParseForm<T>
     where
         T: DeserializeOwned,
     {
-        fn validate_mime(mime: Option<&Mime>) -> Result<(), ExtractBodyError> {
-            let mime = mime.ok_or_else(|| ExtractBodyError::MissingContentType)?;
-            if *mime != mime::APPLICATION_JSON {
-                return Err(ExtractBodyError::UnexpectedContentType {
-                    expected: "application/json",
-                });
+        ParseForm {
+            config: self,
+            temp_dir: None,
+            text_limit: DEFAULT_TEXT_LIMIT,
+            _marker: PhantomData,
+        }
+    }
+
+    fn start(&self, input: &mut Input<'_>) -> Result<Multipart, Error> {
+        let boundary = {
+            let mime = crate::input::header::parse::<ContentType>(input)?
+                .ok_or_else(|| crate::error::bad_request(ExtractBodyError::MissingContentType))?;
+            if mime.type_() != mime::MULTIPART || mime.subtype() != mime::FORM_DATA {
+                return Err(crate::error::bad_request(
+                    ExtractBodyError::UnexpectedContentType {
+                        expected: "multipart/form-data",
+                    },
+                ));
             }
-            Ok(())
+            mime.get_param(mime::BOUNDARY)
+                .ok_or_else(|| MultipartError::MissingBoundary)?
+                .as_str()
+                .to_owned()
+        };
+
+        let body = RequestBody::take_from(input.locals).ok_or_else(stolen_payload)?;
+        let total_limit = self.total_limit.or_else(|| body.limit());
+
+        let mut multipart = Multipart::new(body, &boundary);
+        if let Some(limit) = total_limit {
+            check_content_length(input, limit)?;
+            multipart = multipart.total_limit(limit);
+        }
+        if let Some(limit) = self.part_limit {
+            multipart = multipart.part_limit(limit);
+        }
+        Ok(multipart)
+    }
+}
+
+impl Extractor for ParseMultipart {
+    type Output = (Multipart,);
+    type Error = Error;
+    type Extract = self::parse_multipart::ParseMultipartFuture; // private
+
+    fn extract(&self) -> Self::Extract {
+        self::parse_multipart::ParseMultipartFuture {
+            config: self.clone(),
         }
+    }
+}
+
+mod parse_multipart {
+    use {
+        super::ParseMultipart,
+        crate::{
+            error::Error,
+            future::{Poll, TryFuture},
+            input::{multipart::Multipart, Input},
+        },
+    };
+
+    #[allow(missing_debug_implementations)]
+    pub struct ParseMultipartFuture {
+        pub(super) config: ParseMultipart,
+    }
 
-        fn decode(data: &[u8]) -> Result<T, ExtractBodyError> {
-            serde_json::from_slice(&*data).map_err(|cause| ExtractBodyError::InvalidContent {
-                cause: cause.into(),
-            })
+    impl TryFuture for ParseMultipartFuture {
+        type Ok = (Multipart,);
+        type Error = Error;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            self.config
+                .start(input)
+                .map(|multipart| (multipart,).into())
         }
     }
+}
 
-    decode::<T, JsonDecoder>()
+/// An `Extractor` that parses the request body as `multipart/form-data` and
+/// collects its parts into a `Form<T>`.
+///
+/// The text fields are deserialized into `T` in the same manner as `urlencoded`,
+/// and the file parts are spooled into the temporary files.
+pub struct ParseForm<T> {
+    config: ParseMultipart,
+    temp_dir: Option<PathBuf>,
+    text_limit: u64,
+    _marker: PhantomData<fn() -> T>,
 }
 
-/// Creates an `Extractor` that parses the entire of request body into `T` as url-encoded data.
-pub fn urlencoded<T>() -> impl Extractor<
-    Output = (T,),
-    Error = Error,
-    Extract = impl TryFuture<Ok = (T,), Error = Error> + Send + 'static,
->
+impl<T> fmt::Debug for ParseForm<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParseForm")
+            .field("config", &self.config)
+            .field("temp_dir", &self.temp_dir)
+            .field("text_limit", &self.text_limit)
+            .finish()
+    }
+}
+
+impl<T> ParseForm<T> {
+    /// Sets the directory where the uploaded files are spooled.
+    ///
+    /// By default, the value of `std::env::temp_dir()` is used.
+    pub fn temp_dir(self, temp_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            temp_dir: Some(temp_dir.into()),
+            ..self
+        }
+    }
+
+    /// Sets the maximum size of each text field, in bytes.
+    ///
+    /// The text fields are buffered in memory, and hence this limit is applied
+    /// regardless of `ParseMultipart::part_limit`. The default value is 1 MiB.
+    pub fn text_limit(self, limit: u64) -> Self {
+        Self {
+            text_limit: limit,
+            ..self
+        }
+    }
+}
+
+impl<T> Extractor for ParseForm<T>
 where
-    T: DeserializeOwned + 'static,
+    T: DeserializeOwned,
 {
+    type Output = (Form<T>,);
+    type Error = Error;
+    type Extract = self::parse_form::ParseFormFuture<T>; // private
+
+    fn extract(&self) -> Self::Extract {
+        self::parse_form::ParseFormFuture {
+            config: self.config.clone(),
+            temp_dir: self.temp_dir.clone().unwrap_or_else(std::env::temp_dir),
+            text_limit: self.text_limit,
+            multipart: None,
+            current: None,
+            fields: vec![],
+            files: vec![],
+            _marker: PhantomData,
+        }
+    }
+}
+
+mod parse_form {
+    use {
+        super::{invalid_content, ParseMultipart},
+        crate::{
+            error::Error,
+            fs::blocking_io,
+            future::{Poll, TryFuture},
+            input::{
+                body::PayloadTooLarge,
+                multipart::{Form, Multipart, MultipartError, Part, UploadedFile},
+                Input,
+            },
+        },
+        bytes::{Bytes, BytesMut},
+        futures01::Stream,
+        serde::de::DeserializeOwned,
+        std::{
+            collections::hash_map::RandomState,
+            fs::{File, OpenOptions},
+            hash::{BuildHasher, Hasher},
+            io::Write,
+            marker::PhantomData,
+            mem,
+            path::PathBuf,
+        },
+    };
+
+    #[allow(missing_debug_implementations)]
+    pub enum Sink {
+        Text(BytesMut),
+        File {
+            file: Option<File>,
+            uploaded: UploadedFile,
+            pending: Option<Bytes>,
+        },
+    }
+
     #[allow(missing_debug_implementations)]
-    struct UrlencodedDecoder(());
+    pub struct ParseFormFuture<T> {
+        pub(super) config: ParseMultipart,
+        pub(super) temp_dir: PathBuf,
+        pub(super) text_limit: u64,
+        pub(super) multipart: Option<Multipart>,
+        pub(super) current: Option<(Part, Sink)>,
+        pub(super) fields: Vec<(String, String)>,
+        pub(super) files: Vec<UploadedFile>,
+        pub(super) _marker: PhantomData<fn() -> T>,
+    }
 
-    impl<T> Decoder<T> for UrlencodedDecoder
+    impl<T> ParseFormFuture<T>
     where
         T: DeserializeOwned,
     {
-        fn validate_mime(mime: Option<&Mime>) -> Result<(), ExtractBodyError> {
-            let mime = mime.ok_or_else(|| ExtractBodyError::MissingContentType)?;
-            if *mime != mime::APPLICATION_WWW_FORM_URLENCODED {
-                return Err(ExtractBodyError::UnexpectedContentType {
-                    expected: "application/x-www-form-urlencoded",
-                });
+        fn start_part(&self, part: Part) -> Result<(Part, Sink), Error> {
+            let name = part
+                .name()
+                .ok_or_else(|| MultipartError::Malformed("missing the field name"))?
+                .to_owned();
+
+            let sink = match part.filename() {
+                Some(filename) => {
+                    let path = self.temp_dir.join(format!(
+                        "tsukuyomi-upload-{:016x}",
+                        RandomState::new().build_hasher().finish()
+                    ));
+                    Sink::File {
+                        file: None,
+                        uploaded: UploadedFile::new(
+                            name,
+                            filename.to_owned(),
+                            part.content_type(),
+                            path,
+                        ),
+                        pending: None,
+                    }
+                }
+                None => Sink::Text(BytesMut::new()),
+            };
+
+            Ok((part, sink))
+        }
+
+        fn finish_part(&mut self, part: Part, sink: Sink) -> Result<(), Error> {
+            match sink {
+                Sink::Text(buf) => {
+                    let value = String::from_utf8(buf.to_vec())
+                        .map_err(|cause| invalid_content(cause.into()))?;
+                    let name = part.name().unwrap_or("").to_owned();
+                    self.fields.push((name, value));
+                }
+                Sink::File { uploaded, .. } => self.files.push(uploaded),
             }
             Ok(())
         }
 
-        fn decode(data: &[u8]) -> Result<T, ExtractBodyError> {
-            serde_urlencoded::from_bytes(&*data).map_err(|cause| ExtractBodyError::InvalidContent {
-                cause: cause.into(),
-            })
+        fn finish(&mut self) -> Result<Form<T>, Error> {
+            let encoded = url::form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(&self.fields)
+                .finish();
+            let fields = serde_urlencoded::from_str(&encoded)
+                .map_err(|cause| invalid_content(cause.into()))?;
+            Ok(Form::new(fields, mem::replace(&mut self.files, vec![])))
         }
     }
 
-    decode::<T, UrlencodedDecoder>()
-}
+    impl<T> TryFuture for ParseFormFuture<T>
+    where
+        T: DeserializeOwned,
+    {
+        type Ok = (Form<T>,);
+        type Error = Error;
 
-/// Creates an extractor that reads the entire of request body as a single byte sequence.
-pub fn read_all() -> impl Extractor<
-    Output = (Bytes,),
-    Error = Error,
-    Extract = impl TryFuture<Ok = (Bytes,), Error = Error> + Send + 'static,
-> {
-    super::extract(|| {
-        let mut read_all: Option<ReadAll> = None;
-        crate::future::poll_fn(move |input| loop {
-            if let Some(ref mut read_all) = read_all {
-                return read_all
-                    .poll()
-                    .map(|x| x.map(|chunk| (chunk,)))
-                    .map_err(Into::into);
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            loop {
+                let multipart = match self.multipart {
+                    Some(ref mut multipart) => multipart,
+                    None => {
+                        self.multipart = Some(self.config.start(input)?);
+                        continue;
+                    }
+                };
+
+                let done = match self.current {
+                    Some((ref mut part, Sink::Text(ref mut buf))) => {
+                        match futures01::try_ready!(part.poll()) {
+                            Some(chunk) => {
+                                if (buf.len() + chunk.len()) as u64 > self.text_limit {
+                                    return Err(PayloadTooLarge::new(self.text_limit).into());
+                                }
+                                buf.extend_from_slice(&chunk);
+                                false
+                            }
+                            None => true,
+                        }
+                    }
+
+                    Some((
+                        ref mut part,
+                        Sink::File {
+                            ref mut file,
+                            ref mut uploaded,
+                            ref mut pending,
+                        },
+                    )) => {
+                        if file.is_none() {
+                            let path = uploaded.path();
+                            *file = Some(futures01::try_ready!(blocking_io(|| {
+                                OpenOptions::new().write(true).create_new(true).open(path)
+                            })));
+                        }
+
+                        if let Some(ref chunk) = *pending {
+                            let file = file.as_mut().expect("the file should be opened");
+                            futures01::try_ready!(blocking_io(|| file.write_all(chunk)));
+                            uploaded.add_size(chunk.len() as u64);
+                        }
+                        *pending = None;
+
+                        match futures01::try_ready!(part.poll()) {
+                            Some(chunk) => {
+                                *pending = Some(chunk);
+                                false
+                            }
+                            None => true,
+                        }
+                    }
+
+                    None => match futures01::try_ready!(multipart.poll()) {
+                        Some(part) => {
+                            self.current = Some(self.start_part(part)?);
+                            continue;
+                        }
+                        None => return self.finish().map(|form| (form,).into()),
+                    },
+                };
+
+                if done {
+                    let (part, sink) = self.current.take().expect("the part should be available");
+                    self.finish_part(part, sink)?;
+                }
             }
-            read_all = Some(
-                RequestBody::take_from(input.locals)
-                    .ok_or_else(stolen_payload)?
-                    .read_all(),
-            );
-        })
-    })
+        }
+    }
 }
 
 /// Creates an `Extractor` that takes the raw instance of request body.
@@ -278,3 +837,98 @@ pub fn stream() -> impl Extractor<
 fn stolen_payload() -> crate::error::Error {
     crate::error::internal_server_error("The instance of raw RequestBody has already stolen.")
 }
+
+/// A `ModifyHandler` that sets the maximum size of request body in the scope.
+///
+/// The limit is applied to the body extractors and `ReadAll` created from
+/// the request body, unless the extractor specifies its own limit.
+#[derive(Debug, Copy, Clone)]
+pub struct BodyLimit {
+    limit: u64,
+}
+
+impl BodyLimit {
+    /// Creates a `BodyLimit` with the specified maximum size in bytes.
+    pub fn new(limit: u64) -> Self {
+        Self { limit }
+    }
+}
+
+mod impl_modify_handler_for_body_limit {
+    use {
+        super::BodyLimit,
+        crate::{
+            future::{Poll, TryFuture},
+            handler::{metadata::Metadata, Handler, ModifyHandler},
+            input::{body::RequestBody, localmap::LocalData, Input},
+        },
+    };
+
+    impl<H> ModifyHandler<H> for BodyLimit
+    where
+        H: Handler,
+    {
+        type Output = H::Output;
+        type Error = H::Error;
+        type Handler = BodyLimitHandler<H>;
+
+        fn modify(&self, handler: H) -> Self::Handler {
+            BodyLimitHandler {
+                handler,
+                limit: self.limit,
+            }
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct BodyLimitHandler<H> {
+        handler: H,
+        limit: u64,
+    }
+
+    impl<H> Handler for BodyLimitHandler<H>
+    where
+        H: Handler,
+    {
+        type Output = H::Output;
+        type Error = H::Error;
+        type Handle = BodyLimitHandle<H::Handle>;
+
+        #[inline]
+        fn handle(&self) -> Self::Handle {
+            BodyLimitHandle {
+                handle: self.handler.handle(),
+                limit: Some(self.limit),
+            }
+        }
+
+        #[inline]
+        fn metadata(&self) -> Metadata {
+            self.handler.metadata()
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct BodyLimitHandle<H> {
+        handle: H,
+        limit: Option<u64>,
+    }
+
+    impl<H> TryFuture for BodyLimitHandle<H>
+    where
+        H: TryFuture,
+    {
+        type Ok = H::Ok;
+        type Error = H::Error;
+
+        #[inline]
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            if let Some(limit) = self.limit.take() {
+                if let Some(body) = RequestBody::get_mut(input.locals) {
+                    body.set_limit(limit);
+                }
+            }
+            self.handle.poll_ready(input)
+        }
+    }
+}
@@ -83,6 +83,7 @@ mod chain {
             error::Error,
             future::{Async, MaybeDone, Poll, TryFuture},
             generic::{Combine, Tuple},
+            handler::metadata::RequiredStates,
             input::Input,
             util::Chain,
         },
@@ -104,6 +105,12 @@ mod chain {
                 right: MaybeDone::Pending(self.right.extract()),
             }
         }
+
+        fn required_states(&self) -> RequiredStates {
+            let left = self.left.required_states();
+            let right = self.right.required_states();
+            left.merge(right)
+        }
     }
 
     #[allow(missing_debug_implementations)]
@@ -329,6 +336,7 @@ mod map {
         extractor::Extractor,
         future::{Poll, TryFuture},
         generic::{Func, Tuple},
+        handler::metadata::RequiredStates,
         input::Input,
     };
 
@@ -355,6 +363,10 @@ mod map {
                 f: self.f.clone(),
             }
         }
+
+        fn required_states(&self) -> RequiredStates {
+            self.extractor.required_states()
+        }
     }
 
     #[allow(missing_debug_implementations)]
@@ -385,6 +397,7 @@ mod map_err {
         extractor::Extractor,
         future::{Poll, TryFuture},
         generic::Tuple,
+        handler::metadata::RequiredStates,
         input::Input,
     };
 
@@ -411,6 +424,10 @@ mod map_err {
                 f: self.f.clone(),
             }
         }
+
+        fn required_states(&self) -> RequiredStates {
+            self.extractor.required_states()
+        }
     }
 
     #[allow(missing_debug_implementations)]
@@ -2,7 +2,12 @@
 
 use {
     super::Extractor,
-    crate::{error::Error, future::TryFuture, input::header::HeaderField, util::Never},
+    crate::{
+        error::Error,
+        future::TryFuture,
+        input::header::{FromHeaderValue, HeaderField},
+        util::Never,
+    },
     http::header::{HeaderMap, HeaderName, HeaderValue},
 };
 
@@ -24,6 +29,29 @@ where
     })
 }
 
+/// Creates an `Extractor` that parses the header field with the specified name.
+///
+/// This extractor is used for the header fields without the predefined `HeaderName`,
+/// such as `X-Forwarded-For`. Unlike `parse`, the parsed value is not cached.
+pub fn parse_as<T>(
+    name: HeaderName,
+) -> impl Extractor<
+    Output = (T,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (T,), Error = Error> + Send + 'static,
+>
+where
+    T: FromHeaderValue + Send + 'static,
+{
+    super::ready(move |input| match input.request.headers().get(&name) {
+        Some(h) => T::from_header_value(h).map(|h| (h,)).map_err(Into::into),
+        None => Err(crate::error::bad_request(format!(
+            "missing header field: {}",
+            name
+        ))),
+    })
+}
+
 /// Creates an `Extractor` that checks if a header field equals to the specified value.
 pub fn equals<T>(
     name: HeaderName,
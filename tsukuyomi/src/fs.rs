@@ -6,10 +6,16 @@ use {
         error::Error,
         future::TryFuture,
         handler::{metadata::Uri, ModifyHandler},
-        input::Input,
+        input::{
+            header::{
+                AcceptEncoding, EntityTag, FromHeaderValue, HttpDate, IfModifiedSince, IfNoneMatch,
+                Range,
+            },
+            Input,
+        },
         output::{Responder, ResponseBody},
     },
-    bytes::{BufMut, BytesMut},
+    bytes::{BufMut, Bytes, BytesMut},
     filetime::FileTime,
     futures01::{Async, Poll, Stream},
     http::{
@@ -20,93 +26,108 @@ use {
     mime::Mime,
     std::{
         borrow::Cow,
-        cmp, fmt,
+        cmp,
+        collections::{hash_map::RandomState, VecDeque},
         fs::{File, Metadata},
-        io::{self, Read as _Read},
-        mem,
+        hash::{BuildHasher, Hasher},
+        io::{self, Read as _Read, Seek as _Seek, SeekFrom},
         ops::Deref,
         path::{Path, PathBuf},
-        str::FromStr,
         sync::Arc,
         time::Duration,
     },
-    time::Timespec,
     tokio_threadpool::blocking as poll_blocking,
 };
 
 // ==== headers ====
 
-fn parse_http_date(s: &str) -> Result<Timespec, time::ParseError> {
-    time::strptime(s, "%a, %d %b %Y %T %Z")
-        .or_else(|_| time::strptime(s, "%A, %d-%b-%y %T %Z"))
-        .or_else(|_| time::strptime(s, "%c"))
-        .map(|tm| tm.to_timespec())
+fn etag_from_metadata(metadata: &Metadata) -> EntityTag {
+    let last_modified = FileTime::from_last_modification_time(&metadata);
+    EntityTag::weak(format!(
+        "{:x}-{:x}.{:x}",
+        metadata.len(),
+        last_modified.seconds(),
+        last_modified.nanoseconds()
+    ))
 }
 
-#[derive(Debug)]
-struct ETag {
-    weak: bool,
-    tag: String,
+/// A range of bytes in the file, with the inclusive end position.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct ByteRange {
+    start: u64,
+    end: u64,
 }
 
-impl ETag {
-    fn from_metadata(metadata: &Metadata) -> Self {
-        let last_modified = FileTime::from_last_modification_time(&metadata);
-        Self {
-            weak: true,
-            tag: format!(
-                "{:x}-{:x}.{:x}",
-                metadata.len(),
-                last_modified.seconds(),
-                last_modified.nanoseconds()
-            ),
-        }
+impl ByteRange {
+    fn len(self) -> u64 {
+        self.end - self.start + 1
     }
+}
 
-    fn parse_inner(weak: bool, s: &str) -> Result<Self, failure::Error> {
-        if s.len() < 2 {
-            failure::bail!("");
-        }
-        if !s.starts_with('"') || !s.ends_with('"') {
-            failure::bail!("");
-        }
-
-        let tag = &s[1..s.len() - 1];
-        if !tag.is_ascii() {
-            failure::bail!("");
-        }
-
-        Ok(Self {
-            weak,
-            tag: tag.to_owned(),
-        })
+/// The maximum number of ranges served in a multipart response.
+const MAX_RANGES: usize = 16;
+
+/// Parses the value of `Range` and returns the list of satisfiable ranges.
+///
+/// If the value is malformed or uses an unsupported unit, this function returns
+/// `None` and the header field should be ignored (RFC 7233, section 3.1).
+/// The header field is also ignored if too many ranges remain after the overlapping
+/// or adjacent ones are coalesced, and then the whole file is sent (RFC 7233, section 6.1).
+fn parse_range(s: &str, len: u64) -> Option<Vec<ByteRange>> {
+    let range: Range = s.parse().ok()?;
+    let ranges = range.satisfiable_ranges(len);
+    if ranges.len() > MAX_RANGES {
+        return None;
     }
+    Some(
+        ranges
+            .into_iter()
+            .map(|(start, end)| ByteRange { start, end })
+            .collect(),
+    )
+}
 
-    fn eq(&self, other: &Self) -> bool {
-        self.tag == other.tag && (self.weak || !other.weak)
-    }
+/// A content coding of the precompressed files.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Encoding {
+    Brotli,
+    Gzip,
 }
 
-impl FromStr for ETag {
-    type Err = failure::Error;
+impl Encoding {
+    /// The list of supported encodings, in order of the server's preference.
+    const ALL: [Encoding; 2] = [Encoding::Brotli, Encoding::Gzip];
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.get(0..3) {
-            Some("W/\"") if s[2..].starts_with('"') => Self::parse_inner(true, &s[2..]),
-            Some(t) if t.starts_with('"') => Self::parse_inner(false, s),
-            Some(..) => failure::bail!("invalid string to parse ETag"),
-            None => failure::bail!("empty string to parse ETag"),
+    fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
         }
     }
-}
 
-impl fmt::Display for ETag {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.weak {
-            f.write_str("W/")?;
+    fn extension(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gz",
         }
-        write!(f, "\"{}\"", self.tag)
     }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().cloned().find(|e| e.as_str() == name)
+    }
+}
+
+/// Returns the list of supported encodings acceptable by the client, in order of preference.
+///
+/// The encodings whose quality value is zero and the invalid values of
+/// `Accept-Encoding` are ignored.
+fn acceptable_encodings(headers: &HeaderMap) -> Vec<Encoding> {
+    let available: Vec<&str> = Encoding::ALL.iter().map(|e| e.as_str()).collect();
+    AcceptEncoding::from_headers(headers)
+        .negotiate(&available)
+        .into_iter()
+        .filter_map(Encoding::from_name)
+        .collect()
 }
 
 // ==== Config ====
@@ -124,6 +145,16 @@ pub struct OpenConfig {
     /// If this field is set, the generated HTTP response will include a "Cache-Control" header
     /// that includes the parameter max-age.
     pub max_age: Option<Duration>,
+
+    /// Whether to look up the precompressed files placed next to the original file.
+    ///
+    /// If `true`, the file with the extension `.br` or `.gz` appended (e.g. `foo.js.br`)
+    /// is sent instead of the original one when the client accepts the corresponding
+    /// encoding in `Accept-Encoding`. The header field `Content-Type` is still determined
+    /// from the original file name.
+    ///
+    /// The default value is `false`.
+    pub precompressed: bool,
 }
 
 // ==== NamedFile ====
@@ -185,16 +216,35 @@ where
     type Error = crate::Error;
 
     fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
-        let (file, meta) = futures01::try_ready!(blocking_io(|| {
-            let file = File::open(&self.path)?;
-            let meta = file.metadata()?;
-            Ok((file, meta))
+        let precompressed = self
+            .config
+            .as_ref()
+            .map_or(false, |config| config.precompressed);
+        let encodings = if precompressed {
+            acceptable_encodings(input.request.headers())
+        } else {
+            vec![]
+        };
+
+        let (file, meta, encoding) = futures01::try_ready!(blocking_io(|| {
+            for &encoding in &encodings {
+                let mut path = self.path.as_ref().as_os_str().to_owned();
+                path.push(".");
+                path.push(encoding.extension());
+                match open_file(&path) {
+                    Ok((file, meta)) => return Ok((file, meta, Some(encoding))),
+                    Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+            let (file, meta) = open_file(self.path.as_ref())?;
+            Ok((file, meta, None))
         }));
 
         let config = self.config.take().unwrap_or_default();
 
         let last_modified = FileTime::from_last_modification_time(&meta);
-        let etag = ETag::from_metadata(&meta);
+        let etag = etag_from_metadata(&meta);
 
         let content_type = mime_guess::guess_mime_type(&self.path);
 
@@ -202,6 +252,7 @@ where
             file,
             meta,
             content_type,
+            encoding,
             last_modified,
             etag,
             config,
@@ -212,33 +263,45 @@ where
     }
 }
 
+/// Opens the file at the specified path, and returns it along with its metadata.
+///
+/// If the path does not point to a regular file, it returns an error
+/// whose kind is `NotFound`.
+fn open_file(path: impl AsRef<Path>) -> io::Result<(File, Metadata)> {
+    let file = File::open(path)?;
+    let meta = file.metadata()?;
+    if !meta.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "not a regular file",
+        ));
+    }
+    Ok((file, meta))
+}
+
 #[derive(Debug)]
 struct NamedFileResponse {
     file: File,
     meta: Metadata,
     content_type: Mime,
-    etag: ETag,
+    encoding: Option<Encoding>,
+    etag: EntityTag,
     last_modified: FileTime,
     config: OpenConfig,
 }
 
 impl NamedFileResponse {
-    #[allow(clippy::cast_sign_loss)]
     fn is_modified(&self, headers: &HeaderMap) -> Result<bool, Error> {
         if let Some(h) = headers.get(header::IF_NONE_MATCH) {
             trace!("NamedFile::is_modified(): validate If-None-Match");
 
-            let etag: ETag = h
-                .to_str()
-                .map_err(crate::error::bad_request)?
-                .parse()
-                .map_err(crate::error::bad_request)?;
-            let modified = !etag.eq(&self.etag);
+            let if_none_match = IfNoneMatch::from_header_value(h)?;
+            let modified = !if_none_match.matches(&self.etag);
 
             trace!(
-                "--> self.etag={:?}, etag={:?}, modified={}",
+                "--> self.etag={:?}, if_none_match={:?}, modified={}",
                 self.etag,
-                etag,
+                if_none_match,
                 modified
             );
             return Ok(modified);
@@ -247,15 +310,12 @@ impl NamedFileResponse {
         if let Some(h) = headers.get(header::IF_MODIFIED_SINCE) {
             trace!("NamedFile::is_modified(): validate If-Modified-Since");
 
-            let if_modified_since = {
-                let timespec = parse_http_date(h.to_str().map_err(crate::error::bad_request)?)
-                    .map_err(crate::error::bad_request)?;
-                FileTime::from_unix_time(timespec.sec, timespec.nsec as u32)
-            };
-            let modified = self.last_modified > if_modified_since;
+            let IfModifiedSince(if_modified_since) = IfModifiedSince::from_header_value(h)?;
+            // HTTP-date has the precision of seconds.
+            let modified = self.last_modified.seconds() > if_modified_since.unix_secs();
 
             trace!(
-                "--> if_modified_sicne={:?}, modified={}",
+                "--> if_modified_since={:?}, modified={}",
                 if_modified_since,
                 modified
             );
@@ -272,13 +332,36 @@ impl NamedFileResponse {
         }
     }
 
-    #[allow(clippy::cast_possible_wrap)]
-    fn last_modified(&self) -> Result<String, time::ParseError> {
-        let tm = time::at(Timespec::new(
-            self.last_modified.seconds(),
-            self.last_modified.nanoseconds() as i32,
-        ));
-        time::strftime("%c", &tm)
+    fn last_modified(&self) -> HttpDate {
+        HttpDate::from_unix_secs(self.last_modified.seconds())
+    }
+
+    /// Returns whether the representation is unchanged since the validator
+    /// in `If-Range` was issued.
+    ///
+    /// An invalid `If-Range` is treated as mismatched, and then the entire of
+    /// the file will be sent.
+    fn is_range_applicable(&self, headers: &HeaderMap) -> bool {
+        let h = match headers.get(header::IF_RANGE) {
+            Some(h) => h,
+            None => return true,
+        };
+        trace!("NamedFile::is_range_applicable(): validate If-Range");
+
+        let s = match h.to_str() {
+            Ok(s) => s,
+            Err(..) => return false,
+        };
+
+        // The weak entity tags must not be used in If-Range (RFC 7233, section 3.2).
+        if let Ok(etag) = s.parse::<EntityTag>() {
+            return etag.strong_eq(&self.etag);
+        }
+
+        match s.parse::<HttpDate>() {
+            Ok(date) => date == self.last_modified(),
+            Err(..) => false,
+        }
     }
 
     fn into_response(self, request: &Request<()>) -> Result<Response<ResponseBody>, Error> {
@@ -294,38 +377,144 @@ impl NamedFileResponse {
         // FIXME: optimize
 
         let cache_control = self.cache_control();
-        let last_modified = self
-            .last_modified()
-            .map_err(crate::error::internal_server_error)?;
-        let stream = ReadStream::new(self.file, self.meta, self.config.chunk_size);
+        let last_modified = self.last_modified().to_string();
 
-        Ok(Response::builder()
-            .header(header::CONTENT_TYPE, self.content_type.as_ref())
+        let len = self.meta.len();
+        let ranges = match request.headers().get(header::RANGE) {
+            Some(h) if self.is_range_applicable(request.headers()) => {
+                h.to_str().ok().and_then(|s| parse_range(s, len))
+            }
+            _ => None,
+        };
+
+        let mut response = Response::builder();
+        response
+            .header(header::ACCEPT_RANGES, "bytes")
             .header(header::CACHE_CONTROL, &*cache_control)
             .header(header::LAST_MODIFIED, &*last_modified)
-            .header(header::ETAG, &*self.etag.to_string())
+            .header(header::ETAG, &*self.etag.to_string());
+        if self.config.precompressed {
+            response.header(header::VARY, "accept-encoding");
+        }
+        if let Some(encoding) = self.encoding {
+            response.header(header::CONTENT_ENCODING, encoding.as_str());
+        }
+
+        let segments = match ranges {
+            None => {
+                response.header(header::CONTENT_TYPE, self.content_type.as_ref());
+                vec![Segment::File { offset: 0, len }]
+            }
+
+            Some(ref ranges) if ranges.is_empty() => {
+                trace!("--> the requested range is not satisfiable");
+                return Ok(response
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(header::CONTENT_RANGE, &*format!("bytes */{}", len))
+                    .body(ResponseBody::empty())
+                    .unwrap());
+            }
+
+            Some(ref ranges) if ranges.len() == 1 => {
+                let range = ranges[0];
+                response
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .header(header::CONTENT_TYPE, self.content_type.as_ref())
+                    .header(
+                        header::CONTENT_RANGE,
+                        &*format!("bytes {}-{}/{}", range.start, range.end, len),
+                    );
+                vec![Segment::File {
+                    offset: range.start,
+                    len: range.len(),
+                }]
+            }
+
+            Some(ranges) => {
+                let boundary = generate_boundary();
+                response.status(StatusCode::PARTIAL_CONTENT).header(
+                    header::CONTENT_TYPE,
+                    &*format!("multipart/byteranges; boundary={}", boundary),
+                );
+
+                let mut segments = Vec::with_capacity(ranges.len() * 2 + 1);
+                for (i, range) in ranges.into_iter().enumerate() {
+                    segments.push(Segment::Bytes(Bytes::from(format!(
+                        "{}--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                        if i > 0 { "\r\n" } else { "" },
+                        boundary,
+                        self.content_type,
+                        range.start,
+                        range.end,
+                        len,
+                    ))));
+                    segments.push(Segment::File {
+                        offset: range.start,
+                        len: range.len(),
+                    });
+                }
+                segments.push(Segment::Bytes(Bytes::from(format!(
+                    "\r\n--{}--\r\n",
+                    boundary
+                ))));
+                segments
+            }
+        };
+
+        let content_length: u64 = segments.iter().map(Segment::len).sum();
+        let stream = ReadStream::new(self.file, self.meta, self.config.chunk_size, segments);
+
+        Ok(response
+            .header(header::CONTENT_LENGTH, &*content_length.to_string())
             .body(ResponseBody::wrap_stream(stream))
             .unwrap())
     }
 }
 
+/// Generates a random string used as the boundary of `multipart/byteranges`.
+fn generate_boundary() -> String {
+    format!("{:016x}", RandomState::new().build_hasher().finish())
+}
+
 // ==== ReadStream ====
 
+/// A part of the response body.
 #[derive(Debug)]
-struct ReadStream(State);
+enum Segment {
+    /// A chunk of bytes sent as it is.
+    Bytes(Bytes),
+
+    /// A range of the file contents.
+    File { offset: u64, len: u64 },
+}
+
+impl Segment {
+    fn len(&self) -> u64 {
+        match *self {
+            Segment::Bytes(ref bytes) => bytes.len() as u64,
+            Segment::File { len, .. } => len,
+        }
+    }
+}
 
 #[derive(Debug)]
-enum State {
-    Reading { file: File, buf_size: usize },
-    Eof,
-    Gone,
+struct ReadStream {
+    file: File,
+    buf_size: usize,
+    pos: u64,
+    segments: VecDeque<Segment>,
 }
 
 impl ReadStream {
-    fn new(file: File, meta: Metadata, buf_size: Option<usize>) -> Self {
+    fn new(file: File, meta: Metadata, buf_size: Option<usize>, segments: Vec<Segment>) -> Self {
         let buf_size = finalize_block_size(buf_size, &meta);
         drop(meta);
-        ReadStream(State::Reading { file, buf_size })
+        ReadStream {
+            file,
+            buf_size,
+            pos: 0,
+            segments: segments.into(),
+        }
     }
 }
 
@@ -333,43 +522,63 @@ impl Stream for ReadStream {
     type Item = izanami::http::body::Data;
     type Error = io::Error;
 
+    #[allow(clippy::cast_possible_truncation)]
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let ReadStream {
+            ref mut file,
+            buf_size,
+            ref mut pos,
+            ref mut segments,
+        } = *self;
+
         loop {
-            match self.0 {
-                State::Reading {
-                    ref mut file,
-                    buf_size,
-                    ..
-                } => {
-                    trace!("ReadStream::poll(): polling on the mode State::Reading");
-
-                    let buf = futures01::try_ready!(blocking_io(|| {
-                        let mut buf = BytesMut::with_capacity(buf_size);
-                        if !buf.has_remaining_mut() {
-                            buf.reserve(buf_size);
-                        }
-                        unsafe {
-                            let n = file.read(buf.bytes_mut())?;
-                            buf.advance_mut(n);
-                        }
-                        Ok(buf)
-                    }));
-
-                    if !buf.is_empty() {
-                        return Ok(Async::Ready(Some(buf.freeze().into())));
-                    }
+            let (offset, len) = match segments.front_mut() {
+                Some(Segment::File { len: 0, .. }) => {
+                    segments.pop_front();
+                    continue;
                 }
-                State::Eof => {
-                    trace!("ReadStream::poll(): polling on the mode State::Reading");
+                Some(Segment::Bytes(..)) => match segments.pop_front() {
+                    Some(Segment::Bytes(bytes)) => return Ok(Async::Ready(Some(bytes.into()))),
+                    _ => unreachable!("unexpected state"),
+                },
+                Some(Segment::File { offset, len }) => (offset, len),
+                None => {
+                    trace!("ReadStream::poll(): reached the end of stream");
                     return Ok(Async::Ready(None));
                 }
-                State::Gone => panic!("unexpected state"),
             };
 
-            match mem::replace(&mut self.0, State::Gone) {
-                State::Reading { .. } => self.0 = State::Eof,
-                _ => unreachable!("unexpected state"),
+            trace!("ReadStream::poll(): read the file (offset={})", *offset);
+
+            let buf_size = cmp::min(buf_size as u64, *len) as usize;
+            let buf = futures01::try_ready!(blocking_io(|| {
+                if *pos != *offset {
+                    file.seek(SeekFrom::Start(*offset))?;
+                    *pos = *offset;
+                }
+                let mut buf = BytesMut::with_capacity(buf_size);
+                if !buf.has_remaining_mut() {
+                    buf.reserve(buf_size);
+                }
+                unsafe {
+                    let n = file.read(&mut buf.bytes_mut()[..buf_size])?;
+                    buf.advance_mut(n);
+                }
+                *pos += buf.len() as u64;
+                Ok(buf)
+            }));
+
+            if buf.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "the file has been truncated during reading",
+                ));
             }
+
+            *offset += buf.len() as u64;
+            *len -= buf.len() as u64;
+
+            return Ok(Async::Ready(Some(buf.freeze().into())));
         }
     }
 }
@@ -377,7 +586,7 @@ impl Stream for ReadStream {
 #[allow(dead_code)]
 const DEFAULT_BUF_SIZE: u64 = 8192;
 
-fn blocking_io<T>(f: impl FnOnce() -> io::Result<T>) -> Poll<T, io::Error> {
+pub(crate) fn blocking_io<T>(f: impl FnOnce() -> io::Result<T>) -> Poll<T, io::Error> {
     match poll_blocking(f) {
         Ok(Async::Ready(ready)) => ready.map(Async::Ready),
         Ok(Async::NotReady) => Ok(Async::NotReady),
@@ -590,3 +799,145 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{acceptable_encodings, parse_range, ByteRange, Encoding, MAX_RANGES},
+        http::header::{self, HeaderMap},
+    };
+
+    #[test]
+    fn parse_range_single() {
+        assert_eq!(
+            parse_range("bytes=0-499", 10000),
+            Some(vec![ByteRange { start: 0, end: 499 }])
+        );
+        assert_eq!(
+            parse_range("bytes=9500-", 10000),
+            Some(vec![ByteRange {
+                start: 9500,
+                end: 9999
+            }])
+        );
+        assert_eq!(
+            parse_range("bytes=-500", 10000),
+            Some(vec![ByteRange {
+                start: 9500,
+                end: 9999
+            }])
+        );
+    }
+
+    #[test]
+    fn parse_range_multi() {
+        assert_eq!(
+            parse_range("bytes=0-0, -1", 10000),
+            Some(vec![
+                ByteRange { start: 0, end: 0 },
+                ByteRange {
+                    start: 9999,
+                    end: 9999
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_range_coalesced() {
+        assert_eq!(
+            parse_range("bytes=500-999, 0-99, 50-199, 200-299", 10000),
+            Some(vec![
+                ByteRange { start: 0, end: 299 },
+                ByteRange {
+                    start: 500,
+                    end: 999
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_range_too_many() {
+        let ranges = (0..=MAX_RANGES)
+            .map(|i| format!("{}-{}", i * 10, i * 10 + 4))
+            .collect::<Vec<_>>()
+            .join(",");
+        assert_eq!(parse_range(&format!("bytes={}", ranges), 10000), None);
+
+        // the ranges are counted after coalesced.
+        let ranges = (0..=MAX_RANGES)
+            .map(|i| format!("{}-{}", i * 10, i * 10 + 9))
+            .collect::<Vec<_>>()
+            .join(",");
+        assert_eq!(
+            parse_range(&format!("bytes={}", ranges), 10000),
+            Some(vec![ByteRange {
+                start: 0,
+                end: MAX_RANGES as u64 * 10 + 9
+            }])
+        );
+    }
+
+    #[test]
+    fn parse_range_truncated() {
+        assert_eq!(
+            parse_range("bytes=500-20000", 1000),
+            Some(vec![ByteRange {
+                start: 500,
+                end: 999
+            }])
+        );
+        assert_eq!(
+            parse_range("bytes=-2000", 1000),
+            Some(vec![ByteRange { start: 0, end: 999 }])
+        );
+    }
+
+    #[test]
+    fn parse_range_unsatisfiable() {
+        assert_eq!(parse_range("bytes=1000-", 1000), Some(vec![]));
+        assert_eq!(parse_range("bytes=-0", 1000), Some(vec![]));
+    }
+
+    #[test]
+    fn parse_range_failcase() {
+        assert_eq!(parse_range("items=0-10", 1000), None);
+        assert_eq!(parse_range("bytes=", 1000), None);
+        assert_eq!(parse_range("bytes=10-5", 1000), None);
+        assert_eq!(parse_range("bytes=a-b", 1000), None);
+        assert_eq!(parse_range("bytes=100", 1000), None);
+    }
+
+    fn accept_encoding(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT_ENCODING, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn acceptable_encodings_preference() {
+        assert_eq!(
+            acceptable_encodings(&accept_encoding("gzip, br")),
+            vec![Encoding::Brotli, Encoding::Gzip]
+        );
+        assert_eq!(
+            acceptable_encodings(&accept_encoding("br;q=0.5, gzip")),
+            vec![Encoding::Gzip, Encoding::Brotli]
+        );
+        assert_eq!(
+            acceptable_encodings(&accept_encoding("deflate, gzip;q=0.8")),
+            vec![Encoding::Gzip]
+        );
+    }
+
+    #[test]
+    fn acceptable_encodings_wildcard() {
+        assert_eq!(
+            acceptable_encodings(&accept_encoding("*;q=0.1, br;q=0")),
+            vec![Encoding::Gzip]
+        );
+        assert_eq!(acceptable_encodings(&accept_encoding("identity")), vec![]);
+        assert_eq!(acceptable_encodings(&HeaderMap::new()), vec![]);
+    }
+}
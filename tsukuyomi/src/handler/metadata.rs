@@ -2,8 +2,8 @@ use {
     crate::util::{Never, TryFrom},
     either::Either,
     http::{header::HeaderValue, HttpTryFrom, Method},
-    indexmap::{indexset, IndexSet},
-    std::iter::FromIterator,
+    indexmap::{indexset, IndexMap, IndexSet},
+    std::{any::TypeId, iter::FromIterator},
 };
 
 pub use crate::uri::Uri;
@@ -153,11 +153,57 @@ impl<'a> IntoIterator for &'a AllowedMethods {
     }
 }
 
+/// A set of the types of application state that a route requires.
+///
+/// Whether the states are registered in the scope of the route is checked when building `App`.
+#[derive(Debug, Clone, Default)]
+pub struct RequiredStates(IndexMap<TypeId, &'static str>);
+
+impl RequiredStates {
+    /// Creates an empty `RequiredStates`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the state of type `T` to this set.
+    pub fn insert<T: 'static>(&mut self) {
+        self.0.insert(TypeId::of::<T>(), type_name::<T>());
+    }
+
+    /// Returns an iterator over the `TypeId`s and the names of the required states,
+    /// in insertion order.
+    ///
+    /// The name is used only for diagnostics, and is `"<unknown>"` on the compilers
+    /// older than Rust 1.38.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a TypeId, &'static str)> + 'a {
+        self.0.iter().map(|(id, &name)| (id, name))
+    }
+
+    /// Returns the union of this set and `right`.
+    ///
+    /// This is used to combine the requirements of the chained extractors or endpoints.
+    pub fn merge(mut self, right: Self) -> Self {
+        self.0.extend(right.0);
+        self
+    }
+}
+
+#[cfg(tsukuyomi_type_name)]
+fn type_name<T>() -> &'static str {
+    std::any::type_name::<T>()
+}
+
+#[cfg(not(tsukuyomi_type_name))]
+fn type_name<T>() -> &'static str {
+    "<unknown>"
+}
+
 /// A set of metadata associated with the certain `Handler`.
 #[derive(Debug, Clone)]
 pub struct Metadata {
     path: Option<Uri>,
     allowed_methods: AllowedMethods,
+    required_states: RequiredStates,
 }
 
 impl Metadata {
@@ -165,6 +211,7 @@ impl Metadata {
         Self {
             path: Some(path),
             allowed_methods: AllowedMethods::any(),
+            required_states: RequiredStates::new(),
         }
     }
 
@@ -172,6 +219,7 @@ impl Metadata {
         Self {
             path: None,
             allowed_methods: AllowedMethods::any(),
+            required_states: RequiredStates::new(),
         }
     }
 
@@ -188,4 +236,14 @@ impl Metadata {
     pub fn allowed_methods_mut(&mut self) -> &mut AllowedMethods {
         &mut self.allowed_methods
     }
+
+    /// Returns a reference to the inner value of `RequiredStates`.
+    pub fn required_states(&self) -> &RequiredStates {
+        &self.required_states
+    }
+
+    /// Returns a mutable reference to the inner value of `RequiredStates`.
+    pub fn required_states_mut(&mut self) -> &mut RequiredStates {
+        &mut self.required_states
+    }
 }
@@ -3,7 +3,9 @@
 pub mod body;
 pub mod header;
 pub mod localmap;
+pub mod multipart;
 pub mod param;
+pub mod query;
 
 use {
     self::{localmap::LocalMap, param::Params},
@@ -87,12 +87,54 @@ impl std::error::Error for Error {
 
 impl HttpError for Error {
     fn status_code(&self) -> StatusCode {
-        StatusCode::INTERNAL_SERVER_ERROR
+        if self.0.is::<PayloadTooLarge>() {
+            StatusCode::PAYLOAD_TOO_LARGE
+        } else {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// An error that represents the request body exceeds the configured limit.
+#[derive(Debug)]
+pub struct PayloadTooLarge {
+    limit: u64,
+}
+
+impl PayloadTooLarge {
+    pub(crate) fn new(limit: u64) -> Self {
+        Self { limit }
+    }
+
+    /// Returns the limit of the request body size, in bytes.
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+}
+
+impl fmt::Display for PayloadTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the request body is too large (limit: {} bytes)",
+            self.limit
+        )
+    }
+}
+
+impl std::error::Error for PayloadTooLarge {}
+
+impl HttpError for PayloadTooLarge {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::PAYLOAD_TOO_LARGE
     }
 }
 
 #[derive(Debug)]
-pub struct RequestBody(RequestBodyInner);
+pub struct RequestBody {
+    inner: RequestBodyInner,
+    limit: Option<u64>,
+}
 
 #[derive(Debug)]
 enum RequestBodyInner {
@@ -103,19 +145,33 @@ enum RequestBodyInner {
 
 impl RequestBody {
     pub fn new(data: impl Into<Bytes>) -> Self {
-        RequestBody(RequestBodyInner::Raw(Some(data.into())))
+        Self::from_inner(RequestBodyInner::Raw(Some(data.into())))
+    }
+
+    fn from_inner(inner: RequestBodyInner) -> Self {
+        RequestBody { inner, limit: None }
+    }
+
+    /// Returns the maximum size of the request body, in bytes, that can be read by `ReadAll`.
+    pub fn limit(&self) -> Option<u64> {
+        self.limit
+    }
+
+    /// Sets the maximum size of the request body, in bytes, that can be read by `ReadAll`.
+    pub fn set_limit(&mut self, limit: impl Into<Option<u64>>) {
+        self.limit = limit.into();
     }
 }
 
 impl From<H1Body> for RequestBody {
     fn from(body: H1Body) -> Self {
-        RequestBody(RequestBodyInner::H1(body))
+        Self::from_inner(RequestBodyInner::H1(body))
     }
 }
 
 impl From<H2Body> for RequestBody {
     fn from(body: H2Body) -> Self {
-        RequestBody(RequestBodyInner::H2(body))
+        Self::from_inner(RequestBodyInner::H2(body))
     }
 }
 
@@ -133,7 +189,7 @@ impl HttpBody for RequestBody {
 
     #[inline]
     fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
-        match self.0 {
+        match self.inner {
             RequestBodyInner::H1(ref mut body) => body
                 .poll_data()
                 .map(|x| x.map(|opt| opt.map(|data| Chunk(ChunkInner::H1(data)))))
@@ -152,6 +208,7 @@ impl HttpBody for RequestBody {
 impl RequestBody {
     pub fn read_all(self) -> ReadAll {
         ReadAll {
+            limit: self.limit,
             body: self,
             acc: BytesMut::new(),
         }
@@ -162,6 +219,20 @@ impl RequestBody {
 pub struct ReadAll {
     body: RequestBody,
     acc: BytesMut,
+    limit: Option<u64>,
+}
+
+impl ReadAll {
+    /// Overrides the maximum size of the request body, in bytes.
+    ///
+    /// When the received data exceeds the limit, the future will return
+    /// an error with the status code `413 Payload Too Large`.
+    pub fn limit(self, limit: u64) -> Self {
+        Self {
+            limit: Some(limit),
+            ..self
+        }
+    }
 }
 
 impl Future for ReadAll {
@@ -170,6 +241,11 @@ impl Future for ReadAll {
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
         while let Some(buf) = futures01::try_ready!(self.body.poll_data()) {
+            if let Some(limit) = self.limit {
+                if (self.acc.len() + buf.remaining()) as u64 > limit {
+                    return Err(Error(Box::new(PayloadTooLarge::new(limit))));
+                }
+            }
             self.acc.extend_from_slice(buf.bytes());
         }
 
@@ -0,0 +1,704 @@
+//! Components for parsing `multipart/form-data` request bodies.
+
+use {
+    super::body::RequestBody,
+    crate::error::{Error, HttpError},
+    bytes::{Buf, Bytes, BytesMut},
+    futures01::{Async, Poll, Stream},
+    http::{
+        header::{HeaderMap, HeaderName, HeaderValue, CONTENT_DISPOSITION, CONTENT_TYPE},
+        StatusCode,
+    },
+    izanami::http::body::HttpBody,
+    mime::Mime,
+    std::{
+        fs, io,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex, MutexGuard},
+    },
+    url::percent_encoding::percent_decode,
+};
+
+/// The maximum size of header section in each part.
+const MAX_HEADERS_SIZE: usize = 8 * 1024;
+
+/// An error that occurs during parsing the multipart body.
+#[derive(Debug, failure::Fail)]
+pub enum MultipartError {
+    #[fail(display = "missing the parameter `boundary` in `Content-type`")]
+    MissingBoundary,
+
+    #[fail(display = "the multipart body is malformed: {}", _0)]
+    Malformed(&'static str),
+
+    #[fail(display = "the multipart body has been terminated unexpectedly")]
+    UnexpectedEof,
+
+    #[fail(display = "the part is too large (limit: {} bytes)", limit)]
+    PartTooLarge { limit: u64 },
+
+    #[fail(display = "the multipart body is too large (limit: {} bytes)", limit)]
+    PayloadTooLarge { limit: u64 },
+}
+
+impl HttpError for MultipartError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            MultipartError::PartTooLarge { .. } | MultipartError::PayloadTooLarge { .. } => {
+                StatusCode::PAYLOAD_TOO_LARGE
+            }
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+/// A `Stream` that parses the request body as `multipart/form-data` and yields its parts.
+///
+/// Each part must be consumed before polling the next one.
+/// The remaining data of the current part is discarded when the next part is requested.
+#[derive(Debug)]
+pub struct Multipart {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Multipart {
+    /// Creates a `Multipart` from the request body and the boundary string.
+    pub fn new(body: RequestBody, boundary: &str) -> Self {
+        let mut delimiter = BytesMut::with_capacity(boundary.len() + 4);
+        delimiter.extend_from_slice(b"\r\n--");
+        delimiter.extend_from_slice(boundary.as_bytes());
+
+        // The leading CRLF is inserted so that the first delimiter can be found
+        // in the same way as the subsequent ones.
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(b"\r\n");
+
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                body,
+                buf,
+                delimiter: delimiter.freeze(),
+                state: State::Preamble,
+                part_id: 0,
+                part_size: 0,
+                total_size: 0,
+                part_limit: None,
+                total_limit: None,
+            })),
+        }
+    }
+
+    /// Sets the maximum size of each part, in bytes.
+    pub fn part_limit(self, limit: u64) -> Self {
+        self.lock().part_limit = Some(limit);
+        self
+    }
+
+    /// Sets the maximum size of the entire multipart body, in bytes.
+    pub fn total_limit(self, limit: u64) -> Self {
+        self.lock().total_limit = Some(limit);
+        self
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().expect("the lock has been poisoned")
+    }
+}
+
+impl Stream for Multipart {
+    type Item = Part;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let (headers, id) = match futures01::try_ready!(self.lock().poll_next_part()) {
+            Some(next) => next,
+            None => return Ok(Async::Ready(None)),
+        };
+
+        let (name, filename) = headers
+            .get(CONTENT_DISPOSITION)
+            .map(|h| parse_content_disposition(h.as_bytes()))
+            .unwrap_or((None, None));
+
+        Ok(Async::Ready(Some(Part {
+            headers,
+            name,
+            filename,
+            id,
+            inner: self.inner.clone(),
+        })))
+    }
+}
+
+/// A part of `multipart/form-data`.
+///
+/// The value of this type is also a `Stream` that yields the chunks of part body.
+#[derive(Debug)]
+pub struct Part {
+    headers: HeaderMap,
+    name: Option<String>,
+    filename: Option<String>,
+    id: usize,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Part {
+    /// Returns the header fields of this part.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Returns the field name specified in `Content-Disposition`.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(|s| &**s)
+    }
+
+    /// Returns the file name specified in `Content-Disposition`.
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_ref().map(|s| &**s)
+    }
+
+    /// Returns the media type of this part, if specified.
+    pub fn content_type(&self) -> Option<Mime> {
+        self.headers
+            .get(CONTENT_TYPE)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|s| s.parse().ok())
+    }
+}
+
+impl Stream for Part {
+    type Item = Bytes;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let mut inner = self.inner.lock().expect("the lock has been poisoned");
+        if inner.part_id != self.id {
+            return Ok(Async::Ready(None));
+        }
+        inner.poll_part_data()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum State {
+    Preamble,
+    Delimiter,
+    Headers,
+    Body,
+    End,
+}
+
+#[derive(Debug)]
+struct Inner {
+    body: RequestBody,
+    buf: BytesMut,
+    delimiter: Bytes,
+    state: State,
+    part_id: usize,
+    part_size: u64,
+    total_size: u64,
+    part_limit: Option<u64>,
+    total_limit: Option<u64>,
+}
+
+impl Inner {
+    /// Receives a chunk from the request body and appends it to the buffer.
+    fn fill_buf(&mut self) -> Poll<(), Error> {
+        match futures01::try_ready!(self.body.poll_data()) {
+            Some(chunk) => {
+                self.total_size += chunk.remaining() as u64;
+                if let Some(limit) = self.total_limit {
+                    if self.total_size > limit {
+                        return Err(MultipartError::PayloadTooLarge { limit }.into());
+                    }
+                }
+                self.buf.extend_from_slice(chunk.bytes());
+                Ok(Async::Ready(()))
+            }
+            None => Err(MultipartError::UnexpectedEof.into()),
+        }
+    }
+
+    fn poll_next_part(&mut self) -> Poll<Option<(HeaderMap, usize)>, Error> {
+        loop {
+            match self.state {
+                State::Preamble => match find(&self.buf, &self.delimiter) {
+                    Some(pos) => {
+                        self.buf.advance(pos + self.delimiter.len());
+                        self.state = State::Delimiter;
+                    }
+                    None => {
+                        let n = self.buf.len().saturating_sub(self.delimiter.len() - 1);
+                        self.buf.advance(n);
+                        futures01::try_ready!(self.fill_buf());
+                    }
+                },
+
+                State::Body => {
+                    // skip the remaining data in the current part.
+                    while futures01::try_ready!(self.poll_part_data()).is_some() {}
+                }
+
+                State::Delimiter => {
+                    if self.buf.len() < 2 {
+                        futures01::try_ready!(self.fill_buf());
+                        continue;
+                    }
+                    if self.buf.starts_with(b"--") {
+                        self.buf.clear();
+                        self.state = State::End;
+                        continue;
+                    }
+                    match find(&self.buf, b"\r\n") {
+                        Some(pos) if self.buf[..pos].iter().all(|&b| b == b' ' || b == b'\t') => {
+                            self.buf.advance(pos + 2);
+                            self.state = State::Headers;
+                        }
+                        Some(..) => {
+                            return Err(MultipartError::Malformed("invalid delimiter line").into());
+                        }
+                        None if self.buf.len() > MAX_HEADERS_SIZE => {
+                            return Err(MultipartError::Malformed("invalid delimiter line").into());
+                        }
+                        None => futures01::try_ready!(self.fill_buf()),
+                    }
+                }
+
+                State::Headers => {
+                    let headers = if self.buf.starts_with(b"\r\n") {
+                        self.buf.advance(2);
+                        HeaderMap::new()
+                    } else {
+                        match find(&self.buf, b"\r\n\r\n") {
+                            Some(pos) => {
+                                let headers = parse_headers(&self.buf[..pos])?;
+                                self.buf.advance(pos + 4);
+                                headers
+                            }
+                            None if self.buf.len() > MAX_HEADERS_SIZE => {
+                                return Err(
+                                    MultipartError::Malformed("too large header section").into()
+                                );
+                            }
+                            None => {
+                                futures01::try_ready!(self.fill_buf());
+                                continue;
+                            }
+                        }
+                    };
+
+                    self.state = State::Body;
+                    self.part_id += 1;
+                    self.part_size = 0;
+
+                    return Ok(Async::Ready(Some((headers, self.part_id))));
+                }
+
+                State::End => return Ok(Async::Ready(None)),
+            }
+        }
+    }
+
+    fn poll_part_data(&mut self) -> Poll<Option<Bytes>, Error> {
+        loop {
+            if self.state != State::Body {
+                return Ok(Async::Ready(None));
+            }
+
+            let n = match find(&self.buf, &self.delimiter) {
+                Some(0) => {
+                    self.buf.advance(self.delimiter.len());
+                    self.state = State::Delimiter;
+                    return Ok(Async::Ready(None));
+                }
+                Some(pos) => pos,
+                // The tail of buffer may be the beginning of delimiter.
+                None => self.buf.len().saturating_sub(self.delimiter.len() - 1),
+            };
+
+            if n == 0 {
+                futures01::try_ready!(self.fill_buf());
+                continue;
+            }
+
+            self.part_size += n as u64;
+            if let Some(limit) = self.part_limit {
+                if self.part_size > limit {
+                    return Err(MultipartError::PartTooLarge { limit }.into());
+                }
+            }
+
+            return Ok(Async::Ready(Some(self.buf.split_to(n).freeze())));
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn trim(mut s: &[u8]) -> &[u8] {
+    while let Some((&b, rest)) = s.split_first() {
+        if b != b' ' && b != b'\t' {
+            break;
+        }
+        s = rest;
+    }
+    while let Some((&b, rest)) = s.split_last() {
+        if b != b' ' && b != b'\t' {
+            break;
+        }
+        s = rest;
+    }
+    s
+}
+
+fn parse_headers(data: &[u8]) -> Result<HeaderMap, MultipartError> {
+    let mut headers = HeaderMap::new();
+    for line in data.split(|&b| b == b'\n') {
+        let line = if line.ends_with(b"\r") {
+            &line[..line.len() - 1]
+        } else {
+            line
+        };
+        if line.is_empty() {
+            continue;
+        }
+        let pos = line
+            .iter()
+            .position(|&b| b == b':')
+            .ok_or_else(|| MultipartError::Malformed("invalid header line"))?;
+        let name = HeaderName::from_bytes(trim(&line[..pos]))
+            .map_err(|_| MultipartError::Malformed("invalid header name"))?;
+        let value = HeaderValue::from_bytes(trim(&line[pos + 1..]))
+            .map_err(|_| MultipartError::Malformed("invalid header value"))?;
+        headers.append(name, value);
+    }
+    Ok(headers)
+}
+
+/// Extracts the values of `name` and `filename` from the value of `Content-Disposition`.
+fn parse_content_disposition(value: &[u8]) -> (Option<String>, Option<String>) {
+    let value = String::from_utf8_lossy(value);
+
+    let mut name = None;
+    let mut filename = None;
+    let mut filename_ext = None;
+    for (key, value) in parse_params(&value) {
+        match &*key {
+            "name" => name = Some(value),
+            "filename" => filename = Some(value),
+            "filename*" => filename_ext = decode_ext_value(&value),
+            _ => {}
+        }
+    }
+
+    (name, filename_ext.or(filename))
+}
+
+/// Splits the parameters of a header value (e.g. `form-data; name="foo"`) into key-value pairs.
+///
+/// The first element (the disposition type) is skipped.
+fn parse_params(s: &str) -> Vec<(String, String)> {
+    let mut params = vec![];
+    let mut chars = s.chars().skip_while(|&c| c != ';').peekable();
+
+    loop {
+        while chars
+            .peek()
+            .map_or(false, |&c| c == ';' || c.is_whitespace())
+        {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut key = String::new();
+        while let Some(&c) = chars.peek() {
+            if c == '=' || c == ';' {
+                break;
+            }
+            key.push(c);
+            chars.next();
+        }
+
+        let mut value = String::new();
+        if chars.peek() == Some(&'=') {
+            chars.next();
+            while chars.peek().map_or(false, |c| c.is_whitespace()) {
+                chars.next();
+            }
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' if chars.peek() == Some(&'"') || chars.peek() == Some(&'\\') => {
+                            value.extend(chars.next());
+                        }
+                        c => value.push(c),
+                    }
+                }
+            } else {
+                while let Some(&c) = chars.peek() {
+                    if c == ';' {
+                        break;
+                    }
+                    value.push(c);
+                    chars.next();
+                }
+                value = value.trim().to_owned();
+            }
+        }
+
+        params.push((key.trim().to_ascii_lowercase(), value));
+    }
+
+    params
+}
+
+/// Decodes the extended parameter value defined in RFC 5987 (e.g. `UTF-8''%E3%81%82.txt`).
+fn decode_ext_value(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let charset = parts.next()?;
+    let _language = parts.next()?;
+    let encoded = parts.next()?;
+    if !charset.eq_ignore_ascii_case("utf-8") {
+        return None;
+    }
+    percent_decode(encoded.as_bytes())
+        .decode_utf8()
+        .ok()
+        .map(|s| s.into_owned())
+}
+
+/// A file part spooled to a temporary file.
+///
+/// The temporary file is removed when the value is dropped, unless it is persisted.
+#[derive(Debug)]
+pub struct UploadedFile {
+    name: String,
+    filename: String,
+    content_type: Option<Mime>,
+    path: PathBuf,
+    size: u64,
+    persisted: bool,
+}
+
+impl UploadedFile {
+    pub(crate) fn new(
+        name: String,
+        filename: String,
+        content_type: Option<Mime>,
+        path: PathBuf,
+    ) -> Self {
+        Self {
+            name,
+            filename,
+            content_type,
+            path,
+            size: 0,
+            persisted: false,
+        }
+    }
+
+    pub(crate) fn add_size(&mut self, n: u64) {
+        self.size += n;
+    }
+
+    /// Returns the field name of this file.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the file name sent by the client.
+    ///
+    /// Note that this value is untrusted and should not be used as a path directly.
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// Returns the media type of this file, if specified.
+    pub fn content_type(&self) -> Option<&Mime> {
+        self.content_type.as_ref()
+    }
+
+    /// Returns the path of the temporary file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the size of this file in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Moves the temporary file to the specified path.
+    pub fn persist(mut self, to: impl AsRef<Path>) -> io::Result<()> {
+        let to = to.as_ref();
+        if fs::rename(&self.path, to).is_err() {
+            // `rename` fails if the destination is on another file system.
+            fs::copy(&self.path, to)?;
+            fs::remove_file(&self.path)?;
+        }
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for UploadedFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// The contents of `multipart/form-data` consisting of the text fields and the uploaded files.
+#[derive(Debug)]
+pub struct Form<T> {
+    fields: T,
+    files: Vec<UploadedFile>,
+}
+
+impl<T> Form<T> {
+    pub(crate) fn new(fields: T, files: Vec<UploadedFile>) -> Self {
+        Self { fields, files }
+    }
+
+    /// Returns the reference to the value deserialized from the text fields.
+    pub fn fields(&self) -> &T {
+        &self.fields
+    }
+
+    /// Returns the list of uploaded files.
+    pub fn files(&self) -> &[UploadedFile] {
+        &self.files[..]
+    }
+
+    /// Consumes itself and returns the text fields and the uploaded files.
+    pub fn into_parts(self) -> (T, Vec<UploadedFile>) {
+        (self.fields, self.files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        futures01::{Future, Stream},
+    };
+
+    fn collect_parts(body: &'static str, boundary: &str) -> Result<Vec<(Part, Bytes)>, Error> {
+        let mut multipart = Multipart::new(RequestBody::new(body), boundary);
+        let mut parts = vec![];
+        loop {
+            match multipart.poll()? {
+                Async::Ready(Some(mut part)) => {
+                    let data = (&mut part).concat2().wait()?;
+                    parts.push((part, data));
+                }
+                Async::Ready(None) => return Ok(parts),
+                Async::NotReady => panic!("the raw body should be always ready"),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_multipart() -> Result<(), Error> {
+        let body = "preamble\r\n\
+                    --boundary\r\n\
+                    Content-Disposition: form-data; name=\"field1\"\r\n\
+                    \r\n\
+                    value1\r\n\
+                    --boundary\r\n\
+                    Content-Disposition: form-data; name=\"file1\"; filename=\"a.txt\"\r\n\
+                    Content-Type: text/plain\r\n\
+                    \r\n\
+                    hello\r\n\
+                    world\r\n\
+                    --boundary--\r\n\
+                    epilogue";
+
+        let parts = collect_parts(body, "boundary")?;
+        assert_eq!(parts.len(), 2);
+
+        assert_eq!(parts[0].0.name(), Some("field1"));
+        assert_eq!(parts[0].0.filename(), None);
+        assert_eq!(parts[0].1, "value1");
+
+        assert_eq!(parts[1].0.name(), Some("file1"));
+        assert_eq!(parts[1].0.filename(), Some("a.txt"));
+        assert_eq!(parts[1].0.content_type(), Some(mime::TEXT_PLAIN));
+        assert_eq!(parts[1].1, "hello\r\nworld");
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_multipart_skip_unread_part() -> Result<(), Error> {
+        let body = "--b\r\n\
+                    Content-Disposition: form-data; name=\"a\"\r\n\
+                    \r\n\
+                    AAAA\r\n\
+                    --b\r\n\
+                    Content-Disposition: form-data; name=\"b\"\r\n\
+                    \r\n\
+                    BBBB\r\n\
+                    --b--";
+
+        let mut multipart = Multipart::new(RequestBody::new(body), "b");
+        let _ = multipart.poll()?;
+        let mut part = match multipart.poll()? {
+            Async::Ready(Some(part)) => part,
+            _ => panic!("the second part is missing"),
+        };
+        assert_eq!(part.name(), Some("b"));
+        assert_eq!((&mut part).concat2().wait()?, "BBBB");
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_multipart_failcase() {
+        // missing the close delimiter
+        let body = "--b\r\n\
+                    Content-Disposition: form-data; name=\"a\"\r\n\
+                    \r\n\
+                    AAAA";
+        assert!(collect_parts(body, "b").is_err());
+
+        // part limit
+        let body = "--b\r\n\
+                    Content-Disposition: form-data; name=\"a\"\r\n\
+                    \r\n\
+                    AAAAAAAAAAAAAAAA\r\n\
+                    --b--";
+        let mut multipart = Multipart::new(RequestBody::new(body), "b").part_limit(4);
+        let mut part = match multipart.poll() {
+            Ok(Async::Ready(Some(part))) => part,
+            _ => panic!("the part is missing"),
+        };
+        let err = (&mut part).concat2().wait().unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn content_disposition() {
+        assert_eq!(
+            parse_content_disposition(b"form-data; name=\"foo\"; filename=\"a \\\"b\\\".txt\""),
+            (Some("foo".into()), Some("a \"b\".txt".into()))
+        );
+        assert_eq!(
+            parse_content_disposition(
+                b"form-data; name=foo; filename=\"a.txt\"; filename*=UTF-8''%E3%81%82.txt"
+            ),
+            (Some("foo".into()), Some("\u{3042}.txt".into()))
+        );
+        assert_eq!(parse_content_disposition(b"form-data"), (None, None));
+    }
+}
@@ -0,0 +1,32 @@
+//! Components for parsing the query string in the request URI.
+
+use {super::Input, crate::error::HttpError, http::StatusCode, serde::de::DeserializeOwned};
+
+/// An error that occurs during parsing the query string.
+#[derive(Debug, failure::Fail)]
+#[fail(display = "failed to parse the query string: {}", _0)]
+pub struct QueryError(#[fail(cause)] serde_urlencoded::de::Error);
+
+impl QueryError {
+    /// Returns the reference to the underlying deserialization error.
+    pub fn get_ref(&self) -> &serde_urlencoded::de::Error {
+        &self.0
+    }
+}
+
+impl HttpError for QueryError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+}
+
+/// Parses the query string in the request URI into `T`.
+///
+/// If the request URI has no query, it is treated as an empty string.
+pub fn parse<T>(input: &Input<'_>) -> Result<T, QueryError>
+where
+    T: DeserializeOwned,
+{
+    let query_str = input.request.uri().query().unwrap_or("");
+    serde_urlencoded::from_str(query_str).map_err(QueryError)
+}
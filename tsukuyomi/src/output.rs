@@ -17,6 +17,7 @@ use {
         upgrade::{NeverUpgrade, Upgrade},
         util::Never,
     },
+    mime::Mime,
     serde::Serialize,
     std::marker::PhantomData,
 };
@@ -335,6 +336,15 @@ where
     render(data)
 }
 
+/// Creates a CSV response using the specified pre-rendered data.
+#[inline]
+pub fn csv<T>(data: T) -> Rendered<T, Csv>
+where
+    T: Into<ResponseBody>,
+{
+    render(data)
+}
+
 /// A `Responder` that uses the specified preset.
 #[allow(missing_debug_implementations)]
 pub struct Rendered<T, P>(T, PhantomData<P>);
@@ -475,3 +485,292 @@ mod html {
         }
     }
 }
+
+#[allow(missing_debug_implementations)]
+pub struct Csv(());
+
+mod csv {
+    use super::*;
+    use crate::{
+        future::{Poll, TryFuture},
+        upgrade::NeverUpgrade,
+    };
+
+    impl<T> Preset<T> for Csv
+    where
+        T: Into<ResponseBody>,
+    {
+        type Upgrade = NeverUpgrade;
+        type Error = Error;
+        type Respond = CsvRespond;
+
+        fn respond(this: T) -> Self::Respond {
+            CsvRespond(Some(this.into()))
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct CsvRespond(Option<ResponseBody>);
+
+    impl TryFuture for CsvRespond {
+        type Ok = Response;
+        type Error = Error;
+
+        fn poll_ready(&mut self, _: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            let body = self.0.take().expect("the future has already been polled.");
+            Ok(crate::output::make_response(body, "text/csv").into())
+        }
+    }
+}
+
+/// Creates a `Responder` that selects the representation based on the `Accept` header field.
+///
+/// The representations are registered with their media types, and the one that
+/// has the highest quality value in `Accept` is sent to the client. If several
+/// representations have the same quality value, the one registered first is chosen.
+/// If the request has no `Accept` header field, the first representation is used.
+///
+/// The header field `Vary: Accept` is always added to the response, and the error
+/// `406 Not Acceptable` is returned if no representation matches.
+///
+/// ```
+/// # use tsukuyomi::output::Negotiate;
+/// # use serde::Serialize;
+/// #[derive(Debug, Serialize)]
+/// struct User {
+///     name: String,
+/// }
+///
+/// fn user_page(user: User) -> Negotiate {
+///     let html = format!("<h1>{}</h1>", user.name);
+///     tsukuyomi::output::negotiate()
+///         .json(user)
+///         .html(html)
+/// }
+/// # fn main() {}
+/// ```
+pub fn negotiate() -> Negotiate {
+    Negotiate {
+        variants: Vec::new(),
+    }
+}
+
+/// A `Responder` that performs the content negotiation, created by `negotiate()`.
+pub struct Negotiate {
+    variants: Vec<self::negotiate::Variant>,
+}
+
+impl std::fmt::Debug for Negotiate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Negotiate")
+            .field(
+                "variants",
+                &self
+                    .variants
+                    .iter()
+                    .map(|variant| &variant.media_type)
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl Negotiate {
+    /// Registers a representation rendered by the specified preset.
+    pub fn render<T, P>(mut self, media_type: Mime, data: T) -> Self
+    where
+        P: Preset<T, Upgrade = NeverUpgrade>,
+        P::Respond: Send + 'static,
+    {
+        let mut respond = P::respond(data);
+        self.variants.push(self::negotiate::Variant {
+            media_type,
+            respond: Box::new(move |input: &mut Input<'_>| -> Poll<Response, Error> {
+                let (response, _) =
+                    futures01::try_ready!(respond.poll_respond(input).map_err(Into::into));
+                Ok(response.into())
+            }),
+        });
+        self
+    }
+
+    /// Registers a JSON representation.
+    pub fn json<T>(self, data: T) -> Self
+    where
+        T: Serialize + Send + 'static,
+    {
+        self.render::<T, Json>(mime::APPLICATION_JSON, data)
+    }
+
+    /// Registers an HTML representation.
+    pub fn html<T>(self, data: T) -> Self
+    where
+        T: Into<ResponseBody>,
+    {
+        self.render::<T, Html>(mime::TEXT_HTML, data)
+    }
+
+    /// Registers a CSV representation using the pre-rendered body.
+    pub fn csv<T>(self, data: T) -> Self
+    where
+        T: Into<ResponseBody>,
+    {
+        self.render::<T, Csv>(mime::TEXT_CSV, data)
+    }
+}
+
+impl Responder for Negotiate {
+    type Upgrade = NeverUpgrade;
+    type Error = Error;
+    type Respond = self::negotiate::NegotiateRespond;
+
+    fn respond(self) -> Self::Respond {
+        self::negotiate::NegotiateRespond {
+            variants: self.variants,
+            selected: None,
+        }
+    }
+}
+
+mod negotiate {
+    use {
+        super::{Error, Input, Response},
+        crate::{
+            future::Poll,
+            input::header::{Accept, QualityItem},
+            upgrade::NeverUpgrade,
+        },
+        http::header::{HeaderMap, HeaderValue, VARY},
+        mime::Mime,
+    };
+
+    type BoxedRespond = Box<dyn FnMut(&mut Input<'_>) -> Poll<Response, Error> + Send + 'static>;
+
+    pub(super) struct Variant {
+        pub(super) media_type: Mime,
+        pub(super) respond: BoxedRespond,
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct NegotiateRespond {
+        pub(super) variants: Vec<Variant>,
+        pub(super) selected: Option<BoxedRespond>,
+    }
+
+    impl super::Respond for NegotiateRespond {
+        type Upgrade = NeverUpgrade;
+        type Error = Error;
+
+        fn poll_respond(
+            &mut self,
+            input: &mut Input<'_>,
+        ) -> Poll<(Response, Option<Self::Upgrade>), Self::Error> {
+            loop {
+                if let Some(ref mut respond) = self.selected {
+                    let response = futures01::try_ready!(respond(input));
+                    return Ok((response, None).into());
+                }
+
+                input
+                    .response_headers
+                    .get_or_insert_with(HeaderMap::new)
+                    .append(VARY, HeaderValue::from_static("accept"));
+
+                let index = match crate::input::header::parse::<Accept>(input)? {
+                    Some(accept) => select(&accept.0, &self.variants),
+                    None if !self.variants.is_empty() => Some(0),
+                    None => None,
+                };
+                let variant = match index {
+                    Some(index) => self.variants.swap_remove(index),
+                    None => {
+                        return Err(crate::error::not_acceptable("no acceptable representation"))
+                    }
+                };
+                self.selected = Some(variant.respond);
+            }
+        }
+    }
+
+    /// Returns the index of variant with the highest quality value.
+    fn select(accept: &[QualityItem<Mime>], variants: &[Variant]) -> Option<usize> {
+        let mut selected: Option<(usize, f32)> = None;
+        for (i, variant) in variants.iter().enumerate() {
+            let quality = match quality(accept, &variant.media_type) {
+                Some(quality) if quality > 0.0 => quality,
+                _ => continue,
+            };
+            match selected {
+                Some((_, q)) if q >= quality => {}
+                _ => selected = Some((i, quality)),
+            }
+        }
+        selected.map(|(i, _)| i)
+    }
+
+    /// Returns the quality value of the most specific media range that matches the media type.
+    fn quality(accept: &[QualityItem<Mime>], media_type: &Mime) -> Option<f32> {
+        accept
+            .iter()
+            .filter_map(|range| {
+                let specificity = if range.item.type_() == mime::STAR {
+                    0
+                } else if range.item.type_() != media_type.type_() {
+                    return None;
+                } else if range.item.subtype() == mime::STAR {
+                    1
+                } else if range.item.subtype() == media_type.subtype() {
+                    2
+                } else {
+                    return None;
+                };
+                Some((specificity, range.quality))
+            })
+            .max_by_key(|&(specificity, _)| specificity)
+            .map(|(_, quality)| quality)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn variants(media_types: &[Mime]) -> Vec<Variant> {
+            media_types
+                .iter()
+                .map(|media_type| Variant {
+                    media_type: media_type.clone(),
+                    respond: Box::new(|_: &mut Input<'_>| -> Poll<Response, Error> {
+                        unreachable!()
+                    }),
+                })
+                .collect()
+        }
+
+        fn select_str(accept: &str, media_types: &[Mime]) -> Option<usize> {
+            let accept: Accept = accept.parse().unwrap();
+            select(&accept.0, &variants(media_types))
+        }
+
+        #[test]
+        fn select_by_quality() {
+            let media_types = [mime::APPLICATION_JSON, mime::TEXT_HTML];
+            assert_eq!(select_str("text/html", &media_types), Some(1));
+            assert_eq!(
+                select_str("application/json;q=0.5, text/html;q=0.8", &media_types),
+                Some(1)
+            );
+            assert_eq!(select_str("*/*", &media_types), Some(0));
+            assert_eq!(select_str("text/*, */*;q=0.1", &media_types), Some(1));
+            assert_eq!(select_str("text/csv", &media_types), None);
+        }
+
+        #[test]
+        fn most_specific_range_wins() {
+            let media_types = [mime::APPLICATION_JSON, mime::TEXT_HTML];
+            assert_eq!(
+                select_str("*/*;q=0.9, application/json;q=0", &media_types),
+                Some(1)
+            );
+        }
+    }
+}
@@ -1,26 +1,443 @@
 //! The implementation of HTTP server for tsukuyomi.
+//!
+//! The server speaks both HTTP/1.1 and HTTP/2 on the same listener.
+//! HTTP/2 is selected by ALPN on TLS connections, and `h2` is not offered by ALPN
+//! while HTTP/2 is disabled. On plain connections, the server accepts both h2c with
+//! prior knowledge and the upgrade to h2c via the `Upgrade` header field. The
+//! upgrading request must not have the payload, otherwise the upgrade is ignored
+//! and the connection continues to use HTTP/1.1.
+//!
+//! The value of `HTTP2-Settings` in the upgrading request is validated but not
+//! applied to the connection. The client sends its SETTINGS frame after the
+//! connection preface in any case, and the HTTP/2 settings are taken from that frame.
+//!
+//! On Unix platforms, the server can also listen on Unix domain sockets and
+//! adopt the listeners passed by the service manager (socket activation).
 
+mod conn;
+mod listener;
+#[cfg(feature = "tls")]
+pub mod tls;
+
+use {
+    self::{
+        conn::{EitherConnection, Handshake, Protocol, Timeouts},
+        listener::{ConnectionLimit, Serve},
+    },
+    crate::app::{AppService, VirtualHosts},
+    futures01::{future::Shared, sync::oneshot, Async, Future, Poll, Stream},
+    izanami::{h1::H1, h2::H2, http::Connection},
+    std::{
+        error::Error as StdError,
+        io,
+        net::ToSocketAddrs,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    },
+    tokio::{
+        net::{TcpListener, TcpStream},
+        reactor::Handle,
+        runtime::Runtime,
+        timer::Delay,
+    },
+};
+
+#[cfg(unix)]
 use {
-    crate::app::App,
-    futures01::Future,
-    izanami::{h1::H1, net::tcp::AddrIncoming, service::ServiceExt},
-    std::{io, net::ToSocketAddrs},
-    tokio::runtime::Runtime,
+    crate::app::RemoteAddr,
+    std::path::Path,
+    tokio::net::{UnixListener, UnixStream},
 };
 
+type BoxedStdError = Box<dyn StdError + Send + Sync + 'static>;
+type BoxedServer = Box<dyn Future<Item = (), Error = ()> + Send + 'static>;
+
+/// The size of the request head allowed in addition to `max_header_size`,
+/// for the request line and the delimiters of the header fields.
+const REQUEST_LINE_ALLOWANCE: usize = 8 * 1024;
+
+/// The default duration to wait for the in-flight connections at shutdown.
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Creates a connection that serves HTTP/1.1, or HTTP/2 if the client sends
+/// the connection preface or upgrades to h2c, on the plain stream.
+macro_rules! plain_connection {
+    ($stream:expr, $service:expr, $http2:expr) => {{
+        let service = $service;
+        let http2 = $http2;
+        Handshake::new(
+            self::conn::detect_protocol($stream, http2.enabled),
+            move |(mut stream, protocol)| match protocol {
+                Protocol::Http2 => {
+                    stream.get_mut().upgraded();
+                    EitherConnection::Right(http2.h2().serve(stream, service))
+                }
+                Protocol::Http1 => EitherConnection::Left(H1::new().serve(stream, service)),
+            },
+        )
+    }};
+}
+
+/// The configuration of HTTP/2 connections.
+#[derive(Debug, Clone)]
+pub struct Http2Config {
+    enabled: bool,
+    max_concurrent_streams: Option<u32>,
+    initial_stream_window_size: Option<u32>,
+    initial_connection_window_size: Option<u32>,
+    max_frame_size: Option<u32>,
+}
+
+impl Default for Http2Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Http2Config {
+    /// Creates a new `Http2Config` with the default settings.
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            max_concurrent_streams: None,
+            initial_stream_window_size: None,
+            initial_connection_window_size: None,
+            max_frame_size: None,
+        }
+    }
+
+    /// Sets whether to accept HTTP/2 connections.
+    ///
+    /// The default value is `true`.
+    pub fn enabled(self, enabled: bool) -> Self {
+        Self { enabled, ..self }
+    }
+
+    /// Sets the value of `SETTINGS_MAX_CONCURRENT_STREAMS`.
+    pub fn max_concurrent_streams(self, max: u32) -> Self {
+        Self {
+            max_concurrent_streams: Some(max),
+            ..self
+        }
+    }
+
+    /// Sets the initial window size of each stream, in bytes.
+    pub fn initial_stream_window_size(self, size: u32) -> Self {
+        Self {
+            initial_stream_window_size: Some(size),
+            ..self
+        }
+    }
+
+    /// Sets the initial window size of the whole connection, in bytes.
+    pub fn initial_connection_window_size(self, size: u32) -> Self {
+        Self {
+            initial_connection_window_size: Some(size),
+            ..self
+        }
+    }
+
+    /// Sets the value of `SETTINGS_MAX_FRAME_SIZE`.
+    pub fn max_frame_size(self, size: u32) -> Self {
+        Self {
+            max_frame_size: Some(size),
+            ..self
+        }
+    }
+
+    fn h2(&self) -> H2 {
+        let mut h2 = H2::new();
+        if let Some(max) = self.max_concurrent_streams {
+            h2.max_concurrent_streams(max);
+        }
+        if let Some(size) = self.initial_stream_window_size {
+            h2.initial_window_size(size);
+        }
+        if let Some(size) = self.initial_connection_window_size {
+            h2.initial_connection_window_size(size);
+        }
+        if let Some(size) = self.max_frame_size {
+            h2.max_frame_size(size);
+        }
+        h2
+    }
+}
+
+/// The settings applied to each accepted connection.
+#[derive(Debug, Clone)]
+struct ConnectionConfig {
+    keep_alive_timeout: Option<Duration>,
+    header_read_timeout: Option<Duration>,
+    tcp_nodelay: bool,
+    max_header_count: Option<usize>,
+    max_header_size: Option<usize>,
+}
+
+impl ConnectionConfig {
+    fn io<S>(&self, io: S) -> Timeouts<S> {
+        self.io_until(io, self.header_read_deadline())
+    }
+
+    fn io_until<S>(&self, io: S, header_read_deadline: Option<Instant>) -> Timeouts<S> {
+        let io = Timeouts::new(
+            io,
+            self.keep_alive_timeout,
+            self.header_read_timeout,
+            header_read_deadline,
+        );
+        match self.max_header_size {
+            Some(max) => io.max_head_size(max.saturating_add(REQUEST_LINE_ALLOWANCE)),
+            None => io,
+        }
+    }
+
+    fn header_read_deadline(&self) -> Option<Instant> {
+        self.header_read_timeout
+            .map(|timeout| Instant::now() + timeout)
+    }
+
+    fn service(&self, app: &VirtualHosts) -> AppService {
+        let mut service = app.new_service();
+        if let Some(max) = self.max_header_count {
+            service = service.max_header_count(max);
+        }
+        if let Some(max) = self.max_header_size {
+            service = service.max_header_size(max);
+        }
+        service
+    }
+
+    fn tcp_stream(&self, stream: &TcpStream) {
+        if let Err(err) = stream.set_nodelay(self.tcp_nodelay) {
+            log::warn!("failed to set TCP_NODELAY: {}", err);
+        }
+    }
+}
+
+/// A builder for creating a `Server` with the custom connection settings.
+///
+/// ```no_run
+/// # use tsukuyomi::{App, server::Server};
+/// # use std::time::Duration;
+/// # fn main() -> std::io::Result<()> {
+/// # let app = App::build(|_| Ok(())).unwrap();
+/// let mut server = Server::builder()
+///     .keep_alive_timeout(Duration::from_secs(75))
+///     .header_read_timeout(Duration::from_secs(10))
+///     .max_connections(10_000)
+///     .tcp_nodelay(true)
+///     .build(app)?;
+/// server.bind("127.0.0.1:4000")?;
+/// server.run_forever()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ServerBuilder {
+    conn: ConnectionConfig,
+    max_connections: Option<usize>,
+    worker_threads: Option<usize>,
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerBuilder {
+    /// Creates a new `ServerBuilder` with the default settings.
+    pub fn new() -> Self {
+        Self {
+            conn: ConnectionConfig {
+                keep_alive_timeout: None,
+                header_read_timeout: None,
+                tcp_nodelay: false,
+                max_header_count: None,
+                max_header_size: None,
+            },
+            max_connections: None,
+            worker_threads: None,
+        }
+    }
+
+    /// Sets the duration to keep the idle connections open.
+    ///
+    /// The timer starts when the whole response has been written, and the connection
+    /// is closed unless the client starts sending the next request within this duration.
+    /// The connections upgraded to other protocols, including HTTP/2, are not affected.
+    /// By default, the idle connections are kept open until the client closes them.
+    pub fn keep_alive_timeout(self, timeout: Duration) -> Self {
+        Self {
+            conn: ConnectionConfig {
+                keep_alive_timeout: Some(timeout),
+                ..self.conn
+            },
+            ..self
+        }
+    }
+
+    /// Sets the maximum duration to receive the head of each request.
+    ///
+    /// This setting protects the server from the clients sending the request head
+    /// very slowly (e.g. Slowloris). For the first request, the duration is measured
+    /// from accepting the connection and includes the TLS handshake on TLS connections.
+    /// For the subsequent requests on a keep-alive connection, it is measured from
+    /// when the first byte of the request arrives.
+    pub fn header_read_timeout(self, timeout: Duration) -> Self {
+        Self {
+            conn: ConnectionConfig {
+                header_read_timeout: Some(timeout),
+                ..self.conn
+            },
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of the connections served at the same time.
+    ///
+    /// The limit is shared by all listeners of the server. While the limit is
+    /// reached, the server stops accepting and the new connections are kept
+    /// waiting in the backlog of the listeners.
+    pub fn max_connections(self, max: usize) -> Self {
+        Self {
+            max_connections: Some(max),
+            ..self
+        }
+    }
+
+    /// Sets whether to enable `TCP_NODELAY` on the accepted TCP connections.
+    ///
+    /// The default value is `false`.
+    pub fn tcp_nodelay(self, enabled: bool) -> Self {
+        Self {
+            conn: ConnectionConfig {
+                tcp_nodelay: enabled,
+                ..self.conn
+            },
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of header fields in a request.
+    ///
+    /// The requests exceeding this limit are rejected with `431 Request Header Fields Too Large`.
+    /// This limit is checked after the whole request head has been received, so it does not
+    /// bound the memory used for receiving the head. Use `max_header_size` for that purpose.
+    pub fn max_header_count(self, max: usize) -> Self {
+        Self {
+            conn: ConnectionConfig {
+                max_header_count: Some(max),
+                ..self.conn
+            },
+            ..self
+        }
+    }
+
+    /// Sets the maximum total size of the header fields in a request, in bytes.
+    ///
+    /// The requests exceeding this limit are rejected with `431 Request Header Fields Too Large`.
+    /// In addition, the HTTP/1 connection is closed without any response once the request
+    /// head being received exceeds this limit by more than 8 KiB (the allowance for the
+    /// request line and the delimiters), so that the server does not buffer an oversized
+    /// head.
+    pub fn max_header_size(self, max: usize) -> Self {
+        Self {
+            conn: ConnectionConfig {
+                max_header_size: Some(max),
+                ..self.conn
+            },
+            ..self
+        }
+    }
+
+    /// Sets the number of worker threads of the internal runtime.
+    ///
+    /// By default, the number of CPU cores is used.
+    pub fn worker_threads(self, num_threads: usize) -> Self {
+        Self {
+            worker_threads: Some(num_threads),
+            ..self
+        }
+    }
+
+    /// Creates a new `Server` using the specified `App` or `VirtualHosts` with this settings.
+    pub fn build(self, app: impl Into<VirtualHosts>) -> io::Result<Server> {
+        let mut runtime = tokio::runtime::Builder::new();
+        if let Some(num_threads) = self.worker_threads {
+            runtime.core_threads(num_threads);
+        }
+
+        let (tx, rx) = oneshot::channel();
+        Ok(Server {
+            app: app.into(),
+            runtime: runtime.build()?,
+            conn: self.conn,
+            limit: self
+                .max_connections
+                .map(|max| Arc::new(ConnectionLimit::new(max))),
+            http2: Http2Config::default(),
+            shutdown_tx: Arc::new(Mutex::new(Some(tx))),
+            shutdown_signal: ShutdownSignal(Some(rx.shared())),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            unix_socket_permissions: None,
+            servers: vec![],
+        })
+    }
+}
+
 #[allow(missing_debug_implementations)]
 pub struct Server {
-    app: App,
+    app: VirtualHosts,
     runtime: Runtime,
+    conn: ConnectionConfig,
+    limit: Option<Arc<ConnectionLimit>>,
+    http2: Http2Config,
+    shutdown_tx: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    shutdown_signal: ShutdownSignal,
+    shutdown_timeout: Duration,
+    unix_socket_permissions: Option<u32>,
+    servers: Vec<BoxedServer>,
 }
 
 impl Server {
-    /// Creates a new `Server` using the specified `App`.
-    pub fn new(app: App) -> io::Result<Self> {
-        Ok(Self {
-            app,
-            runtime: Runtime::new()?,
-        })
+    /// Creates a new `Server` using the specified `App` or `VirtualHosts` with the default settings.
+    pub fn new(app: impl Into<VirtualHosts>) -> io::Result<Self> {
+        ServerBuilder::new().build(app)
+    }
+
+    /// Creates a `ServerBuilder` for configuring the connection settings.
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::new()
+    }
+
+    /// Sets the configuration of HTTP/2 connections.
+    ///
+    /// This value is applied to the servers spawned by the subsequent calls of `bind`.
+    pub fn http2(&mut self, config: Http2Config) -> &mut Self {
+        self.http2 = config;
+        self
+    }
+
+    /// Sets the maximum duration to wait for the in-flight connections after
+    /// the graceful shutdown is started.
+    ///
+    /// The connections that are still open after this duration are forcibly closed.
+    /// This value is applied to the servers spawned by the subsequent calls of `bind`.
+    ///
+    /// The default value is 30 seconds.
+    pub fn shutdown_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Sets the permissions of the socket files created by `bind_unix`, e.g. `0o660`.
+    ///
+    /// If this value is not set, the permissions are determined by the umask of the process.
+    #[cfg(unix)]
+    pub fn unix_socket_permissions(&mut self, mode: u32) -> &mut Self {
+        self.unix_socket_permissions = Some(mode);
+        self
     }
 
     /// Spawns an HTTP server using the associated `App` onto the inner runtime.
@@ -28,27 +445,458 @@ impl Server {
     where
         A: ToSocketAddrs,
     {
+        let listener = std::net::TcpListener::bind(addr)?;
+        self.serve_tcp(TcpListener::from_std(listener, &Handle::default())?);
+        Ok(())
+    }
+
+    /// Spawns an HTTP server listening on the Unix domain socket at the specified path.
+    ///
+    /// The stale socket file at the path is removed before binding.
+    /// The peer credentials are exposed to the handlers as `RemoteAddr::Unix`.
+    #[cfg(unix)]
+    pub fn bind_unix(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let listener = self::listener::bind_unix(path.as_ref(), self.unix_socket_permissions)?;
+        self.serve_unix(UnixListener::from_std(listener, &Handle::default())?);
+        Ok(())
+    }
+
+    /// Spawns HTTP servers on the listeners passed by the service manager,
+    /// using the socket activation protocol of systemd (`LISTEN_PID` and `LISTEN_FDS`).
+    ///
+    /// Both TCP and Unix domain sockets are supported.
+    /// The return value is the number of adopted listeners, which is zero if no
+    /// listener is passed to this process.
+    #[cfg(unix)]
+    pub fn bind_listen_fds(&mut self) -> io::Result<usize> {
+        let listeners = self::listener::listen_fds()?;
+        let num_listeners = listeners.len();
+        for listener in listeners {
+            match listener {
+                self::listener::Listener::Tcp(listener) => {
+                    self.serve_tcp(TcpListener::from_std(listener, &Handle::default())?)
+                }
+                self::listener::Listener::Unix(listener) => {
+                    self.serve_unix(UnixListener::from_std(listener, &Handle::default())?)
+                }
+            }
+        }
+        Ok(num_listeners)
+    }
+
+    fn serve_tcp(&mut self, listener: TcpListener) {
         let app = self.app.clone();
-        self.runtime.spawn(
-            izanami::server::Server::new(
-                AddrIncoming::bind(addr)? //
-                    .service_map(move |stream| {
-                        H1::new() //
-                            .serve(stream, app.new_service())
-                    }),
+        let conn = self.conn.clone();
+        let http2 = self.http2.clone();
+        self.spawn_server(listener.incoming(), move |stream: TcpStream| {
+            conn.tcp_stream(&stream);
+            let mut service = conn.service(&app);
+            if let Ok(addr) = stream.peer_addr() {
+                service = service.remote_addr(addr);
+            }
+            plain_connection!(conn.io(stream), service, http2.clone())
+        });
+    }
+
+    #[cfg(unix)]
+    fn serve_unix(&mut self, listener: UnixListener) {
+        let app = self.app.clone();
+        let conn = self.conn.clone();
+        let http2 = self.http2.clone();
+        self.spawn_server(listener.incoming(), move |stream: UnixStream| {
+            let cred = self::listener::peer_cred(&stream);
+            let service = conn.service(&app).remote_addr(RemoteAddr::Unix(cred));
+            plain_connection!(conn.io(stream), service, http2.clone())
+        });
+    }
+
+    /// Registers a server that accepts the connections from the specified listener.
+    ///
+    /// The server is spawned onto the runtime after the startup hooks are completed.
+    fn spawn_server<I, F, C>(&mut self, incoming: I, mut make_connection: F)
+    where
+        I: Stream<Error = io::Error> + Send + 'static,
+        F: FnMut(I::Item) -> C + Send + 'static,
+        C: Connection + Send + 'static,
+        C::Error: Into<BoxedStdError>,
+    {
+        let signal = self.shutdown_signal.clone();
+        let timeout = self.shutdown_timeout;
+        self.servers.push(Box::new(
+            Serve::new(
+                incoming,
+                move |stream| {
+                    GracefulConnection::new(make_connection(stream), signal.clone(), timeout)
+                },
+                self.limit.clone(),
             )
-            .map_err(|e| eprintln!("server error: {}", e)),
-        );
+            // stop accepting the new connections when the shutdown is started.
+            .select(self.shutdown_signal.clone())
+            .then(|_| Ok(())),
+        ));
+    }
 
+    /// Spawns an HTTPS server using the associated `App` onto the inner runtime.
+    ///
+    /// The TLS configuration can be built by using `tls::TlsConfig`.
+    /// If HTTP/2 is disabled, `h2` is removed from the ALPN protocols of the configuration.
+    #[cfg(feature = "tls")]
+    pub fn bind_tls<A>(
+        &mut self,
+        addr: A,
+        config: impl Into<Arc<tls::rustls::ServerConfig>>,
+    ) -> io::Result<()>
+    where
+        A: ToSocketAddrs,
+    {
+        let listener = std::net::TcpListener::bind(addr)?;
+        let listener = TcpListener::from_std(listener, &Handle::default())?;
+        let app = self.app.clone();
+        let conn = self.conn.clone();
+        let http2 = self.http2.clone();
+        let config = self::tls::filter_alpn_protocols(config.into(), http2.enabled);
+        let acceptor = tokio_rustls::TlsAcceptor::from(config);
+        self.spawn_server(listener.incoming(), move |stream: TcpStream| {
+            conn.tcp_stream(&stream);
+            let mut service = conn.service(&app);
+            if let Ok(addr) = stream.peer_addr() {
+                service = service.remote_addr(addr);
+            }
+            let conn = conn.clone();
+            let http2 = http2.clone();
+            // the request head must be received by the deadline measured from the acceptance.
+            let deadline = conn.header_read_deadline();
+            Handshake::new(acceptor.accept(stream), move |stream| {
+                let is_h2 = self::tls::is_h2_negotiated(&stream);
+                let mut stream = conn.io_until(stream, deadline);
+                if is_h2 && http2.enabled {
+                    stream.upgraded();
+                    EitherConnection::Right(http2.h2().serve(stream, service))
+                } else {
+                    EitherConnection::Left(H1::new().serve(stream, service))
+                }
+            })
+            .deadline(deadline)
+        });
         Ok(())
     }
 
-    /// Waits for the runtime until all spawned servers are completed.
-    pub fn run_forever(self) {
+    /// Returns a handle for starting the graceful shutdown of this server.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            tx: self.shutdown_tx.clone(),
+        }
+    }
+
+    /// Runs the startup hooks of the associated `App`s, and then waits for
+    /// the runtime until all spawned servers are completed.
+    ///
+    /// The spawned servers are completed after the shutdown is started
+    /// by `ShutdownHandle` and all in-flight connections are closed.
+    /// The shutdown hooks are run after all servers are completed.
+    ///
+    /// If one of the startup hooks fails, this method returns its error
+    /// without accepting any connections.
+    pub fn run_forever(self) -> io::Result<()> {
+        let Self {
+            app,
+            mut runtime,
+            shutdown_tx,
+            servers,
+            ..
+        } = self;
+
+        // run the startup hooks before accepting the connections.
+        runtime
+            .block_on(app.startup())
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.compat()))?;
+        for server in servers {
+            runtime.spawn(server);
+        }
+
         let mut entered = tokio_executor::enter()
             .expect("another executor has already set on the current thread");
-        let shutdown = self.runtime.shutdown_on_idle();
+        let shutdown = runtime.shutdown_on_idle();
+
+        entered.block_on(shutdown).expect("never fail");
+        drop(entered);
+
+        drop(shutdown_tx);
+
+        // the errors from the shutdown hooks are logged in `app.shutdown()`.
+        tokio::runtime::current_thread::block_on_all(app.shutdown())
+            .map_err(|()| io::Error::new(io::ErrorKind::Other, "failed to run the shutdown hooks"))
+    }
+
+    /// Runs the spawned servers until the specified future is completed,
+    /// and then performs the graceful shutdown.
+    ///
+    /// ```no_run
+    /// # use tsukuyomi::{App, server::Server};
+    /// # fn main() -> std::io::Result<()> {
+    /// # let app = App::build(|_| Ok(())).unwrap();
+    /// let mut server = Server::new(app)?;
+    /// server.bind("127.0.0.1:4000")?;
+    /// server.run_until(tsukuyomi::server::shutdown_signal())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn run_until<F>(mut self, signal: F) -> io::Result<()>
+    where
+        F: Future + Send + 'static,
+    {
+        let handle = self.shutdown_handle();
+        self.runtime.spawn(
+            signal
+                .then(move |_| {
+                    handle.shutdown();
+                    Ok(())
+                })
+                // stop waiting for the signal if the shutdown is started by `ShutdownHandle`,
+                // otherwise the runtime never becomes idle.
+                .select(self.shutdown_signal.clone())
+                .then(|_| Ok(())),
+        );
+        self.run_forever()
+    }
+}
+
+/// A handle for starting the graceful shutdown of `Server`.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    tx: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+}
+
+impl ShutdownHandle {
+    /// Starts the graceful shutdown of the associated server.
+    ///
+    /// The server stops accepting new connections, and notifies the shutdown to
+    /// the open connections, including the upgraded ones.
+    /// This method does nothing if the shutdown has already been started.
+    pub fn shutdown(&self) {
+        let tx = self.tx.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(tx) = tx {
+            let _ = tx.send(());
+        }
+    }
+}
+
+/// Creates a future that will be completed when the process receives `SIGINT`,
+/// or `SIGTERM` on Unix platforms.
+pub fn shutdown_signal() -> impl Future<Item = (), Error = ()> + Send + 'static {
+    first_signal(tokio_signal::ctrl_c())
+        .select(terminate_signal())
+        .map(|_| ())
+        .map_err(|_| ())
+}
+
+fn first_signal<S>(
+    signal: tokio_signal::IoFuture<S>,
+) -> impl Future<Item = (), Error = ()> + Send + 'static
+where
+    S: Stream<Error = io::Error> + Send + 'static,
+{
+    signal
+        .flatten_stream()
+        .into_future()
+        .map(|_| ())
+        .map_err(|_| ())
+}
+
+#[cfg(unix)]
+fn terminate_signal() -> impl Future<Item = (), Error = ()> + Send + 'static {
+    use tokio_signal::unix::{Signal, SIGTERM};
+    first_signal(Signal::new(SIGTERM))
+}
+
+#[cfg(not(unix))]
+fn terminate_signal() -> impl Future<Item = (), Error = ()> + Send + 'static {
+    futures01::future::empty()
+}
+
+/// A future that will be completed when the shutdown is started.
+///
+/// If the sender of the shutdown signal has gone, this future will never be completed.
+#[derive(Clone)]
+struct ShutdownSignal(Option<Shared<oneshot::Receiver<()>>>);
+
+impl Future for ShutdownSignal {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let polled = match self.0 {
+            Some(ref mut rx) => rx.poll(),
+            None => return Ok(Async::NotReady),
+        };
+        match polled {
+            Ok(Async::Ready(..)) => Ok(Async::Ready(())),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(..) => {
+                self.0 = None;
+                Ok(Async::NotReady)
+            }
+        }
+    }
+}
+
+/// A wrapper of `Connection` that starts the graceful shutdown when the
+/// shutdown signal is received.
+struct GracefulConnection<C> {
+    conn: C,
+    signal: ShutdownSignal,
+    timeout: Duration,
+    deadline: Option<Delay>,
+}
+
+impl<C> GracefulConnection<C> {
+    fn new(conn: C, signal: ShutdownSignal, timeout: Duration) -> Self {
+        Self {
+            conn,
+            signal,
+            timeout,
+            deadline: None,
+        }
+    }
+}
+
+impl<C> Connection for GracefulConnection<C>
+where
+    C: Connection,
+{
+    type Error = C::Error;
+
+    fn poll_close(&mut self) -> Poll<(), Self::Error> {
+        if self.deadline.is_none() {
+            if let Ok(Async::Ready(())) = self.signal.poll() {
+                self.conn.graceful_shutdown();
+                self.deadline = Some(Delay::new(Instant::now() + self.timeout));
+            }
+        }
+
+        if let Some(ref mut deadline) = self.deadline {
+            match deadline.poll() {
+                Ok(Async::NotReady) => {}
+                // forcibly close the connection.
+                Ok(Async::Ready(())) | Err(..) => return Ok(Async::Ready(())),
+            }
+        }
+
+        self.conn.poll_close()
+    }
+
+    fn graceful_shutdown(&mut self) {
+        self.conn.graceful_shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::app::App,
+        std::{
+            io::{Read, Write},
+            net::{SocketAddr, TcpStream as StdTcpStream},
+            sync::mpsc,
+            thread,
+        },
+    };
+
+    /// Starts a server on an ephemeral port in another thread.
+    fn start<F>(
+        run: F,
+    ) -> (
+        SocketAddr,
+        ShutdownHandle,
+        thread::JoinHandle<io::Result<()>>,
+    )
+    where
+        F: FnOnce(Server) -> io::Result<()> + Send + 'static,
+    {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap();
+        let (tx, rx) = mpsc::channel();
+        let server = thread::spawn(move || {
+            let app = App::build(|_| Ok(())).unwrap();
+            let mut server = Server::new(app)?;
+            server.shutdown_timeout(Duration::from_millis(500));
+            server.bind(addr)?;
+            tx.send(server.shutdown_handle()).unwrap();
+            run(server)
+        });
+        let handle = rx.recv().expect("failed to start the server");
+        (addr, handle, server)
+    }
+
+    fn send_request(stream: &mut StdTcpStream) {
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut buf = [0; 1024];
+        assert!(stream.read(&mut buf).unwrap() > 0);
+    }
+
+    /// Returns whether the connection is closed by the server within a few seconds.
+    fn wait_closed(stream: &mut StdTcpStream) -> bool {
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        match stream.read_to_end(&mut vec![]) {
+            Ok(..) => true,
+            Err(ref err) => match err.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => false,
+                _ => true,
+            },
+        }
+    }
+
+    #[test]
+    fn graceful_shutdown() {
+        let (addr, handle, server) = start(Server::run_forever);
+
+        // a connection that has not sent the whole request head.
+        let mut pending = StdTcpStream::connect(addr).unwrap();
+        pending.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+        // a keep-alive connection accepted after the above one.
+        let mut idle = StdTcpStream::connect(addr).unwrap();
+        send_request(&mut idle);
+
+        let started = Instant::now();
+        handle.shutdown();
+
+        // the listener is closed and the new connections are refused.
+        while StdTcpStream::connect(addr).is_ok() {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "the server still accepts the connections"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // the idle connection is closed by the graceful shutdown, and the pending
+        // one is forcibly closed after the shutdown timeout at the latest.
+        assert!(wait_closed(&mut idle));
+        assert!(wait_closed(&mut pending));
+
+        server.join().unwrap().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn run_until_signal() {
+        let (tx, rx) = oneshot::channel::<()>();
+        let (addr, _handle, server) = start(move |server| server.run_until(rx));
+
+        let mut stream = StdTcpStream::connect(addr).unwrap();
+        send_request(&mut stream);
+
+        let started = Instant::now();
+        tx.send(()).unwrap();
+        assert!(wait_closed(&mut stream));
 
-        entered.block_on(shutdown).expect("never fail")
+        server.join().unwrap().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
     }
 }
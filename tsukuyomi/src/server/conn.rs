@@ -0,0 +1,1495 @@
+//! Wrappers of the connections used in `Server`.
+
+use {
+    futures01::{Async, Future, Poll},
+    izanami::http::Connection,
+    std::{
+        collections::VecDeque,
+        error::Error as StdError,
+        io::{self, Read, Write},
+        time::{Duration, Instant},
+    },
+    tokio::timer::Delay,
+    tokio_io::{AsyncRead, AsyncWrite},
+};
+
+type BoxedStdError = Box<dyn StdError + Send + Sync + 'static>;
+
+/// A `Connection` that waits for the completion of the handshake
+/// (e.g. TLS or the protocol detection) before serving the HTTP connection.
+pub(super) struct Handshake<Fut, F, C> {
+    state: HandshakeState<Fut, F, C>,
+    deadline: Option<Delay>,
+    shutdown: bool,
+}
+
+enum HandshakeState<Fut, F, C> {
+    Handshaking(Fut, Option<F>),
+    Serving(C),
+}
+
+impl<Fut, F, C> Handshake<Fut, F, C>
+where
+    Fut: Future,
+    Fut::Error: std::fmt::Display,
+    F: FnOnce(Fut::Item) -> C,
+    C: Connection,
+{
+    pub(super) fn new(handshake: Fut, serve: F) -> Self {
+        Self {
+            state: HandshakeState::Handshaking(handshake, Some(serve)),
+            deadline: None,
+            shutdown: false,
+        }
+    }
+
+    /// Sets the deadline of the handshake.
+    pub(super) fn deadline(self, deadline: Option<Instant>) -> Self {
+        Self {
+            deadline: deadline.map(Delay::new),
+            ..self
+        }
+    }
+}
+
+impl<Fut, F, C> Connection for Handshake<Fut, F, C>
+where
+    Fut: Future,
+    Fut::Error: std::fmt::Display,
+    F: FnOnce(Fut::Item) -> C,
+    C: Connection,
+{
+    type Error = C::Error;
+
+    fn poll_close(&mut self) -> Poll<(), Self::Error> {
+        loop {
+            self.state = match self.state {
+                HandshakeState::Handshaking(ref mut handshake, ref mut serve) => {
+                    if let Some(ref mut deadline) = self.deadline {
+                        match deadline.poll() {
+                            Ok(Async::NotReady) => {}
+                            Ok(Async::Ready(())) => {
+                                log::debug!("handshake error: timed out");
+                                return Ok(Async::Ready(()));
+                            }
+                            Err(err) => log::warn!("timer error: {}", err),
+                        }
+                    }
+                    match handshake.poll() {
+                        Ok(Async::Ready(io)) => {
+                            let serve = serve.take().expect("the handshake has already completed");
+                            let mut conn = serve(io);
+                            if self.shutdown {
+                                conn.graceful_shutdown();
+                            }
+                            HandshakeState::Serving(conn)
+                        }
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Err(err) => {
+                            log::debug!("handshake error: {}", err);
+                            return Ok(Async::Ready(()));
+                        }
+                    }
+                }
+                HandshakeState::Serving(ref mut conn) => return conn.poll_close(),
+            };
+        }
+    }
+
+    fn graceful_shutdown(&mut self) {
+        self.shutdown = true;
+        if let HandshakeState::Serving(ref mut conn) = self.state {
+            conn.graceful_shutdown();
+        }
+    }
+}
+
+/// A `Connection` that serves either HTTP/1 or HTTP/2.
+pub(super) enum EitherConnection<L, R> {
+    Left(L),
+    Right(R),
+}
+
+impl<L, R> Connection for EitherConnection<L, R>
+where
+    L: Connection,
+    L::Error: Into<BoxedStdError>,
+    R: Connection,
+    R::Error: Into<BoxedStdError>,
+{
+    type Error = BoxedStdError;
+
+    fn poll_close(&mut self) -> Poll<(), Self::Error> {
+        match self {
+            EitherConnection::Left(l) => l.poll_close().map_err(Into::into),
+            EitherConnection::Right(r) => r.poll_close().map_err(Into::into),
+        }
+    }
+
+    fn graceful_shutdown(&mut self) {
+        match self {
+            EitherConnection::Left(l) => l.graceful_shutdown(),
+            EitherConnection::Right(r) => r.graceful_shutdown(),
+        }
+    }
+}
+
+/// The connection preface sent by the HTTP/2 clients (RFC 7540, section 3.5).
+const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// The maximum size of the request head examined for the upgrade to h2c.
+///
+/// The larger request heads are served by HTTP/1.1 without upgrading.
+const MAX_UPGRADE_HEAD_SIZE: usize = 16 * 1024;
+
+/// The maximum number of header fields in the request upgrading to h2c.
+const MAX_UPGRADE_HEADERS: usize = 64;
+
+/// The response sent to the client when the connection is upgraded to h2c.
+const SWITCHING_PROTOCOLS: &[u8] =
+    b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n";
+
+/// The protocol used on the plain connection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Protocol {
+    Http1,
+    Http2,
+}
+
+/// Creates a future that reads the beginning of the stream to detect the protocol.
+///
+/// If `h2c` is `true`, the future detects whether the client starts HTTP/2 with
+/// prior knowledge, or requests to upgrade to h2c by the header field `Upgrade`
+/// (RFC 7540, section 3.2). In the latter case, the future sends `101 Switching Protocols`
+/// and the upgrading request is passed to HTTP/2 as the stream 1.
+///
+/// The read bytes are replayed by the returned `Rewind`.
+pub(super) fn detect_protocol<S>(io: S, h2c: bool) -> DetectProtocol<S>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    DetectProtocol {
+        io: Some(io),
+        h2c,
+        buf: vec![],
+        state: DetectState::Reading,
+    }
+}
+
+pub(super) struct DetectProtocol<S> {
+    io: Option<S>,
+    h2c: bool,
+    buf: Vec<u8>,
+    state: DetectState,
+}
+
+enum DetectState {
+    Reading,
+    Switching { written: usize, frames: Vec<u8> },
+    Flushing { frames: Vec<u8> },
+}
+
+enum Detected {
+    Incomplete,
+    Http1,
+    Http2,
+    Upgrade(Vec<u8>),
+}
+
+impl<S> DetectProtocol<S> {
+    fn examine(&self) -> Detected {
+        if self.buf.len() < H2_PREFACE.len() && H2_PREFACE.starts_with(&self.buf) {
+            return Detected::Incomplete;
+        }
+        if self.buf.starts_with(H2_PREFACE) {
+            return Detected::Http2;
+        }
+
+        match self
+            .buf
+            .windows(HEAD_TERMINATOR.len())
+            .position(|window| window == HEAD_TERMINATOR)
+        {
+            // The request with the payload or the pipelined requests are not upgraded.
+            Some(pos) if pos + HEAD_TERMINATOR.len() == self.buf.len() => {
+                match upgrade_request(&self.buf) {
+                    Some(frames) => Detected::Upgrade(frames),
+                    None => Detected::Http1,
+                }
+            }
+            Some(..) => Detected::Http1,
+            None if self.buf.len() >= MAX_UPGRADE_HEAD_SIZE => Detected::Http1,
+            None => Detected::Incomplete,
+        }
+    }
+
+    fn rewind(&mut self, upgraded: Option<Vec<u8>>) -> Rewind<S> {
+        Rewind {
+            prefix: std::mem::replace(&mut self.buf, vec![]),
+            pos: 0,
+            upgraded: upgraded.map(Upgraded::new),
+            io: self.io.take().expect("the future has already been polled"),
+        }
+    }
+}
+
+impl<S> Future for DetectProtocol<S>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    type Item = (Rewind<S>, Protocol);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if !self.h2c {
+            return Ok(Async::Ready((self.rewind(None), Protocol::Http1)));
+        }
+
+        loop {
+            self.state = match self.state {
+                DetectState::Reading => match self.examine() {
+                    Detected::Incomplete => {
+                        let mut chunk = [0u8; 1024];
+                        let n = {
+                            let io = self
+                                .io
+                                .as_mut()
+                                .expect("the future has already been polled");
+                            futures01::try_ready!(io.poll_read(&mut chunk))
+                        };
+                        if n == 0 {
+                            return Ok(Async::Ready((self.rewind(None), Protocol::Http1)));
+                        }
+                        self.buf.extend_from_slice(&chunk[..n]);
+                        continue;
+                    }
+                    Detected::Http1 => {
+                        return Ok(Async::Ready((self.rewind(None), Protocol::Http1)));
+                    }
+                    Detected::Http2 => {
+                        return Ok(Async::Ready((self.rewind(None), Protocol::Http2)));
+                    }
+                    Detected::Upgrade(frames) => DetectState::Switching { written: 0, frames },
+                },
+                DetectState::Switching {
+                    ref mut written,
+                    ref mut frames,
+                } => {
+                    let io = self
+                        .io
+                        .as_mut()
+                        .expect("the future has already been polled");
+                    while *written < SWITCHING_PROTOCOLS.len() {
+                        let n =
+                            futures01::try_ready!(io.poll_write(&SWITCHING_PROTOCOLS[*written..]));
+                        if n == 0 {
+                            return Err(io::ErrorKind::WriteZero.into());
+                        }
+                        *written += n;
+                    }
+                    DetectState::Flushing {
+                        frames: std::mem::replace(frames, vec![]),
+                    }
+                }
+                DetectState::Flushing { ref mut frames } => {
+                    futures01::try_ready!(self
+                        .io
+                        .as_mut()
+                        .expect("the future has already been polled")
+                        .poll_flush());
+                    // The request head has been consumed by the upgrade.
+                    self.buf.clear();
+                    let frames = std::mem::replace(frames, vec![]);
+                    return Ok(Async::Ready((self.rewind(Some(frames)), Protocol::Http2)));
+                }
+            };
+        }
+    }
+}
+
+/// Examines whether the request head is the valid request upgrading to h2c,
+/// and returns the HTTP/2 frames of the request translated for the stream 1.
+///
+/// The value of `HTTP2-Settings` is not applied to the connection, since the same
+/// settings are sent by the client with the SETTINGS frame after the connection preface.
+fn upgrade_request(head: &[u8]) -> Option<Vec<u8>> {
+    let mut headers = [httparse::EMPTY_HEADER; MAX_UPGRADE_HEADERS];
+    let mut request = httparse::Request::new(&mut headers);
+    match request.parse(head) {
+        Ok(httparse::Status::Complete(n)) if n == head.len() => {}
+        _ => return None,
+    }
+    if request.version != Some(1) {
+        return None;
+    }
+    let method = request.method?;
+    let path = request.path?;
+    if !path.starts_with('/') && path != "*" {
+        return None;
+    }
+
+    let mut upgrade = false;
+    let mut connection = vec![];
+    let mut num_settings = 0;
+    let mut authority = None;
+    for header in request.headers.iter() {
+        let name = header.name;
+        if name.eq_ignore_ascii_case("upgrade") {
+            upgrade |= tokens(header.value).any(|token| token.eq_ignore_ascii_case("h2c"));
+        } else if name.eq_ignore_ascii_case("connection") {
+            connection.extend(tokens(header.value).map(str::to_ascii_lowercase));
+        } else if name.eq_ignore_ascii_case("http2-settings") {
+            // The client sends the SETTINGS frame after the connection preface,
+            // and hence the settings in this header field are only validated.
+            let settings = base64::decode_config(header.value, base64::URL_SAFE_NO_PAD).ok()?;
+            if settings.len() % 6 != 0 {
+                return None;
+            }
+            num_settings += 1;
+        } else if name.eq_ignore_ascii_case("host") {
+            if authority.is_some() {
+                return None;
+            }
+            authority = Some(header.value);
+        } else if name.eq_ignore_ascii_case("transfer-encoding")
+            || (name.eq_ignore_ascii_case("content-length")
+                && std::str::from_utf8(header.value).map(str::trim) != Ok("0"))
+        {
+            // The request with the payload is not upgraded.
+            return None;
+        }
+    }
+    let has_option = |option: &str| connection.iter().any(|token| token == option);
+    if !upgrade || !has_option("upgrade") || !has_option("http2-settings") || num_settings != 1 {
+        return None;
+    }
+    let authority = authority?;
+
+    let mut block = vec![];
+    encode_header(&mut block, b":method", method.as_bytes());
+    encode_header(&mut block, b":scheme", b"http");
+    encode_header(&mut block, b":authority", authority);
+    encode_header(&mut block, b":path", path.as_bytes());
+    for header in request.headers.iter() {
+        let name = header.name.to_ascii_lowercase();
+        match &*name {
+            "connection" | "upgrade" | "http2-settings" | "host" | "keep-alive"
+            | "proxy-connection" | "transfer-encoding" | "content-length" => continue,
+            "te" if !header.value.eq_ignore_ascii_case(b"trailers") => continue,
+            name if has_option(name) => continue,
+            _ => {}
+        }
+        encode_header(&mut block, name.as_bytes(), header.value);
+    }
+
+    Some(encode_headers_frames(&block))
+}
+
+/// Returns an iterator over the comma-separated tokens in the header value.
+fn tokens(value: &[u8]) -> impl Iterator<Item = &str> {
+    std::str::from_utf8(value)
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+/// Encodes a header field as the literal header field without indexing,
+/// using the new name and no Huffman coding (RFC 7541, section 6.2.2).
+fn encode_header(block: &mut Vec<u8>, name: &[u8], value: &[u8]) {
+    block.push(0x00);
+    encode_string(block, name);
+    encode_string(block, value);
+}
+
+fn encode_string(block: &mut Vec<u8>, s: &[u8]) {
+    encode_integer(block, s.len(), 7);
+    block.extend_from_slice(s);
+}
+
+/// Encodes an integer with the N-bit prefix (RFC 7541, section 5.1).
+#[allow(clippy::cast_possible_truncation)]
+fn encode_integer(block: &mut Vec<u8>, mut n: usize, prefix_bits: u32) {
+    let max_prefix = (1 << prefix_bits) - 1;
+    if n < max_prefix {
+        block.push(n as u8);
+        return;
+    }
+    block.push(max_prefix as u8);
+    n -= max_prefix;
+    while n >= 0x80 {
+        block.push((n & 0x7f) as u8 | 0x80);
+        n >>= 7;
+    }
+    block.push(n as u8);
+}
+
+/// The maximum frame size that all HTTP/2 endpoints accept (RFC 7540, section 4.2).
+const MIN_MAX_FRAME_SIZE: usize = 16_384;
+
+const FRAME_TYPE_HEADERS: u8 = 0x1;
+const FRAME_TYPE_CONTINUATION: u8 = 0x9;
+const FLAG_END_STREAM: u8 = 0x1;
+const FLAG_END_HEADERS: u8 = 0x4;
+
+/// Splits the header block into the HEADERS and CONTINUATION frames of the stream 1,
+/// which is half-closed by the client at the upgrade (RFC 7540, section 3.2).
+#[allow(clippy::cast_possible_truncation)]
+fn encode_headers_frames(block: &[u8]) -> Vec<u8> {
+    let mut frames = Vec::with_capacity(block.len() + 9);
+    let mut chunks = block.chunks(MIN_MAX_FRAME_SIZE).peekable();
+    let mut frame_type = FRAME_TYPE_HEADERS;
+    let mut flags = FLAG_END_STREAM;
+    while let Some(chunk) = chunks.next() {
+        if chunks.peek().is_none() {
+            flags |= FLAG_END_HEADERS;
+        }
+        let len = chunk.len();
+        frames.extend_from_slice(&[
+            (len >> 16) as u8,
+            (len >> 8) as u8,
+            len as u8,
+            frame_type,
+            flags,
+            0,
+            0,
+            0,
+            1,
+        ]);
+        frames.extend_from_slice(chunk);
+        frame_type = FRAME_TYPE_CONTINUATION;
+        flags = 0;
+    }
+    frames
+}
+
+/// An I/O that replays the bytes read in advance before reading from the underlying I/O.
+///
+/// On the connection upgraded to h2c, the frames of the upgrading request are
+/// inserted after the connection preface and the first SETTINGS frame from the client.
+pub(super) struct Rewind<S> {
+    prefix: Vec<u8>,
+    pos: usize,
+    upgraded: Option<Upgraded>,
+    io: S,
+}
+
+impl<S> Rewind<S> {
+    /// Returns a mutable reference to the underlying I/O.
+    pub(super) fn get_mut(&mut self) -> &mut S {
+        &mut self.io
+    }
+}
+
+impl<S: Read> Read for Rewind<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos < self.prefix.len() {
+            let n = (&self.prefix[self.pos..]).read(buf)?;
+            self.pos += n;
+            return Ok(n);
+        }
+        match self.upgraded {
+            Some(ref mut upgraded) if !upgraded.is_done() => upgraded.read(&mut self.io, buf),
+            _ => self.io.read(buf),
+        }
+    }
+}
+
+impl<S: Write> Write for Rewind<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.io.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.io.flush()
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for Rewind<S> {}
+
+impl<S: AsyncWrite> AsyncWrite for Rewind<S> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.io.shutdown()
+    }
+}
+
+/// The length of the connection preface and the header of the first SETTINGS frame.
+const PREFACE_AND_SETTINGS_HEAD_LEN: usize = 24 + 9;
+
+/// The state of inserting the frames of the upgrading request.
+struct Upgraded {
+    frames: Vec<u8>,
+    pos: usize,
+    head: Vec<u8>,
+    received: usize,
+}
+
+impl Upgraded {
+    fn new(frames: Vec<u8>) -> Self {
+        Self {
+            frames,
+            pos: 0,
+            head: Vec::with_capacity(PREFACE_AND_SETTINGS_HEAD_LEN),
+            received: 0,
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.pos == self.frames.len()
+    }
+
+    /// Returns the position where the frames are inserted, if it is determined.
+    fn insert_at(&self) -> Option<usize> {
+        if self.head.len() < PREFACE_AND_SETTINGS_HEAD_LEN {
+            return None;
+        }
+        let len = self.head[24..27]
+            .iter()
+            .fold(0, |len, &b| (len << 8) | usize::from(b));
+        Some(PREFACE_AND_SETTINGS_HEAD_LEN + len)
+    }
+
+    fn read<S: Read>(&mut self, io: &mut S, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = match self.insert_at() {
+            Some(insert_at) => insert_at - self.received,
+            None => PREFACE_AND_SETTINGS_HEAD_LEN - self.received,
+        };
+        if remaining == 0 {
+            let n = (&self.frames[self.pos..]).read(buf)?;
+            self.pos += n;
+            return Ok(n);
+        }
+
+        let len = std::cmp::min(buf.len(), remaining);
+        let n = io.read(&mut buf[..len])?;
+        if self.head.len() < PREFACE_AND_SETTINGS_HEAD_LEN {
+            self.head.extend_from_slice(&buf[..n]);
+        }
+        self.received += n;
+        Ok(n)
+    }
+}
+
+/// The terminator of the message head in HTTP/1.
+const HEAD_TERMINATOR: &[u8] = b"\r\n\r\n";
+
+/// The maximum size of the request head received by `Timeouts`, used when
+/// the limit is not specified.
+const DEFAULT_MAX_REQUEST_HEAD_SIZE: usize = 1024 * 1024;
+
+/// An I/O that enforces the keep-alive timeout, the request head read timeout
+/// and the maximum size of the request head.
+///
+/// * The keep-alive timer runs only between the requests, that is, from when
+///   the whole response has been written until the client starts sending the
+///   next request, and the I/O fails with `TimedOut` if it expires.
+/// * The head timer runs until the end of each request head has been received.
+///   For the first request, it starts from the specified deadline, and for the
+///   subsequent requests, it starts when the first byte of the request arrives.
+/// * The I/O fails with `InvalidData` if a request head exceeds the maximum size,
+///   without buffering the rest of it.
+/// * Once the connection has been upgraded to another protocol (including
+///   HTTP/2), all of them are stopped.
+pub(super) struct Timeouts<S> {
+    io: S,
+    keep_alive_timeout: Option<Duration>,
+    keep_alive: Option<Delay>,
+    header_read_timeout: Option<Duration>,
+    head: Option<Delay>,
+    phase: Phase,
+    request: MessageScanner,
+    response: MessageScanner,
+    /// Whether each of the requests waiting for the response is a HEAD request.
+    head_requests: VecDeque<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    /// Waiting for the next request.
+    Idle,
+    /// Processing a request.
+    Busy,
+    /// Speaking another protocol.
+    Upgraded,
+}
+
+impl<S> Timeouts<S> {
+    pub(super) fn new(
+        io: S,
+        keep_alive_timeout: Option<Duration>,
+        header_read_timeout: Option<Duration>,
+        head_deadline: Option<Instant>,
+    ) -> Self {
+        Self {
+            io,
+            keep_alive_timeout,
+            keep_alive: keep_alive_timeout.map(|timeout| Delay::new(Instant::now() + timeout)),
+            header_read_timeout,
+            head: head_deadline.map(Delay::new),
+            phase: Phase::Idle,
+            request: MessageScanner::new(DEFAULT_MAX_REQUEST_HEAD_SIZE),
+            response: MessageScanner::new(MAX_RESPONSE_HEAD_SIZE),
+            head_requests: VecDeque::new(),
+        }
+    }
+
+    /// Sets the maximum size of the request head, in bytes.
+    pub(super) fn max_head_size(mut self, max: usize) -> Self {
+        self.request.max_head_size = max;
+        self
+    }
+
+    /// Stops the timers since the connection no longer speaks HTTP/1.
+    pub(super) fn upgraded(&mut self) {
+        self.phase = Phase::Upgraded;
+        self.keep_alive = None;
+        self.head = None;
+    }
+
+    fn on_read(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.phase == Phase::Upgraded {
+            return Ok(());
+        }
+        if self.phase == Phase::Idle {
+            self.phase = Phase::Busy;
+            self.keep_alive = None;
+        }
+
+        let head_requests = &mut self.head_requests;
+        let heads = self
+            .request
+            .scan(bytes, |head| {
+                let (state, head_request) = parse_request_head(head);
+                head_requests.push_back(head_request);
+                state
+            })
+            .map_err(|HeadTooLarge| {
+                io::Error::new(io::ErrorKind::InvalidData, "the request head is too large")
+            })?;
+
+        if heads > 0 {
+            self.head = None;
+        }
+        if self.request.in_head() && self.head.is_none() {
+            self.head = self
+                .header_read_timeout
+                .map(|timeout| Delay::new(Instant::now() + timeout));
+        }
+        Ok(())
+    }
+
+    fn on_write(&mut self, bytes: &[u8]) {
+        if self.phase == Phase::Upgraded {
+            return;
+        }
+        let head_requests = &mut self.head_requests;
+        let _ = self.response.scan(bytes, |head| {
+            // The responses to HEAD requests do not have the payload even if
+            // they contain `Content-Length`.
+            let head_request = head_requests.front().cloned().unwrap_or(false);
+            let state = parse_response_head(head, head_request);
+            if state != ScanState::Head {
+                head_requests.pop_front();
+            }
+            state
+        });
+
+        if self.response.state == ScanState::Upgraded {
+            self.upgraded();
+        } else if self.response.is_completed() {
+            if self.phase != Phase::Idle {
+                self.phase = Phase::Idle;
+                self.keep_alive = self
+                    .keep_alive_timeout
+                    .map(|timeout| Delay::new(Instant::now() + timeout));
+            }
+        } else {
+            self.phase = Phase::Busy;
+            self.keep_alive = None;
+        }
+    }
+
+    /// Checks whether the timers have expired, and registers the current task
+    /// to be notified at the expiration.
+    fn poll_timers(&mut self) -> io::Result<()> {
+        fn is_expired(delay: &mut Option<Delay>) -> bool {
+            match delay {
+                Some(delay) => match delay.poll() {
+                    Ok(Async::NotReady) => false,
+                    Ok(Async::Ready(())) => true,
+                    Err(err) => {
+                        log::warn!("timer error: {}", err);
+                        false
+                    }
+                },
+                None => false,
+            }
+        }
+
+        if is_expired(&mut self.head) {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "timed out while reading the request head",
+            ));
+        }
+        if is_expired(&mut self.keep_alive) {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the connection has been idle too long",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<S: Read> Read for Timeouts<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.io.read(buf) {
+            Ok(n) => {
+                if n > 0 {
+                    self.on_read(&buf[..n])?;
+                }
+                Ok(n)
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                self.poll_timers()?;
+                Err(io::ErrorKind::WouldBlock.into())
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl<S: Write> Write for Timeouts<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.io.write(buf) {
+            Ok(n) => {
+                if n > 0 {
+                    self.on_write(&buf[..n]);
+                }
+                Ok(n)
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                self.poll_timers()?;
+                Err(io::ErrorKind::WouldBlock.into())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.io.flush()
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for Timeouts<S> {}
+
+impl<S: AsyncWrite> AsyncWrite for Timeouts<S> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.io.shutdown()
+    }
+}
+
+/// The maximum size of the response head tracked by `Timeouts`.
+const MAX_RESPONSE_HEAD_SIZE: usize = 64 * 1024;
+
+/// The maximum number of header fields in the message parsed by `MessageScanner`.
+const MAX_HEADERS: usize = 128;
+
+/// The error that the message head exceeds the maximum size.
+#[derive(Debug)]
+struct HeadTooLarge;
+
+/// A scanner of the HTTP/1 messages sent in one direction, to detect the end of each message.
+///
+/// If the message cannot be tracked (e.g. the payload of a response is delimited
+/// by closing the connection), it is regarded as incomplete until the connection is closed.
+struct MessageScanner {
+    state: ScanState,
+    head: Vec<u8>,
+    max_head_size: usize,
+    completed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScanState {
+    Head,
+    Body(u64),
+    ChunkSize { size: u64, extension: bool },
+    ChunkData(u64),
+    ChunkEnd,
+    Trailers { line_len: usize },
+    UntilClose,
+    Upgraded,
+}
+
+impl MessageScanner {
+    fn new(max_head_size: usize) -> Self {
+        Self {
+            state: ScanState::Head,
+            head: vec![],
+            max_head_size,
+            completed: false,
+        }
+    }
+
+    /// Returns whether the last message has been completed.
+    fn is_completed(&self) -> bool {
+        self.completed
+    }
+
+    /// Returns whether a part of the message head has been received.
+    fn in_head(&self) -> bool {
+        self.state == ScanState::Head && !self.head.is_empty()
+    }
+
+    /// Scans the bytes and returns the number of message heads found in them.
+    ///
+    /// `on_head` is called with each message head and returns how its payload is delimited.
+    /// The message head that exceeds the maximum size is not passed to `on_head` and
+    /// the scanner stops tracking the messages.
+    fn scan(
+        &mut self,
+        mut bytes: &[u8],
+        mut on_head: impl FnMut(&[u8]) -> ScanState,
+    ) -> Result<usize, HeadTooLarge> {
+        let mut heads = 0;
+        while !bytes.is_empty() {
+            self.state = match self.state {
+                ScanState::Head => {
+                    if self.head.is_empty() {
+                        // The empty lines preceding the message are ignored (RFC 7230, section 3.5).
+                        let n = bytes
+                            .iter()
+                            .take_while(|&&b| b == b'\r' || b == b'\n')
+                            .count();
+                        bytes = &bytes[n..];
+                        if bytes.is_empty() {
+                            break;
+                        }
+                    }
+                    self.completed = false;
+                    let start = self.head.len().saturating_sub(HEAD_TERMINATOR.len() - 1);
+                    self.head.extend_from_slice(bytes);
+                    let end = self.head[start..]
+                        .windows(HEAD_TERMINATOR.len())
+                        .position(|window| window == HEAD_TERMINATOR)
+                        .map(|pos| start + pos + HEAD_TERMINATOR.len());
+                    if end.unwrap_or(self.head.len()) > self.max_head_size {
+                        self.head = vec![];
+                        self.state = ScanState::UntilClose;
+                        return Err(HeadTooLarge);
+                    }
+                    match end {
+                        Some(end) => {
+                            bytes = &bytes[bytes.len() - (self.head.len() - end)..];
+                            let state = on_head(&self.head[..end]);
+                            self.head.clear();
+                            heads += 1;
+                            self.body(state)
+                        }
+                        None => break,
+                    }
+                }
+                ScanState::Body(remaining) => {
+                    let n = advance(&mut bytes, remaining);
+                    self.body(ScanState::Body(remaining - n))
+                }
+                ScanState::ChunkData(remaining) => {
+                    let n = advance(&mut bytes, remaining);
+                    match remaining - n {
+                        0 => ScanState::ChunkEnd,
+                        remaining => ScanState::ChunkData(remaining),
+                    }
+                }
+                ScanState::UntilClose | ScanState::Upgraded => break,
+                state => {
+                    let b = bytes[0];
+                    bytes = &bytes[1..];
+                    self.body(scan_chunked(state, b))
+                }
+            };
+        }
+        Ok(heads)
+    }
+
+    /// Marks the message as completed if its payload has been received.
+    fn body(&mut self, state: ScanState) -> ScanState {
+        match state {
+            ScanState::Body(0) => {
+                self.completed = true;
+                ScanState::Head
+            }
+            state => state,
+        }
+    }
+}
+
+/// Determines how the payload of the request is delimited (RFC 7230, section 3.3.3),
+/// and returns it with whether the request is a HEAD request.
+fn parse_request_head(head: &[u8]) -> (ScanState, bool) {
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut request = httparse::Request::new(&mut headers);
+    match request.parse(head) {
+        Ok(httparse::Status::Complete(..)) => {}
+        _ => return (ScanState::UntilClose, false),
+    }
+    let head_request = request.method == Some("HEAD");
+    // The request without `Content-Length` and `Transfer-Encoding` has no payload.
+    let state = parse_payload_length(request.headers).unwrap_or(ScanState::Body(0));
+    (state, head_request)
+}
+
+/// Determines how the payload of the response is delimited (RFC 7230, section 3.3.3).
+fn parse_response_head(head: &[u8], head_request: bool) -> ScanState {
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut response = httparse::Response::new(&mut headers);
+    let code = match response.parse(head) {
+        Ok(httparse::Status::Complete(..)) => response.code.unwrap_or(0),
+        _ => return ScanState::UntilClose,
+    };
+    match code {
+        101 => return ScanState::Upgraded,
+        // The interim responses are followed by the final response.
+        100..=199 => return ScanState::Head,
+        204 | 304 => return ScanState::Body(0),
+        _ if head_request => return ScanState::Body(0),
+        _ => {}
+    }
+    parse_payload_length(response.headers).unwrap_or(ScanState::UntilClose)
+}
+
+/// Returns the state to scan the payload from `Transfer-Encoding` and `Content-Length`,
+/// or `None` if neither of them is present.
+fn parse_payload_length(headers: &[httparse::Header<'_>]) -> Option<ScanState> {
+    let mut state = None;
+    for header in headers {
+        if header.name.eq_ignore_ascii_case("transfer-encoding") {
+            let chunked = std::str::from_utf8(header.value)
+                .ok()
+                .and_then(|value| value.rsplit(',').next())
+                .map_or(false, |coding| {
+                    coding.trim().eq_ignore_ascii_case("chunked")
+                });
+            if !chunked {
+                return Some(ScanState::UntilClose);
+            }
+            state = Some(ScanState::ChunkSize {
+                size: 0,
+                extension: false,
+            });
+        } else if header.name.eq_ignore_ascii_case("content-length") && state.is_none() {
+            state = Some(
+                std::str::from_utf8(header.value)
+                    .ok()
+                    .and_then(|value| value.trim().parse().ok())
+                    .map_or(ScanState::UntilClose, ScanState::Body),
+            );
+        }
+    }
+    state
+}
+
+/// Advances the chunked payload by a byte, except the chunk data.
+fn scan_chunked(state: ScanState, b: u8) -> ScanState {
+    match state {
+        ScanState::ChunkSize { size, extension } => match b {
+            b'\n' if size == 0 => ScanState::Trailers { line_len: 0 },
+            b'\n' => ScanState::ChunkData(size),
+            b';' => ScanState::ChunkSize {
+                size,
+                extension: true,
+            },
+            b if !extension => match (b as char).to_digit(16) {
+                Some(digit) => match size.checked_mul(16) {
+                    Some(size) => ScanState::ChunkSize {
+                        size: size + u64::from(digit),
+                        extension,
+                    },
+                    None => ScanState::UntilClose,
+                },
+                None => state,
+            },
+            _ => state,
+        },
+        ScanState::ChunkEnd => match b {
+            b'\n' => ScanState::ChunkSize {
+                size: 0,
+                extension: false,
+            },
+            _ => state,
+        },
+        ScanState::Trailers { line_len } => match b {
+            b'\n' if line_len == 0 => ScanState::Body(0),
+            b'\n' => ScanState::Trailers { line_len: 0 },
+            b'\r' => state,
+            _ => ScanState::Trailers {
+                line_len: line_len + 1,
+            },
+        },
+        state => state,
+    }
+}
+
+/// Skips at most `max` bytes from the beginning of the slice, and returns the number of skipped bytes.
+#[allow(clippy::cast_possible_truncation)]
+fn advance(bytes: &mut &[u8], max: u64) -> u64 {
+    let n = std::cmp::min(bytes.len() as u64, max) as usize;
+    *bytes = &bytes[n..];
+    n as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::collections::VecDeque};
+
+    /// An I/O that returns the input chunks one by one and records the output.
+    struct MockIo {
+        input: VecDeque<Vec<u8>>,
+        output: Vec<u8>,
+        /// Whether to return `WouldBlock` instead of EOF after all input has been read.
+        would_block: bool,
+    }
+
+    fn mock_io(input: &[&[u8]]) -> MockIo {
+        MockIo {
+            input: input.iter().map(|chunk| chunk.to_vec()).collect(),
+            output: vec![],
+            would_block: false,
+        }
+    }
+
+    impl Read for MockIo {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let chunk = match self.input.front_mut() {
+                Some(chunk) => chunk,
+                None if self.would_block => return Err(io::ErrorKind::WouldBlock.into()),
+                None => return Ok(0),
+            };
+            let n = std::cmp::min(buf.len(), chunk.len());
+            buf[..n].copy_from_slice(&chunk[..n]);
+            chunk.drain(..n);
+            if chunk.is_empty() {
+                self.input.pop_front();
+            }
+            Ok(n)
+        }
+    }
+
+    impl Write for MockIo {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl AsyncRead for MockIo {}
+
+    impl AsyncWrite for MockIo {
+        fn shutdown(&mut self) -> Poll<(), io::Error> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    /// Runs the detection and returns the bytes read from `Rewind`,
+    /// the detected protocol and the bytes written by the detection.
+    fn detect(input: &[&[u8]]) -> (Vec<u8>, Protocol, Vec<u8>) {
+        let io = mock_io(input);
+        let (mut rewind, protocol) = detect_protocol(io, true).wait().unwrap();
+        let mut replayed = vec![];
+        rewind.read_to_end(&mut replayed).unwrap();
+        (replayed, protocol, rewind.io.output)
+    }
+
+    const SETTINGS: &[u8] = b"\x00\x00\x06\x04\x00\x00\x00\x00\x00\x00\x03\x00\x00\x00\x64";
+
+    const UPGRADE_REQUEST: &[u8] = b"GET /index.html HTTP/1.1\r\n\
+        Host: example.com\r\n\
+        Connection: Upgrade, HTTP2-Settings\r\n\
+        Upgrade: h2c\r\n\
+        HTTP2-Settings: AAMAAABkAARAAAAAAAIAAAAA\r\n\
+        Accept: */*\r\n\
+        \r\n";
+
+    #[test]
+    fn detect_prior_knowledge() {
+        let mut input = H2_PREFACE.to_vec();
+        input.extend_from_slice(SETTINGS);
+        assert_eq!(
+            detect(&[&input[..10], &input[10..]]),
+            (input.clone(), Protocol::Http2, vec![])
+        );
+    }
+
+    #[test]
+    fn detect_http1() {
+        let input = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        assert_eq!(
+            detect(&[&input[..20], &input[20..]]),
+            (input.to_vec(), Protocol::Http1, vec![])
+        );
+    }
+
+    #[test]
+    fn detect_short_input() {
+        assert_eq!(
+            detect(&[b"PRI *"]),
+            (b"PRI *".to_vec(), Protocol::Http1, vec![])
+        );
+        assert_eq!(detect(&[]), (vec![], Protocol::Http1, vec![]));
+    }
+
+    #[test]
+    fn detect_disabled() {
+        let io = mock_io(&[H2_PREFACE]);
+        let (rewind, protocol) = detect_protocol(io, false).wait().unwrap();
+        assert_eq!(protocol, Protocol::Http1);
+        assert_eq!(rewind.io.input.len(), 1);
+    }
+
+    #[test]
+    fn upgrade_to_h2c() {
+        let mut h2_input = H2_PREFACE.to_vec();
+        h2_input.extend_from_slice(SETTINGS);
+        h2_input.extend_from_slice(b"\x00\x00\x00\x04\x01\x00\x00\x00\x00"); // SETTINGS ACK
+
+        let (replayed, protocol, output) = detect(&[
+            &UPGRADE_REQUEST[..30],
+            &UPGRADE_REQUEST[30..],
+            &h2_input[..30],
+            &h2_input[30..],
+        ]);
+        assert_eq!(protocol, Protocol::Http2);
+        assert_eq!(output, SWITCHING_PROTOCOLS);
+
+        let mut block = vec![];
+        encode_header(&mut block, b":method", b"GET");
+        encode_header(&mut block, b":scheme", b"http");
+        encode_header(&mut block, b":authority", b"example.com");
+        encode_header(&mut block, b":path", b"/index.html");
+        encode_header(&mut block, b"accept", b"*/*");
+
+        let mut expected = H2_PREFACE.to_vec();
+        expected.extend_from_slice(SETTINGS);
+        expected.extend_from_slice(&[0, 0, block.len() as u8, 0x1, 0x5, 0, 0, 0, 1]);
+        expected.extend_from_slice(&block);
+        expected.extend_from_slice(b"\x00\x00\x00\x04\x01\x00\x00\x00\x00");
+        assert_eq!(replayed, expected);
+    }
+
+    #[test]
+    fn upgrade_ignored() {
+        let with_body = b"POST / HTTP/1.1\r\n\
+            Host: example.com\r\n\
+            Connection: Upgrade, HTTP2-Settings\r\n\
+            Upgrade: h2c\r\n\
+            HTTP2-Settings: AAMAAABkAARAAAAAAAIAAAAA\r\n\
+            Content-Length: 5\r\n\
+            \r\n\
+            hello";
+        let missing_settings = b"GET / HTTP/1.1\r\n\
+            Host: example.com\r\n\
+            Connection: Upgrade\r\n\
+            Upgrade: h2c\r\n\
+            \r\n";
+        let other_protocol = b"GET / HTTP/1.1\r\n\
+            Host: example.com\r\n\
+            Connection: Upgrade, HTTP2-Settings\r\n\
+            Upgrade: websocket\r\n\
+            HTTP2-Settings: AAMAAABkAARAAAAAAAIAAAAA\r\n\
+            \r\n";
+        for &input in &[&with_body[..], &missing_settings[..], &other_protocol[..]] {
+            assert_eq!(
+                detect(&[input]),
+                (input.to_vec(), Protocol::Http1, vec![]),
+                "{}",
+                String::from_utf8_lossy(input),
+            );
+        }
+    }
+
+    #[test]
+    fn large_header_block() {
+        let mut block = vec![];
+        encode_header(&mut block, b"x-large", &vec![b'a'; MIN_MAX_FRAME_SIZE]);
+        let frames = encode_headers_frames(&block);
+
+        let rest = block.len() - MIN_MAX_FRAME_SIZE;
+        assert_eq!(frames[..9], [0x00, 0x40, 0x00, 0x1, 0x1, 0, 0, 0, 1]);
+        let continuation = &frames[9 + MIN_MAX_FRAME_SIZE..];
+        assert_eq!(continuation[..9], [0, 0, rest as u8, 0x9, 0x4, 0, 0, 0, 1]);
+        assert_eq!(continuation.len(), 9 + rest);
+    }
+
+    #[test]
+    fn encode_integer_with_prefix() {
+        let mut block = vec![];
+        encode_integer(&mut block, 10, 7);
+        encode_integer(&mut block, 1337, 5);
+        assert_eq!(block, [10, 0b1_1111, 0b1001_1010, 0b0000_1010]);
+    }
+
+    #[test]
+    fn scan_request_heads() {
+        let mut io = Timeouts::new(
+            io::empty(),
+            None,
+            Some(Duration::from_secs(10)),
+            Some(Instant::now() + Duration::from_secs(10)),
+        );
+        io.on_read(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r")
+            .unwrap();
+        assert!(io.head.is_some());
+        io.on_read(b"\nPOST / HTTP/1.1\r\nContent-Length: 5\r\n")
+            .unwrap();
+        assert!(io.head.is_some());
+        io.on_read(b"\r\nhel").unwrap();
+        assert!(io.head.is_none());
+        io.on_read(b"lo").unwrap();
+        assert!(io.head.is_none());
+
+        // the timer is armed again for the next request.
+        io.on_read(b"\r\nG").unwrap();
+        assert!(io.head.is_some());
+        io.on_read(b"ET / HTTP/1.1\r\n\r\n").unwrap();
+        assert!(io.head.is_none());
+        assert_eq!(io.head_requests, vec![false, false, false]);
+    }
+
+    #[test]
+    fn scan_chunked_request() {
+        let mut io = Timeouts::new(io::empty(), None, Some(Duration::from_secs(10)), None);
+        io.on_read(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n")
+            .unwrap();
+        io.on_read(b"5\r\nhello\r\n0\r\n\r\nHEAD / HTTP/1.1\r\n")
+            .unwrap();
+        assert!(io.head.is_some());
+        assert_eq!(io.head_requests, vec![false]);
+        io.on_read(b"\r\n").unwrap();
+        assert!(io.head.is_none());
+        assert_eq!(io.head_requests, vec![false, true]);
+    }
+
+    #[test]
+    fn request_head_too_large() {
+        let mut io = Timeouts::new(io::empty(), None, None, None).max_head_size(32);
+        io.on_read(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        let err = io
+            .on_read(b"GET / HTTP/1.1\r\nX-Padding: aaaaaaaaaaaaaaaa")
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    fn scan_responses(chunks: &[&[u8]], head_request: bool) -> Vec<(bool, ScanState)> {
+        let mut scanner = MessageScanner::new(MAX_RESPONSE_HEAD_SIZE);
+        chunks
+            .iter()
+            .map(|chunk| {
+                let _ = scanner.scan(chunk, |head| parse_response_head(head, head_request));
+                (scanner.is_completed(), scanner.state)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scan_response_with_content_length() {
+        assert_eq!(
+            scan_responses(
+                &[
+                    b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n",
+                    b"\r\nhello",
+                    b"world",
+                ],
+                false
+            ),
+            vec![
+                (false, ScanState::Head),
+                (false, ScanState::Body(5)),
+                (true, ScanState::Head),
+            ]
+        );
+        assert_eq!(
+            scan_responses(&[b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n"], true),
+            vec![(true, ScanState::Head)]
+        );
+    }
+
+    #[test]
+    fn scan_chunked_response() {
+        assert_eq!(
+            scan_responses(
+                &[
+                    b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+                    b"5;ext=1\r\nhello\r\n",
+                    b"1A\r\nabcdefghijklmnopqrstuvwxyz\r\n0\r\n",
+                    b"Trailer: value\r\n",
+                    b"\r\n",
+                ],
+                false
+            ),
+            vec![
+                (
+                    false,
+                    ScanState::ChunkSize {
+                        size: 0,
+                        extension: false
+                    }
+                ),
+                (
+                    false,
+                    ScanState::ChunkSize {
+                        size: 0,
+                        extension: false
+                    }
+                ),
+                (false, ScanState::Trailers { line_len: 0 }),
+                (false, ScanState::Trailers { line_len: 0 }),
+                (true, ScanState::Head),
+            ]
+        );
+    }
+
+    #[test]
+    fn scan_pipelined_responses() {
+        assert_eq!(
+            scan_responses(
+                &[b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 204 No Content\r\n\r\nHTTP/1.1 200 OK\r\n"],
+                false
+            ),
+            vec![(false, ScanState::Head)]
+        );
+        assert_eq!(
+            scan_responses(
+                &[
+                    b"HTTP/1.1 100 Continue\r\n\r\n",
+                    b"HTTP/1.1 304 Not Modified\r\n\r\n"
+                ],
+                false
+            ),
+            vec![(false, ScanState::Head), (true, ScanState::Head)]
+        );
+    }
+
+    #[test]
+    fn scan_untracked_response() {
+        assert_eq!(
+            scan_responses(&[b"HTTP/1.1 200 OK\r\n\r\nhello", b"world"], false),
+            vec![
+                (false, ScanState::UntilClose),
+                (false, ScanState::UntilClose)
+            ]
+        );
+        assert_eq!(
+            scan_responses(
+                &[b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\r\n"],
+                false
+            ),
+            vec![(false, ScanState::Upgraded)]
+        );
+    }
+
+    #[test]
+    fn keep_alive_between_requests() {
+        let mut io = Timeouts::new(
+            mock_io(&[b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n"]),
+            Some(Duration::from_secs(10)),
+            None,
+            None,
+        );
+        assert_eq!(io.phase, Phase::Idle);
+        assert!(io.keep_alive.is_some());
+
+        assert!(io.read(&mut [0u8; 1024]).unwrap() > 0);
+        assert_eq!(io.phase, Phase::Busy);
+        assert!(io.keep_alive.is_none());
+
+        io.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhel")
+            .unwrap();
+        assert_eq!(io.phase, Phase::Busy);
+        assert!(io.keep_alive.is_none());
+
+        io.write_all(b"lo").unwrap();
+        assert_eq!(io.phase, Phase::Idle);
+        assert!(io.keep_alive.is_some());
+    }
+
+    #[test]
+    fn no_timers_after_upgrade() {
+        let mut io = Timeouts::new(
+            mock_io(&[b"GET /ws HTTP/1.1\r\n"]),
+            Some(Duration::from_secs(10)),
+            Some(Duration::from_secs(10)),
+            Some(Instant::now() + Duration::from_secs(10)),
+        );
+        assert!(io.read(&mut [0u8; 1024]).unwrap() > 0);
+        assert!(io.head.is_some());
+
+        io.write_all(b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\r\n")
+            .unwrap();
+        assert_eq!(io.phase, Phase::Upgraded);
+        assert!(io.keep_alive.is_none());
+        assert!(io.head.is_none());
+
+        io.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+        assert_eq!(io.phase, Phase::Upgraded);
+        assert!(io.keep_alive.is_none());
+    }
+
+    #[test]
+    fn pipelined_head_request() {
+        let mut io = Timeouts::new(
+            mock_io(&[b"HEAD / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n"]),
+            Some(Duration::from_secs(10)),
+            None,
+            None,
+        );
+        assert!(io.read(&mut [0u8; 1024]).unwrap() > 0);
+
+        io.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n")
+            .unwrap();
+        assert_eq!(io.phase, Phase::Idle);
+
+        io.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n")
+            .unwrap();
+        assert_eq!(io.phase, Phase::Busy);
+        io.write_all(b"hello").unwrap();
+        assert_eq!(io.phase, Phase::Idle);
+        assert!(io.head_requests.is_empty());
+    }
+
+    #[test]
+    fn head_timeout_on_subsequent_request() {
+        let mut io = Timeouts::new(
+            mock_io(&[b"GET / HTTP/1.1\r\n\r\n", b"GET / HT"]),
+            None,
+            Some(Duration::from_millis(50)),
+            None,
+        );
+        assert!(io.read(&mut [0u8; 1024]).unwrap() > 0);
+        assert!(io.head.is_none());
+        io.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+
+        // the client sends the part of the next request head and then stalls.
+        assert!(io.read(&mut [0u8; 1024]).unwrap() > 0);
+        assert!(io.head.is_some());
+        io.io.would_block = true;
+
+        let mut rt = tokio::runtime::current_thread::Runtime::new().unwrap();
+        let err = rt
+            .block_on(futures01::future::poll_fn(|| -> Poll<(), io::Error> {
+                match io.read(&mut [0u8; 1024]) {
+                    Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Ok(Async::NotReady),
+                    Ok(n) => panic!("unexpected read: {} bytes", n),
+                    Err(err) => Err(err),
+                }
+            }))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,450 @@
+//! The listeners used in `Server` and the loop accepting connections from them.
+
+use {
+    futures01::{
+        task::{self, Task},
+        Async, Future, Poll, Stream,
+    },
+    izanami::http::Connection,
+    std::{
+        error::Error as StdError,
+        io,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant},
+    },
+    tokio::timer::Delay,
+};
+
+#[cfg(unix)]
+use {
+    crate::app::PeerCred,
+    std::{
+        env,
+        fs::{self, Permissions},
+        net::TcpListener,
+        os::unix::{
+            fs::{FileTypeExt, PermissionsExt},
+            io::{AsRawFd, FromRawFd, IntoRawFd, RawFd},
+            net::{UnixListener, UnixStream},
+        },
+        path::Path,
+    },
+};
+
+type BoxedStdError = Box<dyn StdError + Send + Sync + 'static>;
+
+/// The duration to pause accepting after an error occurred, e.g. running out of file descriptors.
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The limit of the number of connections shared among the listeners.
+#[derive(Debug)]
+pub(super) struct ConnectionLimit {
+    max: usize,
+    active: AtomicUsize,
+    waiters: Mutex<Vec<Task>>,
+}
+
+impl ConnectionLimit {
+    pub(super) fn new(max: usize) -> Self {
+        Self {
+            max,
+            active: AtomicUsize::new(0),
+            waiters: Mutex::new(vec![]),
+        }
+    }
+
+    /// Returns whether a new connection can be accepted.
+    ///
+    /// If not, the current task is notified when one of the active connections is closed.
+    fn poll_ready(&self) -> bool {
+        if self.active.load(Ordering::SeqCst) < self.max {
+            return true;
+        }
+        {
+            let mut waiters = self.waiters.lock().unwrap_or_else(|e| e.into_inner());
+            // the listener is polled repeatedly while the limit is reached.
+            if !waiters.iter().any(Task::will_notify_current) {
+                waiters.push(task::current());
+            }
+        }
+        // re-check since a connection may be closed before registering the task.
+        self.active.load(Ordering::SeqCst) < self.max
+    }
+
+    fn acquire(this: &Arc<Self>) -> ConnectionGuard {
+        this.active.fetch_add(1, Ordering::SeqCst);
+        ConnectionGuard(this.clone())
+    }
+}
+
+/// A guard that decrements the number of active connections when dropped.
+struct ConnectionGuard(Arc<ConnectionLimit>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::SeqCst);
+        let waiters = std::mem::replace(
+            &mut *self.0.waiters.lock().unwrap_or_else(|e| e.into_inner()),
+            vec![],
+        );
+        for waiter in waiters {
+            waiter.notify();
+        }
+    }
+}
+
+/// A future that accepts the incoming connections and spawns them onto the runtime.
+///
+/// If the limit of connections is reached, the listener stops accepting
+/// until some of the active connections are closed.
+pub(super) struct Serve<I, F> {
+    incoming: I,
+    make_connection: F,
+    limit: Option<Arc<ConnectionLimit>>,
+    backoff: Option<Delay>,
+}
+
+impl<I, F, C> Serve<I, F>
+where
+    I: Stream<Error = io::Error>,
+    F: FnMut(I::Item) -> C,
+    C: Connection + Send + 'static,
+    C::Error: Into<BoxedStdError>,
+{
+    pub(super) fn new(
+        incoming: I,
+        make_connection: F,
+        limit: Option<Arc<ConnectionLimit>>,
+    ) -> Self {
+        Self {
+            incoming,
+            make_connection,
+            limit,
+            backoff: None,
+        }
+    }
+}
+
+impl<I, F, C> Future for Serve<I, F>
+where
+    I: Stream<Error = io::Error>,
+    F: FnMut(I::Item) -> C,
+    C: Connection + Send + 'static,
+    C::Error: Into<BoxedStdError>,
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            if let Some(mut backoff) = self.backoff.take() {
+                if let Ok(Async::NotReady) = backoff.poll() {
+                    self.backoff = Some(backoff);
+                    return Ok(Async::NotReady);
+                }
+            }
+
+            if let Some(ref limit) = self.limit {
+                if !limit.poll_ready() {
+                    return Ok(Async::NotReady);
+                }
+            }
+
+            match self.incoming.poll() {
+                Ok(Async::Ready(Some(stream))) => {
+                    let guard = self.limit.as_ref().map(ConnectionLimit::acquire);
+                    let mut conn = (self.make_connection)(stream);
+                    tokio::spawn(
+                        futures01::future::poll_fn(move || conn.poll_close())
+                            .map_err(|e| {
+                                let e: BoxedStdError = e.into();
+                                log::debug!("connection error: {}", e);
+                            })
+                            .then(move |result| {
+                                drop(guard);
+                                result
+                            }),
+                    );
+                }
+                Ok(Async::Ready(None)) => return Ok(Async::Ready(())),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(e) => {
+                    log::error!("failed to accept a connection: {}", e);
+                    self.backoff = Some(Delay::new(Instant::now() + ACCEPT_ERROR_BACKOFF));
+                }
+            }
+        }
+    }
+}
+
+/// Creates a Unix domain socket listener bound to the specified path.
+///
+/// The stale socket file left by the previous process is removed before binding,
+/// and the permissions of the socket file are changed to `mode` if specified.
+#[cfg(unix)]
+pub(super) fn bind_unix(path: &Path, mode: Option<u32>) -> io::Result<UnixListener> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "the path already exists and is not a socket",
+            ));
+        }
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "another process is listening on the socket",
+            ));
+        }
+        fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    if let Some(mode) = mode {
+        fs::set_permissions(path, Permissions::from_mode(mode))?;
+    }
+    Ok(listener)
+}
+
+/// Returns the credentials of the peer connected to the Unix domain socket.
+#[cfg(unix)]
+pub(super) fn peer_cred(stream: &tokio::net::UnixStream) -> Option<PeerCred> {
+    stream.peer_cred().ok().map(|cred| PeerCred {
+        uid: cred.uid,
+        gid: cred.gid,
+    })
+}
+
+/// A listener passed from the service manager.
+#[cfg(unix)]
+#[derive(Debug)]
+pub(super) enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+#[cfg(unix)]
+impl Listener {
+    /// Takes the ownership of the listening socket and detects its address family.
+    ///
+    /// The close-on-exec flag is set to the socket so that it is not leaked to
+    /// the child processes. The socket is closed if it is not a listening stream socket.
+    unsafe fn from_raw_fd(fd: RawFd) -> io::Result<Self> {
+        let listener = TcpListener::from_raw_fd(fd);
+        set_cloexec(listener.as_raw_fd())?;
+        if getsockopt(listener.as_raw_fd(), libc::SOL_SOCKET, libc::SO_TYPE)? != libc::SOCK_STREAM {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the passed socket is not a stream socket",
+            ));
+        }
+        if getsockopt(listener.as_raw_fd(), libc::SOL_SOCKET, libc::SO_ACCEPTCONN)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the passed socket is not listening",
+            ));
+        }
+
+        if listener.local_addr().is_ok() {
+            return Ok(Listener::Tcp(listener));
+        }
+
+        let listener = UnixListener::from_raw_fd(listener.into_raw_fd());
+        listener.local_addr()?;
+        Ok(Listener::Unix(listener))
+    }
+}
+
+/// Returns the value of the integer socket option.
+#[cfg(unix)]
+#[allow(clippy::cast_possible_truncation)]
+fn getsockopt(fd: RawFd, level: libc::c_int, name: libc::c_int) -> io::Result<libc::c_int> {
+    let mut value: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            level,
+            name,
+            &mut value as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(value)
+}
+
+/// Sets the flag `FD_CLOEXEC` to the file descriptor.
+#[cfg(unix)]
+fn set_cloexec(fd: RawFd) -> io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The first file descriptor passed by the socket activation.
+#[cfg(unix)]
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Takes the listeners passed from the service manager by the socket activation
+/// (the `LISTEN_PID` and `LISTEN_FDS` environment variables, as in systemd).
+///
+/// The environment variables are removed so that the child processes do not
+/// inherit them. If the listeners are not passed to this process, the empty
+/// list is returned. If one of the passed sockets is invalid, all of the passed
+/// sockets are closed and the error is returned.
+#[cfg(unix)]
+pub(super) fn listen_fds() -> io::Result<Vec<Listener>> {
+    let pid = match env::var("LISTEN_PID") {
+        Ok(pid) => pid,
+        Err(..) => return Ok(vec![]),
+    };
+    if pid.trim().parse::<u32>().ok() != Some(std::process::id()) {
+        return Ok(vec![]);
+    }
+
+    let num_fds = env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|n| n.trim().parse::<RawFd>().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid LISTEN_FDS"))?;
+
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    // take the ownership of all file descriptors before checking the errors,
+    // so that the remaining ones are closed on failure.
+    let listeners: Vec<_> = (LISTEN_FDS_START..LISTEN_FDS_START + num_fds)
+        .map(|fd| unsafe { Listener::from_raw_fd(fd) })
+        .collect();
+    listeners.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connection_limit() {
+        futures01::future::lazy(|| {
+            let limit = Arc::new(ConnectionLimit::new(2));
+            assert!(limit.poll_ready());
+
+            let guard1 = ConnectionLimit::acquire(&limit);
+            let guard2 = ConnectionLimit::acquire(&limit);
+            assert!(!limit.poll_ready());
+            assert!(!limit.poll_ready());
+            assert_eq!(limit.waiters.lock().unwrap().len(), 1);
+
+            drop(guard1);
+            assert!(limit.waiters.lock().unwrap().is_empty());
+            assert!(limit.poll_ready());
+
+            drop(guard2);
+            assert_eq!(limit.active.load(Ordering::SeqCst), 0);
+            Ok::<(), ()>(())
+        })
+        .wait()
+        .unwrap();
+    }
+
+    /// The path of a socket file in the temporary directory, removed when dropped.
+    #[cfg(unix)]
+    struct TempSocketPath(std::path::PathBuf);
+
+    #[cfg(unix)]
+    impl TempSocketPath {
+        fn new(name: &str) -> Self {
+            TempSocketPath(std::env::temp_dir().join(format!(
+                "tsukuyomi-test-{}-{}.sock",
+                std::process::id(),
+                name
+            )))
+        }
+
+        fn path(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    #[cfg(unix)]
+    impl Drop for TempSocketPath {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[cfg(unix)]
+    fn is_cloexec(fd: RawFd) -> bool {
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        assert_ne!(flags, -1);
+        flags & libc::FD_CLOEXEC != 0
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn bind_unix_with_permissions() {
+        let temp = TempSocketPath::new("permissions");
+        let path = temp.path();
+        let listener = bind_unix(path, Some(0o660)).unwrap();
+        let mode = fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+
+        // the socket is in use.
+        assert!(bind_unix(path, None).is_err());
+
+        // the stale socket file is replaced.
+        drop(listener);
+        assert!(bind_unix(path, None).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn bind_unix_rejects_regular_file() {
+        let temp = TempSocketPath::new("regular-file");
+        fs::write(temp.path(), b"").unwrap();
+        assert!(bind_unix(temp.path(), None).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn listener_from_raw_fd() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        // the inherited sockets may not have the close-on-exec flag.
+        assert_ne!(
+            unsafe { libc::fcntl(tcp.as_raw_fd(), libc::F_SETFD, 0) },
+            -1
+        );
+        assert!(!is_cloexec(tcp.as_raw_fd()));
+        let listener = unsafe { Listener::from_raw_fd(tcp.into_raw_fd()) }.unwrap();
+        match listener {
+            Listener::Tcp(ref listener) => assert!(is_cloexec(listener.as_raw_fd())),
+            ref listener => panic!("unexpected listener: {:?}", listener),
+        }
+
+        let temp = TempSocketPath::new("from-raw-fd");
+        let unix = bind_unix(temp.path(), None).unwrap();
+        let listener = unsafe { Listener::from_raw_fd(unix.into_raw_fd()) }.unwrap();
+        matches::assert_matches!(listener, Listener::Unix(..));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn listener_from_raw_fd_rejects_non_listening_sockets() {
+        let udp = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        assert!(unsafe { Listener::from_raw_fd(udp.into_raw_fd()) }.is_err());
+
+        let (stream, _peer) = UnixStream::pair().unwrap();
+        assert!(unsafe { Listener::from_raw_fd(stream.into_raw_fd()) }.is_err());
+    }
+}
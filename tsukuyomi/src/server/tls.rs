@@ -0,0 +1,408 @@
+//! TLS support based on `rustls`.
+
+pub use tokio_rustls::rustls;
+
+use {
+    rustls::{
+        internal::pemfile,
+        sign::{self, CertifiedKey},
+        Certificate, ClientHello, NoClientAuth, PrivateKey, ResolvesServerCert, ServerConfig,
+        Session,
+    },
+    std::{
+        collections::HashMap,
+        fmt,
+        fs::File,
+        io::{self, BufReader, Cursor, Read},
+        path::Path,
+        sync::Arc,
+    },
+    tokio_rustls::server::TlsStream,
+};
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Loads the certificate chain from a PEM file.
+pub fn load_certs(path: impl AsRef<Path>) -> io::Result<Vec<Certificate>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certs = pemfile::certs(&mut reader) //
+        .map_err(|()| invalid_data("invalid PEM certificate"))?;
+    if certs.is_empty() {
+        return Err(invalid_data("no certificate found"));
+    }
+    Ok(certs)
+}
+
+/// Loads the private key from a PEM file.
+///
+/// Both PKCS#8 and RSA (PKCS#1) private keys are supported.
+pub fn load_private_key(path: impl AsRef<Path>) -> io::Result<PrivateKey> {
+    let mut pem = vec![];
+    File::open(path)?.read_to_end(&mut pem)?;
+
+    let mut keys = pemfile::pkcs8_private_keys(&mut Cursor::new(&pem[..]))
+        .map_err(|()| invalid_data("invalid PEM private key"))?;
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut Cursor::new(&pem[..]))
+            .map_err(|()| invalid_data("invalid PEM private key"))?;
+    }
+
+    keys.into_iter()
+        .next()
+        .ok_or_else(|| invalid_data("no private key found"))
+}
+
+fn load_certified_key(
+    cert_path: impl AsRef<Path>,
+    key_path: impl AsRef<Path>,
+) -> io::Result<CertifiedKey> {
+    let certs = load_certs(cert_path)?;
+    let key = load_private_key(key_path)?;
+    let signing_key = sign::any_supported_type(&key) //
+        .map_err(|()| invalid_data("unsupported private key type"))?;
+    Ok(CertifiedKey::new(certs, Arc::new(signing_key)))
+}
+
+/// A builder of the TLS configuration used in `Server::bind_tls`.
+///
+/// ```no_run
+/// # use tsukuyomi::server::tls::TlsConfig;
+/// # fn main() -> std::io::Result<()> {
+/// let config = TlsConfig::new()
+///     .certificate("/path/to/default.crt", "/path/to/default.key")?
+///     .sni_certificate("api.example.com", "/path/to/api.crt", "/path/to/api.key")?
+///     .build()?;
+/// # drop(config);
+/// # Ok(())
+/// # }
+/// ```
+pub struct TlsConfig {
+    default_cert: Option<CertifiedKey>,
+    sni_certs: HashMap<String, CertifiedKey>,
+    alpn_protocols: Vec<Vec<u8>>,
+}
+
+impl fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("default_cert", &self.default_cert.is_some())
+            .field("sni_certs", &self.sni_certs.keys().collect::<Vec<_>>())
+            .field("alpn_protocols", &self.alpn_protocols)
+            .finish()
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TlsConfig {
+    /// Creates a new `TlsConfig` with the default configuration.
+    pub fn new() -> Self {
+        Self {
+            default_cert: None,
+            sni_certs: HashMap::new(),
+            alpn_protocols: vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+        }
+    }
+
+    /// Sets the certificate chain and private key used by default.
+    ///
+    /// This certificate is used if the client does not send the SNI extension or
+    /// no certificate registered by `sni_certificate` matches the requested hostname.
+    pub fn certificate(
+        mut self,
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> io::Result<Self> {
+        self.default_cert = Some(load_certified_key(cert_path, key_path)?);
+        Ok(self)
+    }
+
+    /// Registers the certificate chain and private key used for the specified hostname.
+    ///
+    /// The hostname may start with a wildcard label, e.g. `*.example.com`.
+    pub fn sni_certificate(
+        mut self,
+        hostname: &str,
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> io::Result<Self> {
+        let certified_key = load_certified_key(cert_path, key_path)?;
+        self.sni_certs
+            .insert(hostname.to_ascii_lowercase(), certified_key);
+        Ok(self)
+    }
+
+    /// Sets the list of protocols offered by ALPN, in order of preference.
+    ///
+    /// The default value is `["h2", "http/1.1"]`.
+    pub fn alpn_protocols<I, P>(mut self, protocols: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<[u8]>,
+    {
+        self.alpn_protocols = protocols
+            .into_iter()
+            .map(|protocol| protocol.as_ref().to_vec())
+            .collect();
+        self
+    }
+
+    /// Creates a `ServerConfig` from this configuration.
+    pub fn build(self) -> io::Result<ServerConfig> {
+        if self.default_cert.is_none() && self.sni_certs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no certificate is registered",
+            ));
+        }
+
+        let mut config = ServerConfig::new(NoClientAuth::new());
+        config.cert_resolver = Arc::new(SniResolver {
+            default_cert: self.default_cert,
+            sni_certs: self.sni_certs,
+        });
+        config.set_protocols(&self.alpn_protocols);
+
+        Ok(config)
+    }
+}
+
+/// A certificate resolver that selects the certificate by the hostname in SNI.
+struct SniResolver {
+    default_cert: Option<CertifiedKey>,
+    sni_certs: HashMap<String, CertifiedKey>,
+}
+
+impl SniResolver {
+    fn lookup(&self, server_name: Option<&str>) -> Option<&CertifiedKey> {
+        server_name
+            .and_then(|name| {
+                let name = name.to_ascii_lowercase();
+                self.sni_certs.get(&name).or_else(|| {
+                    let pos = name.find('.')?;
+                    self.sni_certs.get(&format!("*{}", &name[pos..]))
+                })
+            })
+            .or_else(|| self.default_cert.as_ref())
+    }
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<CertifiedKey> {
+        let server_name = client_hello.server_name().map(<&str>::from);
+        self.lookup(server_name).cloned()
+    }
+}
+
+/// Removes `h2` from the ALPN protocols unless HTTP/2 is enabled,
+/// so that the clients do not select the protocol which the server does not speak.
+pub(super) fn filter_alpn_protocols(
+    config: Arc<ServerConfig>,
+    http2_enabled: bool,
+) -> Arc<ServerConfig> {
+    if http2_enabled || !config.alpn_protocols.iter().any(|p| p == b"h2") {
+        return config;
+    }
+    let mut config = (*config).clone();
+    config.alpn_protocols.retain(|p| p != b"h2");
+    Arc::new(config)
+}
+
+/// Returns whether HTTP/2 is selected by ALPN in the TLS session.
+pub(super) fn is_h2_negotiated<S>(stream: &TlsStream<S>) -> bool {
+    let (_, session) = stream.get_ref();
+    session.get_alpn_protocol() == Some(&b"h2"[..])
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    struct TempCert {
+        cert_path: std::path::PathBuf,
+        key_path: std::path::PathBuf,
+    }
+
+    impl TempCert {
+        fn generate(hostname: &str) -> Self {
+            let cert = rcgen::generate_simple_self_signed(vec![hostname.to_owned()]).unwrap();
+            // the tests run in parallel and may generate the certificates for the same hostname.
+            static COUNT: AtomicUsize = AtomicUsize::new(0);
+            let dir = std::env::temp_dir().join(format!(
+                "tsukuyomi-test-tls-{}-{}-{}",
+                std::process::id(),
+                COUNT.fetch_add(1, Ordering::SeqCst),
+                hostname.replace('*', "_")
+            ));
+            std::fs::create_dir_all(&dir).unwrap();
+            let cert_path = dir.join("cert.pem");
+            let key_path = dir.join("key.pem");
+            std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+            std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+            Self {
+                cert_path,
+                key_path,
+            }
+        }
+
+        fn certs(&self) -> Vec<Certificate> {
+            load_certs(&self.cert_path).unwrap()
+        }
+    }
+
+    impl Drop for TempCert {
+        fn drop(&mut self) {
+            if let Some(dir) = self.cert_path.parent() {
+                let _ = std::fs::remove_dir_all(dir);
+            }
+        }
+    }
+
+    #[test]
+    fn load_pem_files() {
+        let cert = TempCert::generate("localhost");
+        assert_eq!(cert.certs().len(), 1);
+        assert!(load_private_key(&cert.key_path).is_ok());
+        assert!(load_certs(&cert.key_path).is_err());
+    }
+
+    #[test]
+    fn build_requires_certificate() {
+        assert!(TlsConfig::new().build().is_err());
+
+        let cert = TempCert::generate("localhost");
+        let config = TlsConfig::new()
+            .certificate(&cert.cert_path, &cert.key_path)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(
+            config.alpn_protocols,
+            vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+        );
+    }
+
+    /// Performs a TLS handshake in memory, trusting the specified certificates.
+    fn handshake(
+        config: Arc<ServerConfig>,
+        roots: &[Certificate],
+        hostname: &str,
+    ) -> tokio_rustls::rustls::ClientSession {
+        use tokio_rustls::{
+            rustls::{ClientConfig, ClientSession, ServerSession},
+            webpki::DNSNameRef,
+        };
+
+        fn transfer(from: &mut dyn Session, to: &mut dyn Session) {
+            let mut buf = vec![];
+            while from.wants_write() {
+                from.write_tls(&mut buf).unwrap();
+            }
+            let mut rd = Cursor::new(buf);
+            while (rd.position() as usize) < rd.get_ref().len() {
+                to.read_tls(&mut rd).unwrap();
+            }
+            to.process_new_packets().unwrap();
+        }
+
+        let mut client_config = ClientConfig::new();
+        for root in roots {
+            client_config.root_store.add(root).unwrap();
+        }
+        client_config.set_protocols(&[b"h2".to_vec(), b"http/1.1".to_vec()]);
+        let mut client = ClientSession::new(
+            &Arc::new(client_config),
+            DNSNameRef::try_from_ascii_str(hostname).unwrap(),
+        );
+        let mut server = ServerSession::new(&config);
+
+        while client.is_handshaking() || server.is_handshaking() {
+            transfer(&mut client, &mut server);
+            transfer(&mut server, &mut client);
+        }
+        assert_eq!(client.get_alpn_protocol(), server.get_alpn_protocol());
+        client
+    }
+
+    #[test]
+    fn handshake_with_sni() {
+        let default = TempCert::generate("localhost");
+        let api = TempCert::generate("api.example.com");
+        let config = Arc::new(
+            TlsConfig::new()
+                .certificate(&default.cert_path, &default.key_path)
+                .unwrap()
+                .sni_certificate("api.example.com", &api.cert_path, &api.key_path)
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+        let roots = [default.certs().remove(0), api.certs().remove(0)];
+
+        let client = handshake(config.clone(), &roots, "api.example.com");
+        assert_eq!(client.get_peer_certificates(), Some(api.certs()));
+
+        let client = handshake(config, &roots, "localhost");
+        assert_eq!(client.get_peer_certificates(), Some(default.certs()));
+    }
+
+    #[test]
+    fn alpn_follows_http2_setting() {
+        let cert = TempCert::generate("localhost");
+        let config = Arc::new(
+            TlsConfig::new()
+                .certificate(&cert.cert_path, &cert.key_path)
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+        let roots = cert.certs();
+        let negotiate = |http2_enabled: bool| {
+            let config = filter_alpn_protocols(config.clone(), http2_enabled);
+            let client = handshake(config, &roots, "localhost");
+            client.get_alpn_protocol().map(ToOwned::to_owned)
+        };
+
+        assert_eq!(negotiate(true), Some(b"h2".to_vec()));
+        assert_eq!(negotiate(false), Some(b"http/1.1".to_vec()));
+    }
+
+    #[test]
+    fn resolve_by_sni() {
+        let default = TempCert::generate("localhost");
+        let api = TempCert::generate("api.example.com");
+        let wildcard = TempCert::generate("*.example.com");
+
+        let resolver = SniResolver {
+            default_cert: Some(load_certified_key(&default.cert_path, &default.key_path).unwrap()),
+            sni_certs: vec![
+                (
+                    "api.example.com".to_owned(),
+                    load_certified_key(&api.cert_path, &api.key_path).unwrap(),
+                ),
+                (
+                    "*.example.com".to_owned(),
+                    load_certified_key(&wildcard.cert_path, &wildcard.key_path).unwrap(),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        };
+
+        let resolve = |name: Option<&str>| resolver.lookup(name).map(|key| key.cert.clone());
+        assert_eq!(resolve(Some("api.example.com")), Some(api.certs()));
+        assert_eq!(resolve(Some("API.Example.com")), Some(api.certs()));
+        assert_eq!(resolve(Some("www.example.com")), Some(wildcard.certs()));
+        assert_eq!(resolve(Some("example.org")), Some(default.certs()));
+        assert_eq!(resolve(None), Some(default.certs()));
+    }
+}
@@ -6,7 +6,7 @@
 
 use {
     crate::{
-        app::{App, AppService},
+        app::{AppService, VirtualHosts},
         input::body::RequestBody,
         output::ResponseBody,
     },
@@ -41,21 +41,21 @@ macro_rules! loc {
 pub type Result<T = ()> = std::result::Result<T, exitfailure::ExitFailure>;
 
 pub struct TestServer {
-    app: App,
+    app: VirtualHosts,
     runtime: Runtime,
 }
 
 impl TestServer {
-    pub fn new(app: App) -> Result<Self> {
-        Ok(Self {
-            app,
-            runtime: {
-                let mut builder = tokio::runtime::Builder::new();
-                builder.core_threads(1);
-                builder.blocking_threads(1);
-                builder.build()?
-            },
-        })
+    pub fn new(app: impl Into<VirtualHosts>) -> Result<Self> {
+        let app = app.into();
+        let mut runtime = {
+            let mut builder = tokio::runtime::Builder::new();
+            builder.core_threads(1);
+            builder.blocking_threads(1);
+            builder.build()?
+        };
+        runtime.block_on(app.startup())?;
+        Ok(Self { app, runtime })
     }
 
     pub fn connect(&mut self) -> TestClient<'_> {
@@ -66,6 +66,12 @@ impl TestServer {
     }
 }
 
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.runtime.block_on(self.app.shutdown());
+    }
+}
+
 pub struct TestClient<'a> {
     service: AppService,
     server: &'a mut TestServer,
@@ -140,17 +140,18 @@ impl Uri {
             if segment.is_empty() {
                 failure::bail!("empty segment");
             }
-            if segment
-                .get(1..)
-                .map_or(false, |s| s.bytes().any(|b| b == b':' || b == b'*'))
-            {
-                failure::bail!("invalid character in a segment");
-            }
             match segment.as_bytes()[0] {
                 b':' | b'*' => {
-                    names.get_or_insert_with(Default::default).push(segment)?;
+                    let param = parse_param_segment(segment)?;
+                    names
+                        .get_or_insert_with(Default::default)
+                        .push(param.name, param.catch_all)?;
+                }
+                _ => {
+                    if segment.bytes().any(|b| b == b':' || b == b'*') {
+                        failure::bail!("invalid character in a segment");
+                    }
                 }
-                _ => {}
             }
         }
 
@@ -202,7 +203,7 @@ impl Uri {
                     };
                     match (&mut names, other_names) {
                         (&mut Some(ref mut names), &Some(ref other_names)) => {
-                            names.extend(other_names.params.iter().cloned())?;
+                            names.extend(other_names)?;
                         }
                         (ref mut names @ None, &Some(ref other_names)) => {
                             **names = Some(other_names.clone());
@@ -223,18 +224,11 @@ pub struct CaptureNames {
 }
 
 impl CaptureNames {
-    fn push(&mut self, segment: &str) -> Result<(), Error> {
+    fn push(&mut self, name: &str, catch_all: bool) -> Result<(), Error> {
         if self.has_wildcard {
             failure::bail!("The wildcard parameter has already set");
         }
 
-        let (kind, name) = segment.split_at(1);
-        match kind {
-            ":" | "*" => {}
-            "" => failure::bail!("empty segment"),
-            c => failure::bail!("unknown parameter kind: '{}'", c),
-        }
-
         if name.is_empty() {
             failure::bail!("empty parameter name");
         }
@@ -243,19 +237,17 @@ impl CaptureNames {
             failure::bail!("the duplicated parameter name");
         }
 
-        if kind == "*" {
+        if catch_all {
             self.has_wildcard = true;
         }
 
         Ok(())
     }
 
-    fn extend<T>(&mut self, names: impl IntoIterator<Item = T>) -> Result<(), Error>
-    where
-        T: AsRef<str>,
-    {
-        for name in names {
-            self.push(name.as_ref())?;
+    fn extend(&mut self, other: &Self) -> Result<(), Error> {
+        let last = other.params.len().saturating_sub(1);
+        for (i, name) in other.params.iter().enumerate() {
+            self.push(name, other.has_wildcard && i == last)?;
         }
         Ok(())
     }
@@ -265,6 +257,70 @@ impl CaptureNames {
     }
 }
 
+/// The constraint on the value of a parameter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ParamConstraint<'a> {
+    /// A regular expression that the whole segment must match, e.g. `:id<\d+>`.
+    Regex(&'a str),
+    /// The name of type which the segment must be parsed into, e.g. `:id:u64`.
+    Type(&'a str),
+}
+
+/// A wildcard segment in the URI of a route, such as `:id`, `:id<\d+>` or `*path`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ParamSegment<'a> {
+    pub(crate) catch_all: bool,
+    pub(crate) name: &'a str,
+    pub(crate) constraint: Option<ParamConstraint<'a>>,
+}
+
+pub(crate) fn parse_param_segment(segment: &str) -> Result<ParamSegment<'_>, Error> {
+    let catch_all = match segment.as_bytes().first() {
+        Some(b':') => false,
+        Some(b'*') => true,
+        Some(..) => failure::bail!("unknown parameter kind"),
+        None => failure::bail!("empty segment"),
+    };
+
+    let rest = &segment[1..];
+    let (name, constraint) = match rest.find(|c| c == '<' || c == ':') {
+        Some(pos) => (&rest[..pos], &rest[pos..]),
+        None => (rest, ""),
+    };
+    if name.is_empty() {
+        failure::bail!("empty parameter name");
+    }
+    if name.bytes().any(|b| b == b'*' || b == b'>') {
+        failure::bail!("invalid character in the parameter name");
+    }
+
+    let constraint = match constraint.as_bytes().first() {
+        None => None,
+        Some(..) if catch_all => {
+            failure::bail!("the catch-all parameter cannot have a constraint")
+        }
+        Some(b'<') => {
+            if constraint.len() <= 2 || !constraint.ends_with('>') {
+                failure::bail!("invalid regex constraint: {:?}", constraint);
+            }
+            Some(ParamConstraint::Regex(&constraint[1..constraint.len() - 1]))
+        }
+        Some(..) => {
+            let ty = &constraint[1..];
+            if ty.is_empty() || !ty.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_') {
+                failure::bail!("invalid type constraint: {:?}", constraint);
+            }
+            Some(ParamConstraint::Type(ty))
+        }
+    };
+
+    Ok(ParamSegment {
+        catch_all,
+        name,
+        constraint,
+    })
+}
+
 #[allow(clippy::non_ascii_literal)]
 #[cfg(test)]
 mod tests {
@@ -326,6 +382,61 @@ mod tests {
         assert!("/foo/bar//".parse::<Uri>().is_err());
     }
 
+    #[test]
+    fn parse_uri_constrained_params() {
+        let uri: Uri = r"/users/:id<\d+>/posts/:post_id:u64".parse().unwrap();
+        assert_eq!(
+            uri.0,
+            UriKind::Segments(
+                r"/users/:id<\d+>/posts/:post_id:u64".into(),
+                Some(CaptureNames {
+                    params: indexset!["id".into(), "post_id".into()],
+                    has_wildcard: false,
+                })
+            )
+        );
+    }
+
+    #[test]
+    fn parse_param_segments() {
+        assert_eq!(
+            parse_param_segment(r":id<[0-9]{2,4}>").unwrap(),
+            ParamSegment {
+                catch_all: false,
+                name: "id",
+                constraint: Some(ParamConstraint::Regex("[0-9]{2,4}")),
+            }
+        );
+        assert_eq!(
+            parse_param_segment(":id:uuid").unwrap(),
+            ParamSegment {
+                catch_all: false,
+                name: "id",
+                constraint: Some(ParamConstraint::Type("uuid")),
+            }
+        );
+        assert!(parse_param_segment(":<\\d+>").is_err());
+        assert!(parse_param_segment(":id<>").is_err());
+        assert!(parse_param_segment(":id<\\d+").is_err());
+        assert!(parse_param_segment(":id:").is_err());
+        assert!(parse_param_segment("*path:u64").is_err());
+    }
+
+    #[test]
+    fn join_with_params() {
+        let uri = Uri::parse("/posts/:id")
+            .unwrap()
+            .join(Uri::parse("/comments/*path").unwrap())
+            .unwrap();
+        assert_eq!(
+            uri.capture_names(),
+            Some(&CaptureNames {
+                params: indexset!["id".into(), "path".into()],
+                has_wildcard: true,
+            })
+        );
+    }
+
     #[test]
     fn parse_uri_failcase_invalid_wildcard_specifier_pos() {
         assert!("/pa:th".parse::<Uri>().is_err());
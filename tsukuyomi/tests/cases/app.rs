@@ -1,10 +1,12 @@
 use {
     http::{
-        header::{CONTENT_LENGTH, CONTENT_TYPE},
+        header::{ALLOW, CONTENT_LENGTH, CONTENT_TYPE, LOCATION},
         Request, StatusCode,
     },
     tsukuyomi::{
+        app::{NormalizePath, TrailingSlash, VirtualHosts, MOUNT_PREFIX},
         endpoint::builder as endpoint,
+        extractor, path,
         test::{self, loc, TestServer},
         App,
     },
@@ -191,3 +193,445 @@ fn scoped_fallback() -> test::Result {
 
     Ok(())
 }
+
+#[test]
+fn virtual_hosts() -> test::Result {
+    let app = |name: &'static str| {
+        App::build(move |s| {
+            s.at("/", (), {
+                endpoint::call(move || name) //
+            })
+        })
+    };
+    let hosts = VirtualHosts::new()
+        .host("api.example.com", app("api")?)?
+        .host("*.users.example.com", app("users")?)?
+        .fallback(app("site")?);
+    let mut server = TestServer::new(hosts)?;
+    let mut client = server.connect();
+
+    let request = |host: &str| Request::get("/").header("host", host).body("");
+
+    client
+        .request(request("api.example.com")?)
+        .assert(loc!(), test::body::eq("api"))?;
+    client
+        .request(request("alice.users.example.com:8080")?)
+        .assert(loc!(), test::body::eq("users"))?;
+    client
+        .request(request("www.example.com")?)
+        .assert(loc!(), test::body::eq("site"))?;
+    client
+        .get("/") //
+        .assert(loc!(), test::body::eq("site"))?;
+
+    let mut server = TestServer::new(VirtualHosts::new().host("api.example.com", app("api")?)?)?;
+    server
+        .connect()
+        .request(request("www.example.com")?)
+        .assert(loc!(), StatusCode::NOT_FOUND)?;
+
+    Ok(())
+}
+
+#[test]
+fn trailing_slash_policy() -> test::Result {
+    let app = App::build(|s| {
+        s.at("/strict", (), endpoint::reply("strict"))?;
+        s.nest("/redirect", (), |s| {
+            s.trailing_slash(TrailingSlash::Redirect);
+            s.at("/", (), endpoint::reply("redirect"))?;
+            s.at("/foo", (), endpoint::reply("redirect-foo"))
+        })?;
+        s.nest("/transparent", (), |s| {
+            s.trailing_slash(TrailingSlash::Transparent);
+            s.at("/foo/", (), endpoint::reply("transparent-foo"))
+        })
+    })?;
+    let mut server = TestServer::new(app)?;
+    let mut client = server.connect();
+
+    client.get("/strict").assert(loc!(), StatusCode::OK)?;
+    client
+        .get("/strict/")
+        .assert(loc!(), StatusCode::NOT_FOUND)?;
+
+    client
+        .get("/redirect/foo/?bar=baz")
+        .assert(loc!(), StatusCode::MOVED_PERMANENTLY)?
+        .assert(loc!(), test::header::eq(LOCATION, "/redirect/foo?bar=baz"))?;
+    client
+        .request(Request::post("/redirect/foo/").body("")?)
+        .assert(loc!(), StatusCode::PERMANENT_REDIRECT)?;
+
+    client
+        .get("/transparent/foo")
+        .assert(loc!(), test::body::eq("transparent-foo"))?;
+
+    Ok(())
+}
+
+#[test]
+fn trailing_slash_redirect_with_error_handler() -> test::Result {
+    use tsukuyomi::output::ResponseBody;
+
+    let app = App::build(|s| {
+        s.trailing_slash(TrailingSlash::Redirect);
+        s.error_handler(|err, _| {
+            let mut response = http::Response::new(ResponseBody::from("error"));
+            *response.status_mut() = err.status_code();
+            response
+        });
+        s.at("/foo", (), endpoint::reply("foo"))
+    })?;
+    let mut server = TestServer::new(app)?;
+    let mut client = server.connect();
+
+    client
+        .get("/foo/")
+        .assert(loc!(), StatusCode::MOVED_PERMANENTLY)?
+        .assert(loc!(), test::header::eq(LOCATION, "/foo"))?;
+    client
+        .get("/bar/")
+        .assert(loc!(), StatusCode::NOT_FOUND)?
+        .assert(loc!(), test::body::eq("error"))?;
+
+    Ok(())
+}
+
+#[test]
+fn normalize_path() -> test::Result {
+    let app = App::build(|s| {
+        s.normalize_path(
+            NormalizePath::new()
+                .merge_slashes(true)
+                .resolve_dot_segments(true),
+        )?;
+        s.at("/foo/bar", (), endpoint::reply("bar"))
+    })?;
+    let mut server = TestServer::new(app)?;
+    let mut client = server.connect();
+
+    client
+        .get("//foo///bar")
+        .assert(loc!(), test::body::eq("bar"))?;
+    client
+        .get("/foo/baz/../bar")
+        .assert(loc!(), test::body::eq("bar"))?;
+
+    // the normalization cannot be set in the sub-scopes.
+    let app: tsukuyomi::app::Result<App> = App::build(|s| {
+        s.nest("/api", (), |s| s.normalize_path(NormalizePath::new())) //
+    });
+    assert!(app.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn automatic_head_and_options() -> test::Result {
+    let app = App::build(|s| {
+        s.at("/", (), endpoint::get().reply("hello"))?;
+        s.at("/any", (), endpoint::reply("any"))?;
+        s.at("/method", (), {
+            endpoint::get()
+                .extract(extractor::method())
+                .call(|method: http::Method| method.to_string())
+        })?;
+        s.at("/explicit", (), {
+            endpoint::get_or_head()
+                .extract(extractor::method())
+                .call(|method: http::Method| method.to_string())
+        })
+    })?;
+    let mut server = TestServer::new(app)?;
+    let mut client = server.connect();
+
+    client
+        .request(Request::options("/").body("")?)
+        .assert(loc!(), StatusCode::NO_CONTENT)?
+        .assert(loc!(), test::header::eq(ALLOW, "GET, HEAD, OPTIONS"))?;
+
+    client
+        .request(Request::head("/").body("")?)
+        .assert(loc!(), StatusCode::OK)?
+        .assert(loc!(), test::header::eq(CONTENT_LENGTH, "5"))?
+        .assert(loc!(), test::body::eq(""))?;
+
+    client
+        .request(Request::post("/").body("")?)
+        .assert(loc!(), StatusCode::METHOD_NOT_ALLOWED)?
+        .assert(loc!(), test::header::eq(ALLOW, "GET, HEAD, OPTIONS"))?;
+
+    // HEAD is handled as GET unless the route accepts HEAD explicitly.
+    client
+        .request(Request::head("/method").body("")?)
+        .assert(loc!(), StatusCode::OK)?
+        .assert(loc!(), test::header::eq(CONTENT_LENGTH, "3"))?
+        .assert(loc!(), test::body::eq(""))?;
+    client
+        .request(Request::head("/explicit").body("")?)
+        .assert(loc!(), StatusCode::OK)?
+        .assert(loc!(), test::header::eq(CONTENT_LENGTH, "4"))?
+        .assert(loc!(), test::body::eq(""))?;
+
+    // the routes accepting any methods handle OPTIONS by themselves.
+    client
+        .request(Request::options("/any").body("")?)
+        .assert(loc!(), test::body::eq("any"))?;
+
+    Ok(())
+}
+
+#[test]
+fn mount_app() -> test::Result {
+    let admin = App::build(|s| {
+        s.at("/", (), {
+            endpoint::get()
+                .extract(extractor::local::clone(&MOUNT_PREFIX))
+                .call(|prefix: String| format!("admin(prefix={})", prefix))
+        })?;
+        s.at(path!("/users/:id"), (), {
+            endpoint::get()
+                .extract(extractor::local::clone(&MOUNT_PREFIX))
+                .call(|id: u32, prefix: String| format!("user(id={}, prefix={})", id, prefix))
+        })
+    })?;
+    let app = App::build(|s| {
+        s.at("/", (), endpoint::reply("index"))?;
+        s.nest("/v1", (), |s| s.mount("/admin", admin))
+    })?;
+    let mut server = TestServer::new(app)?;
+    let mut client = server.connect();
+
+    client.get("/").assert(loc!(), test::body::eq("index"))?;
+    client
+        .get("/v1/admin")
+        .assert(loc!(), test::body::eq("admin(prefix=/v1/admin)"))?;
+    client
+        .get("/v1/admin/")
+        .assert(loc!(), test::body::eq("admin(prefix=/v1/admin)"))?;
+    client
+        .get("/v1/admin/users/42?q=1")
+        .assert(loc!(), test::body::eq("user(id=42, prefix=/v1/admin)"))?;
+    client
+        .get("/v1/admin/posts")
+        .assert(loc!(), StatusCode::NOT_FOUND)?;
+
+    Ok(())
+}
+
+#[test]
+fn mount_app_named_routes() -> test::Result {
+    use tsukuyomi::app::NamedRoutes;
+
+    let admin = App::build(|s| {
+        s.at_named("user", path!("/users/:id"), (), {
+            endpoint::call(|id: u32| format!("user(id={})", id))
+        })?;
+        s.at("/", (), {
+            endpoint::get()
+                .extract(extractor::named_routes())
+                .call(|routes: NamedRoutes| {
+                    routes
+                        .url_for("user", &[("id", "42")])
+                        .unwrap_or_else(|e| e.to_string())
+                })
+        })
+    })?;
+    let app = App::build(|s| s.nest("/v1", (), |s| s.mount("/admin", admin)))?;
+    let mut server = TestServer::new(app)?;
+    let mut client = server.connect();
+
+    client
+        .get("/v1/admin")
+        .assert(loc!(), test::body::eq("/v1/admin/users/42"))?;
+    client
+        .get("/v1/admin/users/42")
+        .assert(loc!(), test::body::eq("user(id=42)"))?;
+
+    Ok(())
+}
+
+#[test]
+fn lifecycle_hooks() -> test::Result {
+    use std::sync::{Arc, Mutex};
+
+    let marker = Arc::new(Mutex::new(vec![]));
+    let hook = |name: &'static str| {
+        let marker = marker.clone();
+        move || -> Result<(), failure::Error> {
+            marker.lock().unwrap().push(name);
+            Ok(())
+        }
+    };
+
+    let app = App::build(|s| {
+        s.on_startup(hook("startup1"));
+        s.on_shutdown(hook("shutdown1"));
+        s.nest("/api", (), |s| {
+            s.on_startup(hook("startup2"));
+            s.on_shutdown(hook("shutdown2"));
+            Ok(())
+        })?;
+        s.at("/", (), endpoint::reply("index"))
+    })?;
+    {
+        let mut server = TestServer::new(app)?;
+        assert_eq!(&**marker.lock().unwrap(), &*vec!["startup1", "startup2"]);
+
+        let mut client = server.connect();
+        client.get("/").assert(loc!(), StatusCode::OK)?;
+    }
+    assert_eq!(
+        &**marker.lock().unwrap(),
+        &*vec!["startup1", "startup2", "shutdown1", "shutdown2"]
+    );
+
+    // the remaining hooks are skipped if a startup hook fails.
+    marker.lock().unwrap().clear();
+    let app = App::build(|s| {
+        s.on_startup(|| Err(failure::format_err!("failed to start")));
+        s.on_startup(hook("startup"));
+        Ok(())
+    })?;
+    assert!(TestServer::new(app).is_err());
+    assert!(marker.lock().unwrap().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn scoped_error_handler() -> test::Result {
+    use tsukuyomi::output::ResponseBody;
+
+    let app = App::build(|s| {
+        s.error_handler(|err, request| {
+            let mut response = http::Response::new(ResponseBody::from(format!(
+                "{} {}",
+                err.status_code().as_u16(),
+                request.uri().path()
+            )));
+            *response.status_mut() = err.status_code();
+            response
+        });
+        s.at("/", (), endpoint::get().reply("index"))?;
+        s.nest("/api", (), |s| {
+            s.error_handler(|err, _| {
+                let mut response = http::Response::new(ResponseBody::from(format!(
+                    r#"{{"status":{}}}"#,
+                    err.status_code().as_u16()
+                )));
+                *response.status_mut() = err.status_code();
+                response
+                    .headers_mut()
+                    .insert(CONTENT_TYPE, "application/problem+json".parse().unwrap());
+                response
+            });
+            s.at("/posts", (), endpoint::get().reply("posts"))
+        })
+    })?;
+    let mut server = TestServer::new(app)?;
+    let mut client = server.connect();
+
+    client.get("/").assert(loc!(), test::body::eq("index"))?;
+    client
+        .get("/missing")
+        .assert(loc!(), StatusCode::NOT_FOUND)?
+        .assert(loc!(), test::body::eq("404 /missing"))?;
+    client
+        .request(Request::post("/").body("")?)
+        .assert(loc!(), StatusCode::METHOD_NOT_ALLOWED)?
+        .assert(loc!(), test::header::eq(ALLOW, "GET, HEAD, OPTIONS"))?
+        .assert(loc!(), test::body::eq("405 /"))?;
+    client
+        .request(Request::post("/api/posts").body("")?)
+        .assert(loc!(), StatusCode::METHOD_NOT_ALLOWED)?
+        .assert(
+            loc!(),
+            test::header::eq(CONTENT_TYPE, "application/problem+json"),
+        )?
+        .assert(loc!(), test::body::eq(r#"{"status":405}"#))?;
+    client
+        .get("/api")
+        .assert(loc!(), StatusCode::NOT_FOUND)?
+        .assert(loc!(), test::body::eq(r#"{"status":404}"#))?;
+
+    Ok(())
+}
+
+#[test]
+fn problem_details() -> test::Result {
+    use {serde_json::json, tsukuyomi::error::ProblemDetails};
+
+    let app = App::build(|s| {
+        s.error_handler(|err, _| ProblemDetails::from_error(err));
+        s.at("/posts", (), {
+            endpoint::post()
+                .extract(extractor::body::json())
+                .call(|post: serde_json::Value| post.to_string())
+        })?;
+        s.at("/conflict", (), {
+            endpoint::get().call(|| {
+                ProblemDetails::new(StatusCode::CONFLICT)
+                    .with_type("https://example.com/probs/conflict")
+                    .with_instance("/conflict")
+                    .with_extension("retry", true)
+            })
+        })
+    })?;
+    let mut server = TestServer::new(app)?;
+    let mut client = server.connect();
+
+    let body = client
+        .request(Request::post("/posts").body("{}")?)
+        .assert(loc!(), StatusCode::BAD_REQUEST)?
+        .assert(
+            loc!(),
+            test::header::eq(CONTENT_TYPE, "application/problem+json"),
+        )?
+        .into_bytes()?;
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&body)?,
+        json!({
+            "type": "about:blank",
+            "title": "Bad Request",
+            "status": 400,
+            "detail": "missing the header field `Content-type`",
+        })
+    );
+
+    let body = client
+        .get("/posts")
+        .assert(loc!(), StatusCode::METHOD_NOT_ALLOWED)?
+        .assert(
+            loc!(),
+            test::header::eq(CONTENT_TYPE, "application/problem+json"),
+        )?
+        .into_bytes()?;
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&body)?["status"],
+        405
+    );
+
+    let body = client
+        .get("/conflict")
+        .assert(loc!(), StatusCode::CONFLICT)?
+        .assert(
+            loc!(),
+            test::header::eq(CONTENT_TYPE, "application/problem+json"),
+        )?
+        .into_bytes()?;
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&body)?,
+        json!({
+            "type": "https://example.com/probs/conflict",
+            "title": "Conflict",
+            "status": 409,
+            "instance": "/conflict",
+            "retry": true,
+        })
+    );
+
+    Ok(())
+}
@@ -1,7 +1,11 @@
-use tsukuyomi::{
-    endpoint::builder as endpoint,
-    fs::{NamedFile, Staticfiles},
-    App,
+use {
+    http::{header, Request, StatusCode},
+    tsukuyomi::{
+        endpoint::builder as endpoint,
+        fs::{NamedFile, OpenConfig, Staticfiles},
+        test::{self, loc, TestServer},
+        App,
+    },
 };
 
 #[test]
@@ -21,3 +25,232 @@ fn compiletest() -> tsukuyomi::app::Result<()> {
 fn compiletest_staticfiles() -> tsukuyomi::app::Result<()> {
     App::build(|s| Staticfiles::new("./public").register(s)).map(|_: App| ())
 }
+
+const FILE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+
+#[test]
+fn named_file_range() -> test::Result {
+    let content = std::fs::read(FILE_PATH)?;
+
+    let app = App::build(|s| {
+        s.at("/", (), {
+            endpoint::get() //
+                .reply(NamedFile::open(FILE_PATH))
+        })
+    })?;
+    let mut server = TestServer::new(app)?;
+    let mut client = server.connect();
+
+    client
+        .get("/")
+        .assert(loc!(), StatusCode::OK)?
+        .assert(loc!(), test::header::eq(header::ACCEPT_RANGES, "bytes"))?
+        .assert(loc!(), test::body::eq(&content[..]))?;
+
+    client
+        .request(
+            Request::get("/")
+                .header(header::RANGE, "bytes=0-9")
+                .body("")?,
+        )
+        .assert(loc!(), StatusCode::PARTIAL_CONTENT)?
+        .assert(
+            loc!(),
+            test::header::eq(
+                header::CONTENT_RANGE,
+                format!("bytes 0-9/{}", content.len()).as_str(),
+            ),
+        )?
+        .assert(loc!(), test::body::eq(&content[0..10]))?;
+
+    // the overlapping ranges are coalesced into a single part.
+    client
+        .request(
+            Request::get("/")
+                .header(header::RANGE, "bytes=10-19, 0-9, 5-14")
+                .body("")?,
+        )
+        .assert(loc!(), StatusCode::PARTIAL_CONTENT)?
+        .assert(
+            loc!(),
+            test::header::eq(
+                header::CONTENT_RANGE,
+                format!("bytes 0-19/{}", content.len()).as_str(),
+            ),
+        )?
+        .assert(loc!(), test::body::eq(&content[0..20]))?;
+
+    // too many ranges are ignored and the whole file is sent.
+    let ranges = (0..64)
+        .map(|i| format!("{}-{}", i * 4, i * 4 + 1))
+        .collect::<Vec<_>>()
+        .join(",");
+    client
+        .request(
+            Request::get("/")
+                .header(header::RANGE, format!("bytes={}", ranges).as_str())
+                .body("")?,
+        )
+        .assert(loc!(), StatusCode::OK)?
+        .assert(loc!(), test::body::eq(&content[..]))?;
+
+    client
+        .request(
+            Request::get("/")
+                .header(header::RANGE, format!("bytes={}-", content.len()).as_str())
+                .body("")?,
+        )
+        .assert(loc!(), StatusCode::RANGE_NOT_SATISFIABLE)?
+        .assert(
+            loc!(),
+            test::header::eq(
+                header::CONTENT_RANGE,
+                format!("bytes */{}", content.len()).as_str(),
+            ),
+        )?;
+
+    client
+        .request(
+            Request::get("/")
+                .header(header::RANGE, "bytes=0-9")
+                .header(header::IF_RANGE, "\"mismatched-etag\"")
+                .body("")?,
+        )
+        .assert(loc!(), StatusCode::OK)?
+        .assert(loc!(), test::body::eq(&content[..]))?;
+
+    // the date in Last-Modified can be used as the validator in If-Range.
+    let last_modified = client.get("/").headers()[header::LAST_MODIFIED]
+        .to_str()?
+        .to_owned();
+    client
+        .request(
+            Request::get("/")
+                .header(header::RANGE, "bytes=0-9")
+                .header(header::IF_RANGE, last_modified.as_str())
+                .body("")?,
+        )
+        .assert(loc!(), StatusCode::PARTIAL_CONTENT)?
+        .assert(loc!(), test::body::eq(&content[0..10]))?;
+
+    // the weak entity tag of the file cannot be used as the validator in If-Range.
+    let etag = client.get("/").headers()[header::ETAG].to_str()?.to_owned();
+    assert!(etag.starts_with("W/"));
+    client
+        .request(
+            Request::get("/")
+                .header(header::RANGE, "bytes=0-9")
+                .header(header::IF_RANGE, etag.as_str())
+                .body("")?,
+        )
+        .assert(loc!(), StatusCode::OK)?
+        .assert(loc!(), test::body::eq(&content[..]))?;
+
+    Ok(())
+}
+
+#[test]
+fn named_file_conditional() -> test::Result {
+    let app = App::build(|s| {
+        s.at("/", (), {
+            endpoint::get() //
+                .reply(NamedFile::open(FILE_PATH))
+        })
+    })?;
+    let mut server = TestServer::new(app)?;
+    let mut client = server.connect();
+
+    let response = client.get("/");
+    let etag = response.headers()[header::ETAG].to_str()?.to_owned();
+    let last_modified = response.headers()[header::LAST_MODIFIED]
+        .to_str()?
+        .to_owned();
+
+    client
+        .request(
+            Request::get("/")
+                .header(header::IF_NONE_MATCH, etag.as_str())
+                .body("")?,
+        )
+        .assert(loc!(), StatusCode::NOT_MODIFIED)?;
+
+    client
+        .request(
+            Request::get("/")
+                .header(header::IF_NONE_MATCH, "\"other\", *")
+                .body("")?,
+        )
+        .assert(loc!(), StatusCode::NOT_MODIFIED)?;
+
+    client
+        .request(
+            Request::get("/")
+                .header(header::IF_MODIFIED_SINCE, last_modified.as_str())
+                .body("")?,
+        )
+        .assert(loc!(), StatusCode::NOT_MODIFIED)?;
+
+    client
+        .request(
+            Request::get("/")
+                .header(header::IF_MODIFIED_SINCE, "Sun, 06 Nov 1994 08:49:37 GMT")
+                .body("")?,
+        )
+        .assert(loc!(), StatusCode::OK)?;
+
+    Ok(())
+}
+
+#[test]
+fn staticfiles_precompressed() -> test::Result {
+    // Removes the temporary directory even if the assertions fail.
+    struct TempDir(std::path::PathBuf);
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    let root_dir = std::env::temp_dir().join(format!(
+        "tsukuyomi-test-precompressed-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&root_dir)?;
+    let _guard = TempDir(root_dir.clone());
+    std::fs::write(root_dir.join("app.js"), "original")?;
+    std::fs::write(root_dir.join("app.js.gz"), "gzipped")?;
+
+    let app = App::build(|s| {
+        Staticfiles::new(&root_dir)
+            .open_config(OpenConfig {
+                precompressed: true,
+                ..Default::default()
+            })
+            .register(s)
+    })?;
+    let mut server = TestServer::new(app)?;
+    let mut client = server.connect();
+
+    client
+        .request(
+            Request::get("/app.js")
+                .header(header::ACCEPT_ENCODING, "br, gzip")
+                .body("")?,
+        )
+        .assert(loc!(), StatusCode::OK)?
+        .assert(loc!(), test::header::eq(header::CONTENT_ENCODING, "gzip"))?
+        .assert(loc!(), test::header::eq(header::VARY, "accept-encoding"))?
+        .assert(
+            loc!(),
+            test::header::eq(header::CONTENT_TYPE, "application/javascript"),
+        )?
+        .assert(loc!(), test::body::eq("gzipped"))?;
+
+    client
+        .get("/app.js")
+        .assert(loc!(), StatusCode::OK)?
+        .assert(loc!(), test::header::not_exists(header::CONTENT_ENCODING))?
+        .assert(loc!(), test::body::eq("original"))?;
+
+    Ok(())
+}
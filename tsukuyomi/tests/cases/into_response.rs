@@ -92,3 +92,60 @@ fn test_into_response_preset() -> test::Result {
 
     Ok(())
 }
+
+#[test]
+fn test_negotiate() -> test::Result {
+    use http::Request;
+
+    let app = App::build(|s| {
+        s.at("/", (), {
+            endpoint::call(|| {
+                tsukuyomi::output::negotiate()
+                    .json(vec!["a", "b"])
+                    .html("<ul><li>a</li><li>b</li></ul>")
+                    .csv("a\nb\n")
+            })
+        })
+    })?;
+    let mut server = TestServer::new(app)?;
+    let mut client = server.connect();
+
+    client
+        .get("/")
+        .assert(loc!(), StatusCode::OK)?
+        .assert(
+            loc!(),
+            test::header::eq(header::CONTENT_TYPE, "application/json"),
+        )?
+        .assert(loc!(), test::header::eq(header::VARY, "accept"))?
+        .assert(loc!(), test::body::eq(r#"["a","b"]"#))?;
+
+    client
+        .request(
+            Request::get("/")
+                .header(header::ACCEPT, "text/html;q=0.9, text/csv")
+                .body("")?,
+        )
+        .assert(loc!(), StatusCode::OK)?
+        .assert(loc!(), test::header::eq(header::CONTENT_TYPE, "text/csv"))?
+        .assert(loc!(), test::body::eq("a\nb\n"))?;
+
+    client
+        .request(
+            Request::get("/")
+                .header(header::ACCEPT, "text/*, application/json;q=0.5")
+                .body("")?,
+        )
+        .assert(loc!(), test::header::eq(header::CONTENT_TYPE, "text/html"))?;
+
+    client
+        .request(
+            Request::get("/")
+                .header(header::ACCEPT, "image/png")
+                .body("")?,
+        )
+        .assert(loc!(), StatusCode::NOT_ACCEPTABLE)?
+        .assert(loc!(), test::header::eq(header::VARY, "accept"))?;
+
+    Ok(())
+}